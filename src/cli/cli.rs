@@ -0,0 +1,82 @@
+//! Command-line interface of the Deston load balancer.
+//!
+//! This module defines the `run` and `check` subcommands, the path of the
+//! config file and the settings that override it, so several instances can
+//! run from the same binary with different configs.
+
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::config::config::{LayerMode, Overrides};
+use crate::config::schema::Named;
+
+/// Command-line arguments
+#[derive(Debug, Parser)]
+#[command(name = "deston", version, about = "Layer 4 and Layer 7 load balancer")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>, //subcommand, run if None
+    #[command(flatten)]
+    pub args: ConfigArgs, //arguments of run without a subcommand
+}
+
+/// Subcommands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the load balancer
+    Run(ConfigArgs),
+    /// Validate the config and print the effective config
+    Check(ConfigArgs),
+}
+
+/// Config file and the settings overriding it
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Path of the config file
+    #[arg(short, long, value_name = "PATH", default_value = "config.toml")]
+    pub config: PathBuf,
+    /// Address and port to listen on, e.g. 0.0.0.0:8080
+    #[arg(long, value_name = "ADDRESS:PORT", value_parser = parse_listen)]
+    pub listen: Option<(String, u16)>,
+    /// Layer mode, L4 or L7
+    #[arg(long, value_name = "LAYER", value_parser = parse_layer)]
+    pub layer: Option<LayerMode>,
+}
+
+impl Cli {
+    //returns the subcommand to execute, run without a subcommand
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Run(self.args))
+    }
+}
+
+impl ConfigArgs {
+    //returns the settings overriding the config file
+    pub fn overrides(&self) -> Overrides {
+        Overrides {
+            address: self.listen.as_ref().map(|(address, _)| address.clone()),
+            port: self.listen.as_ref().map(|(_, port)| *port),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+//parses ADDRESS:PORT, IPv6 addresses are written in brackets
+fn parse_listen(listen: &str) -> Result<(String, u16), String> {
+    let (address, port) = listen
+        .rsplit_once(':')
+        .ok_or("expected ADDRESS:PORT, e.g. 0.0.0.0:8080")?;
+    if address.is_empty() {
+        return Err("missing address".to_owned());
+    }
+    match port.parse::<u16>() {
+        Ok(port) if port != 0 => Ok((address.to_owned(), port)),
+        _ => Err(format!("invalid port \"{}\"", port)),
+    }
+}
+
+//parses a layer mode, case-insensitively
+fn parse_layer(layer: &str) -> Result<LayerMode, String> {
+    LayerMode::from_name(layer).ok_or_else(|| format!("unknown layer \"{}\"", layer))
+}
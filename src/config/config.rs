@@ -2,29 +2,59 @@
 //!
 //! This module handles parsing and managing configuration from TOML files.
 //! It defines the configuration structure including load balancer settings,
-//! backend servers, and algorithm selection.
+//! backend servers, and algorithm selection, built from the sections of the
+//! config file schema.
 
-use hyper::Uri;
+use hyper::header::HeaderName;
+use hyper::{Method, Uri};
+use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use toml::{Table, Value};
+use std::time::Duration;
+use tokio::sync::Notify;
+use toml::Table;
 
+use crate::config::env;
+use crate::config::error::ConfigError;
+use crate::config::schema::{
+    ConfigFile, HeaderRulesSection, HealthCheckSection, LoadBalancerSection, ProbeKind,
+    RouteSection, ServerSection, Status, StatusList, TlsSection, UpstreamSection,
+};
+use crate::health_check::health_check::{HealthCheck, HttpProbe, Probe};
+use crate::health_check::outlier_detection::OutlierDetection;
 use crate::load_balancer::algorithm::algorithm::Algorithm as AlgorithmTrait;
+use crate::load_balancer::algorithm::dynamic::{
+    least_connections::LeastConnections, weighted_least_connections::WeightedLeastConnections,
+};
 use crate::load_balancer::algorithm::r#static::{
     ip_hashing::IpHashing, round_robin::RoundRobin, weighted_round_robin::WeightedRoundRobin,
 };
+use crate::load_balancer::queue::WaitQueue;
+use crate::load_balancer::router::{PathMatch, Route, Router};
+use crate::load_balancer::upstream::Upstream;
+use crate::server::forwarded::{Forwarding, TrustedProxy};
+use crate::server::headers::{HeaderRewrite, HeaderRules, HeaderTemplate};
+use crate::server::pool::ConnectionPool;
 use crate::server::server::{Server, SyncServer};
+use crate::tls::resolver::CertResolver;
+use crate::tls::tls::{CertificateSettings, TlsSettings};
+use crate::tls::upstream::UpstreamTlsSettings;
 
 //type alias for a thread-safe, synchronized Config using Arc and Mutex
 pub type SyncConfig = Arc<Mutex<Config>>;
 
 /// Load balancing algorithm options
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum Algorithm {
-    RoundRobin,         //round robin
-    WeightedRoundRobin, //weighted round robin
-    IpHashing,          //ip hashing
+    #[default]
+    RoundRobin, //round robin
+    WeightedRoundRobin,       //weighted round robin
+    IpHashing,                //ip hashing
+    LeastConnections,         //least connections
+    WeightedLeastConnections, //weighted least connections
 }
 
 /// Load balancer layer mode
@@ -36,173 +66,1019 @@ pub enum LayerMode {
 
 /// Configuration structure for the load balancer
 pub struct Config {
-    pub load_balancer_address: Uri,    //address of load balancer
-    pub servers: Arc<Vec<SyncServer>>, //thread safe vector of servers
-    #[allow(dead_code)]
-    pub algorithm: Algorithm, //algorithm to pick server
-    pub last_picked_index: usize,      //index of last picked server
-    pub algorithm_object: Box<dyn AlgorithmTrait>, //algorithm object
-    pub layer_mode: LayerMode,         //layer mode (L4 or L7)
+    pub name: Option<String>,                //name of the listener in logs
+    pub load_balancer_address: Uri,          //address of load balancer
+    pub upstream: Option<String>, //upstream of requests matching no route, empty for the servers of the listener
+    pub upstreams: Vec<Upstream>, //upstreams the listener forwards to
+    pub router: Router,           //routes of requests to upstreams (L7)
+    pub layer_mode: LayerMode,    //layer mode (L4 or L7)
+    pub tls: Option<TlsSettings>, //TLS termination settings (L7)
+    pub tls_passthrough: bool,    //route TLS connections by SNI without decrypting (L4)
+    pub client_hello_timeout: Duration, //time a client may take to start TLS (L4 SNI, L7 handshake)
+    pub cert_resolver: Arc<CertResolver>, //certificates of TLS termination, reloadable (L7)
+    pub queue: Arc<WaitQueue>,    //queue of connections waiting for a server
+    pub max_connect_attempts: u32, //servers tried before a connection fails
+    pub upstream_timeout: Duration, //time a server may take to respond (L7)
+    pub error_body: String,       //body of error responses (L7)
+    pub error_content_type: String, //content type of error responses (L7)
+    pub forwarding: Arc<Forwarding>, //Forwarded and X-Forwarded-* headers of requests (L7)
+    pub connection_pool: ConnectionPool, //pooling of connections to servers (L7)
+    pub health_check: HealthCheck, //health check settings
+    pub outlier_detection: OutlierDetection, //outlier detection settings
+}
+
+/// Settings overriding the config file, e.g. given on the command line
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub address: Option<String>,  //address of load balancer
+    pub port: Option<u16>,        //port of load balancer
+    pub layer: Option<LayerMode>, //layer mode of load balancer
+}
+
+impl Overrides {
+    //replaces the settings of file that are overridden
+    //returns an error if settings are overridden and the file has several listeners
+    pub fn apply(&self, file: &mut ConfigFile) -> Result<(), ConfigError> {
+        if *self == Overrides::default() {
+            return Ok(());
+        }
+        let load_balancer = match file.listener.as_mut_slice() {
+            [] => file.load_balancer.get_or_insert_with(Default::default),
+            [listener] => listener,
+            _ => {
+                return Err(ConfigError::invalid(
+                    "listener",
+                    "the address, port and layer can only be overridden with a single listener",
+                ))
+            }
+        };
+        if let Some(address) = &self.address {
+            load_balancer.address = address.clone();
+        }
+        if let Some(port) = self.port {
+            load_balancer.port = port;
+        }
+        if let Some(layer) = &self.layer {
+            load_balancer.layer = layer.clone();
+        }
+        Ok(())
+    }
 }
 
 impl Config {
     /// Creates and returns a new Config from a TOML file
+    ///
+    /// Panics if the config is invalid, use `Config::load` to handle the error
+    #[deprecated(note = "use Config::load, which returns an error instead of panicking")]
     pub fn new(config_path: &Path) -> Self {
-        //read contents of config file
-        let contents = fs::read_to_string(config_path).unwrap();
+        Self::load(config_path).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Loads a Config from a TOML file with a single listener
+    ///
+    /// Returns an error pointing to the line of the first unknown key or invalid value
+    pub fn load(config_path: &Path) -> Result<Self, ConfigError> {
+        Self::load_with(config_path, &Overrides::default())
+    }
+
+    /// Loads a Config from a TOML file with a single listener, with overrides applied on top of it
+    pub fn load_with(config_path: &Path, overrides: &Overrides) -> Result<Self, ConfigError> {
+        single_listener(Self::load_listeners(config_path, overrides)?)
+    }
+
+    /// Loads the Config of every listener of a TOML file, with overrides applied on top of it
+    ///
+    /// Environment variables are interpolated and DESTON_ prefixed variables are
+    /// applied before the overrides.
+    pub fn load_listeners(
+        config_path: &Path,
+        overrides: &Overrides,
+    ) -> Result<Vec<Self>, ConfigError> {
+        //read contents of config file, replacing ${VAR} with environment variables
+        let contents = env::interpolate(&fs::read_to_string(config_path)?, |name| {
+            std::env::var(name).ok()
+        })?;
         //parse config file contents
-        let values = contents.parse::<Table>().unwrap();
-
-        //get host name, port and algorithm of load balancer
-        let (load_balancer_host, load_balancer_port, algorithm, layer_mode) = {
-            if let Some(table) = values.get("load_balancer") {
-                let host = {
-                    if let Some(Value::String(address)) = table.get("address") {
-                        address.as_str()
-                    } else {
-                        //if host not found in config
-                        "localhost"
-                    }
-                };
-                let port = {
-                    if let Some(Value::Integer(port)) = table.get("port") {
-                        port
-                    } else {
-                        &8080
-                    }
-                };
-                let algorithm = {
-                    if let Some(Value::String(algorithm)) = table.get("algorithm") {
-                        get_algorithm(algorithm)
-                    } else {
-                        Algorithm::RoundRobin
-                    }
-                };
-                let layer = {
-                    if let Some(Value::String(layer)) = table.get("layer") {
-                        get_layer_mode(layer)
-                    } else {
-                        LayerMode::L4 // Default to L4 for backward compatibility
-                    }
-                };
-                (host, port, algorithm, layer)
-            } else {
-                //if host not found in config
-                ("localhost", &8080, Algorithm::RoundRobin, LayerMode::L4)
+        let mut values = contents.parse::<Table>()?;
+        env::apply_overrides(&mut values, std::env::vars())?;
+        let mut file = ConfigFile::from_table(values, &contents)?;
+        overrides
+            .apply(&mut file)
+            .and_then(|()| Self::listeners_from_file(&file))
+            .map_err(|err| err.locate(&contents))
+    }
+
+    /// Creates and returns a new Config from the sections of a config file with a single listener
+    pub fn from_file(file: &ConfigFile) -> Result<Self, ConfigError> {
+        single_listener(Self::listeners_from_file(file)?)
+    }
+
+    /// Creates and returns the Config of every listener of a config file
+    ///
+    /// The connection pool, health check and outlier detection settings are
+    /// shared by all listeners. Every named upstream is built once and its
+    /// servers are shared by the listeners using it, so connection limits,
+    /// ejections and health are per server. Every upstream is validated even
+    /// if no listener uses it.
+    pub fn listeners_from_file(file: &ConfigFile) -> Result<Vec<Self>, ConfigError> {
+        //get health check settings
+        let health_check =
+            get_health_check(&file.health_check, "health_check", &HealthCheck::default())?;
+
+        let mut upstreams = BTreeMap::new();
+        for (name, upstream) in &file.upstream {
+            if name.is_empty() {
+                return Err(ConfigError::invalid("upstream", "names must not be empty"));
             }
+            upstreams.insert(name.clone(), get_upstream(name, upstream, &health_check)?);
+        }
+
+        //a connection released by any listener may free a server another listener waits for
+        let released = Arc::new(Notify::new());
+        file.listeners()?
+            .iter()
+            .map(|(listener, path, servers_path)| {
+                Self::from_listener(
+                    file,
+                    listener,
+                    path,
+                    servers_path,
+                    &health_check,
+                    &upstreams,
+                    &released,
+                )
+            })
+            .collect()
+    }
+
+    //creates and returns the Config of a listener of a config file
+    //named upstreams are taken from upstreams, their queue is woken by released
+    fn from_listener(
+        file: &ConfigFile,
+        load_balancer: &LoadBalancerSection,
+        path: &str,
+        servers_path: &str,
+        health_check: &HealthCheck,
+        upstreams: &BTreeMap<String, Upstream>,
+        released: &Arc<Notify>,
+    ) -> Result<Self, ConfigError> {
+        //get address of load balancer
+        let load_balancer_address = get_address(&load_balancer.address, load_balancer.port, path)?;
+
+        //get TLS termination settings of load balancer (L7)
+        let tls = match &load_balancer.tls {
+            Some(tls) => Some(get_tls(tls, &key_path(path, "tls"))?),
+            None => None,
         };
 
-        //create Config
-        Self {
-            //address of load balancer
-            load_balancer_address: (load_balancer_host.to_owned()
-                + ":"
-                + &load_balancer_port.to_string())
-                .parse::<Uri>()
-                .unwrap(),
-
-            //list of servers
-            servers: {
-                //get servers table
-                if let Some(Value::Array(servers)) = values.get("server") {
-                    Arc::new(
-                        servers
-                            .iter()
-                            .map(|server| {
-                                //get server host
-                                let server_host = {
-                                    if let Some(Value::String(address)) = server.get("address") {
-                                        address.as_str()
-                                    } else {
-                                        "localhost"
-                                    }
-                                };
-                                //get server port
-                                let server_port = {
-                                    if let Some(Value::Integer(port)) = server.get("port") {
-                                        port
-                                    } else {
-                                        &3000
-                                    }
-                                };
-                                //get max connections
-                                let max_connections = *{
-                                    if let Some(Value::Integer(max_connections)) =
-                                        server.get("max_connections")
-                                    {
-                                        max_connections
-                                    } else {
-                                        &1000
-                                    }
-                                } as u32;
-                                //get weight
-                                let weight = *{
-                                    if let Some(Value::Integer(weight)) = server.get("weight") {
-                                        weight
-                                    } else {
-                                        &1
-                                    }
-                                } as usize;
-                                //create new server object
-                                Arc::new(Mutex::new(Server::new(
-                                    (server_host.to_owned() + ":" + &server_port.to_string())
-                                        .parse::<Uri>()
-                                        .unwrap(),
-                                    max_connections,
-                                    weight,
-                                )))
-                            })
-                            .collect(),
-                    )
-                } else {
-                    //if servers not found in config
-                    let server1 =
-                        Server::new("http://127.0.0.1:3000".parse::<Uri>().unwrap(), 1000, 1);
-                    let server2 =
-                        Server::new("http://127.0.0.1:3001".parse::<Uri>().unwrap(), 1000, 1);
-                    Arc::new(vec![
-                        Arc::new(Mutex::new(server1)),
-                        Arc::new(Mutex::new(server2)),
-                    ])
+        //get the upstream of requests matching no route, a named one or the servers of the listener
+        let named_upstreams = upstreams;
+        let mut upstreams = Vec::new();
+        let upstream = match &load_balancer.upstream {
+            Some(name) => {
+                if !load_balancer.server.is_empty() {
+                    return Err(ConfigError::invalid(
+                        servers_path,
+                        "can not be used with upstream, servers are set in the upstream",
+                    ));
                 }
-            },
-            algorithm_object: {
-                //pick algorithm based on input
-                match &algorithm {
-                    Algorithm::RoundRobin => Box::new(RoundRobin::new()),
-                    Algorithm::WeightedRoundRobin => Box::new(WeightedRoundRobin::new()),
-                    Algorithm::IpHashing => Box::new(IpHashing::new()),
+                if load_balancer.algorithm.is_some() {
+                    return Err(ConfigError::invalid(
+                        key_path(path, "algorithm"),
+                        "can not be used with upstream, the algorithm is set in the upstream",
+                    ));
                 }
+                upstreams.push(get_named_upstream(
+                    named_upstreams,
+                    name,
+                    &key_path(path, "upstream"),
+                )?);
+                Some(name.clone())
+            }
+            //requests matching no route are rejected
+            None if load_balancer.server.is_empty() && !load_balancer.route.is_empty() => None,
+            None => {
+                upstreams.push(get_servers_upstream(
+                    &load_balancer.server,
+                    servers_path,
+                    load_balancer.algorithm.clone().unwrap_or_default(),
+                    health_check,
+                )?);
+                Some(String::new())
+            }
+        };
+
+        //get routes of requests to upstreams (L7)
+        if !load_balancer.route.is_empty() && load_balancer.layer != LayerMode::L7 {
+            return Err(ConfigError::invalid(
+                key_path(path, "route"),
+                "routes can only be used with layer = \"L7\"",
+            ));
+        }
+        let mut routes = Vec::new();
+        for (index, section) in load_balancer.route.iter().enumerate() {
+            let route_path = format!("{}.route[{}]", path, index);
+            let route = get_route(section, &route_path)?;
+            if !upstreams
+                .iter()
+                .any(|upstream| upstream.name == route.upstream)
+            {
+                upstreams.push(get_named_upstream(
+                    named_upstreams,
+                    &route.upstream,
+                    &key_path(&route_path, "upstream"),
+                )?);
+            }
+            routes.push(route);
+        }
+
+        //check TLS passthrough settings against the layer and servers (L4)
+        if load_balancer.tls_passthrough && load_balancer.layer != LayerMode::L4 {
+            return Err(ConfigError::invalid(
+                key_path(path, "tls_passthrough"),
+                "tls_passthrough can only be used with layer = \"L4\"",
+            ));
+        }
+        for upstream in upstreams.iter() {
+            let upstream_servers_path = if upstream.name.is_empty() {
+                servers_path.to_owned()
+            } else {
+                format!("upstream.{}.server", upstream.name)
+            };
+            check_tls_passthrough(
+                upstream,
+                &upstream_servers_path,
+                load_balancer.tls_passthrough,
+            )?;
+        }
+
+        //get the proxies whose forwarded headers are trusted (L7)
+        let trusted_proxies = load_balancer
+            .trusted_proxies
+            .iter()
+            .enumerate()
+            .map(|(index, proxy)| {
+                TrustedProxy::parse(proxy).map_err(|message| {
+                    ConfigError::invalid(format!("{}.trusted_proxies[{}]", path, index), message)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        //a zero timeout fails every request (L7)
+        if load_balancer.upstream_timeout_ms == 0 {
+            return Err(ConfigError::invalid(
+                key_path(path, "upstream_timeout_ms"),
+                "must be at least 1",
+            ));
+        }
+
+        let pool = &file.connection_pool;
+        let outlier_detection = &file.outlier_detection;
+        //an empty window makes every server an outlier on its first failure
+        if outlier_detection.window_size == 0 {
+            return Err(ConfigError::invalid(
+                "outlier_detection.window_size",
+                "must be at least 1",
+            ));
+        }
+        if outlier_detection.max_ejection_percent > 100 {
+            return Err(ConfigError::invalid(
+                "outlier_detection.max_ejection_percent",
+                "must be between 0 and 100",
+            ));
+        }
+
+        //create Config
+        Ok(Self {
+            name: load_balancer.name.clone(),
+            load_balancer_address,
+            upstream,
+            upstreams,
+            router: Router {
+                matching: load_balancer.route_matching,
+                routes,
+            },
+            layer_mode: load_balancer.layer.clone(),
+            tls,
+            tls_passthrough: load_balancer.tls_passthrough,
+            client_hello_timeout: Duration::from_millis(load_balancer.client_hello_timeout_ms),
+            cert_resolver: Arc::new(CertResolver::new()),
+            queue: Arc::new(WaitQueue::with_released(
+                load_balancer.queue_size,
+                Duration::from_millis(load_balancer.queue_timeout_ms),
+                released.clone(),
+            )),
+            max_connect_attempts: load_balancer.max_connect_attempts,
+            upstream_timeout: Duration::from_millis(load_balancer.upstream_timeout_ms),
+            error_body: load_balancer.error_body.clone(),
+            error_content_type: load_balancer.error_content_type.clone(),
+            forwarding: Arc::new(Forwarding {
+                headers: load_balancer.forwarded_headers,
+                trusted_proxies,
+            }),
+            connection_pool: ConnectionPool {
+                max_idle: pool.max_idle,
+                idle_timeout: Duration::from_millis(pool.idle_timeout_ms),
+                max_lifetime: Duration::from_millis(pool.max_lifetime_ms),
+            },
+            health_check: health_check.clone(),
+            outlier_detection: OutlierDetection {
+                enabled: outlier_detection.enabled,
+                consecutive_failures: outlier_detection.consecutive_failures,
+                failure_rate_percent: outlier_detection.failure_rate_percent,
+                window_size: outlier_detection.window_size,
+                base_ejection: Duration::from_millis(outlier_detection.base_ejection_ms),
+                max_ejection: Duration::from_millis(outlier_detection.max_ejection_ms),
+                max_ejection_percent: outlier_detection.max_ejection_percent,
+            },
+        })
+    }
+
+    /// Returns the upstream named name, the servers of the listener if name is empty
+    pub fn find_upstream(&self, name: &str) -> Option<&Upstream> {
+        self.upstreams.iter().find(|upstream| upstream.name == name)
+    }
+
+    /// Returns the servers of the upstream of requests matching no route
+    pub fn servers(&self) -> Arc<Vec<SyncServer>> {
+        self.upstream
+            .as_deref()
+            .and_then(|name| self.find_upstream(name))
+            .map(|upstream| upstream.servers.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the effective config as the sections of a config file
+    ///
+    /// Every setting is written out, including defaults and reloaded servers.
+    /// Use `ConfigFile::to_toml` to dump it.
+    pub fn to_file(&self) -> ConfigFile {
+        let mut load_balancer = self.to_listener();
+        ConfigFile {
+            server: std::mem::take(&mut load_balancer.server),
+            load_balancer: Some(load_balancer),
+            connection_pool: (&self.connection_pool).into(),
+            health_check: (&self.health_check).into(),
+            outlier_detection: (&self.outlier_detection).into(),
+            upstream: self.to_upstreams(),
+            listener: Vec::new(),
+        }
+    }
+
+    /// Returns the effective config of several listeners as the sections of a config file
+    ///
+    /// A single listener is written as `[load_balancer]`, several as `[[listener]]`
+    /// blocks with the shared settings of the first one.
+    pub fn listeners_to_file(listeners: &[Config]) -> ConfigFile {
+        match listeners {
+            [config] => config.to_file(),
+            _ => ConfigFile {
+                load_balancer: None,
+                server: Vec::new(),
+                upstream: listeners.iter().flat_map(Config::to_upstreams).collect(),
+                listener: listeners.iter().map(Config::to_listener).collect(),
+                ..listeners.first().map(Config::to_file).unwrap_or_default()
             },
-            algorithm,
-            last_picked_index: 0,
-            layer_mode,
         }
     }
+
+    //returns the sections of the named upstreams of the config
+    fn to_upstreams(&self) -> BTreeMap<String, UpstreamSection> {
+        self.upstreams
+            .iter()
+            .filter(|upstream| !upstream.name.is_empty())
+            .map(|upstream| (upstream.name.clone(), upstream.into()))
+            .collect()
+    }
+
+    //returns the listener section of the config, with its own servers
+    fn to_listener(&self) -> LoadBalancerSection {
+        //servers of a named upstream are in its section
+        let servers = self.find_upstream("").map(UpstreamSection::from);
+        LoadBalancerSection {
+            name: self.name.clone(),
+            address: self
+                .load_balancer_address
+                .host()
+                .unwrap_or("localhost")
+                .to_owned(),
+            port: self.load_balancer_address.port_u16().unwrap_or(8080),
+            upstream: self.upstream.clone().filter(|name| !name.is_empty()),
+            algorithm: servers.as_ref().map(|servers| servers.algorithm.clone()),
+            layer: self.layer_mode.clone(),
+            tls_passthrough: self.tls_passthrough,
+            client_hello_timeout_ms: self.client_hello_timeout.as_millis() as u64,
+            queue_size: self.queue.capacity(),
+            queue_timeout_ms: self.queue.timeout().as_millis() as u64,
+            max_connect_attempts: self.max_connect_attempts,
+            upstream_timeout_ms: self.upstream_timeout.as_millis() as u64,
+            error_body: self.error_body.clone(),
+            error_content_type: self.error_content_type.clone(),
+            route_matching: self.router.matching,
+            forwarded_headers: self.forwarding.headers,
+            trusted_proxies: self
+                .forwarding
+                .trusted_proxies
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            tls: self.tls.as_ref().map(Into::into),
+            route: self.router.routes.iter().map(Into::into).collect(),
+            server: servers.map(|servers| servers.server).unwrap_or_default(),
+        }
+    }
+
+    /// Applies a config parsed again from the same file
+    ///
+    /// Upstreams with the same name are kept. Their servers at the same address
+    /// keep their connections, health and pooled connections and take the new
+    /// settings, other servers and upstreams are added or removed.
+    /// Connections to removed servers finish normally. The listener address,
+    /// layer and TLS listener settings other than certificates need a restart.
+    /// Returns an error and keeps the current config if the new certificates fail to load
+    pub fn apply(&mut self, new: Config) -> std::io::Result<()> {
+        //load the new certificates first, the only step that can fail
+        if let (Some(_), Some(tls)) = (&self.tls, &new.tls) {
+            self.cert_resolver.load(tls)?;
+        }
+
+        if new.load_balancer_address != self.load_balancer_address
+            || new.layer_mode != self.layer_mode
+            || new.tls.is_some() != self.tls.is_some()
+            || new.tls.as_ref().map(|tls| &tls.client_ca)
+                != self.tls.as_ref().map(|tls| &tls.client_ca)
+        {
+            eprintln!("Listener settings changed, restart to apply them");
+        }
+
+        //keep upstreams with the same name, add the new ones
+        let mut upstreams = Vec::new();
+        for new_upstream in new.upstreams {
+            match self
+                .upstreams
+                .iter()
+                .position(|upstream| upstream.name == new_upstream.name)
+            {
+                Some(index) => {
+                    let mut upstream = self.upstreams.swap_remove(index);
+                    upstream.apply(new_upstream);
+                    upstreams.push(upstream);
+                }
+                None => {
+                    if !new_upstream.name.is_empty() {
+                        println!("Upstream {} added", new_upstream.name);
+                    }
+                    upstreams.push(new_upstream);
+                }
+            }
+        }
+        for upstream in self.upstreams.iter() {
+            if !upstream.name.is_empty() {
+                println!("Upstream {} removed", upstream.name);
+            }
+        }
+        self.upstreams = upstreams;
+        self.upstream = new.upstream;
+        self.router = new.router;
+
+        //connections already waiting keep their queue
+        if new.queue.capacity() != self.queue.capacity()
+            || new.queue.timeout() != self.queue.timeout()
+        {
+            self.queue = Arc::new(
+                self.queue
+                    .resized(new.queue.capacity(), new.queue.timeout()),
+            );
+        }
+
+        if self.tls.is_some() && new.tls.is_some() {
+            self.tls = new.tls;
+        }
+        self.tls_passthrough = new.tls_passthrough;
+        self.client_hello_timeout = new.client_hello_timeout;
+        self.max_connect_attempts = new.max_connect_attempts;
+        self.upstream_timeout = new.upstream_timeout;
+        self.error_body = new.error_body;
+        self.error_content_type = new.error_content_type;
+        self.forwarding = new.forwarding;
+        self.connection_pool = new.connection_pool;
+        self.health_check = new.health_check;
+        self.outlier_detection = new.outlier_detection;
+        Ok(())
+    }
 }
 
-//function to get Algorithm from string (case-insensitive)
-fn get_algorithm(algorithm: &str) -> Algorithm {
-    let algo_lower = algorithm.to_lowercase();
-    if algo_lower == "roundrobin" || algo_lower == "round_robin" {
-        Algorithm::RoundRobin
-    } else if algo_lower == "weightedroundrobin" || algo_lower == "weighted_round_robin" {
-        Algorithm::WeightedRoundRobin
-    } else if algo_lower == "iphashing" || algo_lower == "ip_hashing" {
-        Algorithm::IpHashing
-    } else {
-        Algorithm::RoundRobin
+//function to return the only listener of a config file
+fn single_listener(mut listeners: Vec<Config>) -> Result<Config, ConfigError> {
+    match listeners.len() {
+        1 => Ok(listeners.remove(0)),
+        count => Err(ConfigError::invalid(
+            "listener",
+            format!("expected a single listener, found {}", count),
+        )),
     }
 }
 
-//function to get LayerMode from string (case-insensitive)
-fn get_layer_mode(layer: &str) -> LayerMode {
-    let layer_lower = layer.to_lowercase();
-    if layer_lower == "l4" || layer_lower == "layer4" || layer_lower == "layer_4" {
-        LayerMode::L4
-    } else if layer_lower == "l7" || layer_lower == "layer7" || layer_lower == "layer_7" {
-        LayerMode::L7
-    } else {
-        LayerMode::L4 // Default to L4
+//function to get the named Upstream referenced at path for a listener
+//its servers are shared with the other listeners, the state of its algorithm is not
+fn get_named_upstream(
+    upstreams: &BTreeMap<String, Upstream>,
+    name: &str,
+    path: &str,
+) -> Result<Upstream, ConfigError> {
+    let upstream = upstreams
+        .get(name)
+        .ok_or_else(|| ConfigError::invalid(path, format!("unknown upstream \"{}\"", name)))?;
+    Ok(Upstream {
+        name: upstream.name.clone(),
+        servers: upstream.servers.clone(),
+        algorithm_object: get_algorithm_object(&upstream.algorithm),
+        algorithm: upstream.algorithm.clone(),
+        last_picked_index: 0,
+        health_check: upstream.health_check.clone(),
+    })
+}
+
+//function to get a Route from a route section
+fn get_route(section: &RouteSection, path: &str) -> Result<Route, ConfigError> {
+    let path_match = match (&section.path, &section.path_prefix, &section.path_regex) {
+        (None, None, None) => None,
+        (Some(exact), None, None) => Some(PathMatch::Exact(get_route_path(
+            exact,
+            &key_path(path, "path"),
+        )?)),
+        (None, Some(prefix), None) => Some(PathMatch::Prefix(get_route_path(
+            prefix,
+            &key_path(path, "path_prefix"),
+        )?)),
+        (None, None, Some(regex)) => {
+            Some(PathMatch::Regex(Regex::new(regex).map_err(|err| {
+                ConfigError::invalid(key_path(path, "path_regex"), err.to_string())
+            })?))
+        }
+        _ => {
+            return Err(ConfigError::invalid(
+                path,
+                "only one of path, path_prefix and path_regex can be set",
+            ))
+        }
+    };
+    let methods = section
+        .methods
+        .iter()
+        .enumerate()
+        .map(|(index, method)| {
+            Method::from_bytes(method.to_uppercase().as_bytes()).map_err(|_| {
+                ConfigError::invalid(
+                    format!("{}.methods[{}]", path, index),
+                    format!("invalid method \"{}\"", method),
+                )
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    let headers = section
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            let header = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                ConfigError::invalid(format!("{}.headers.{}", path, name), "invalid header name")
+            })?;
+            Ok((header, value.clone()))
+        })
+        .collect::<Result<_, ConfigError>>()?;
+    Ok(Route {
+        hosts: section
+            .hosts
+            .iter()
+            .map(|host| host.to_lowercase())
+            .collect(),
+        path: path_match,
+        methods,
+        headers,
+        upstream: section.upstream.clone(),
+        rewrite: Arc::new(HeaderRewrite {
+            preserve_host: section.preserve_host,
+            request: get_header_rules(
+                &section.request_headers,
+                &key_path(path, "request_headers"),
+            )?,
+            response: get_header_rules(
+                &section.response_headers,
+                &key_path(path, "response_headers"),
+            )?,
+        }),
+    })
+}
+
+//function to get HeaderRules from a section of header rules
+fn get_header_rules(section: &HeaderRulesSection, path: &str) -> Result<HeaderRules, ConfigError> {
+    let header_name = |name: &str, path: String| {
+        HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| ConfigError::invalid(path, "invalid header name"))
+    };
+    let templates = |headers: &BTreeMap<String, String>, path: String| {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value_path = format!("{}.{}", path, name);
+                let template = HeaderTemplate::parse(value)
+                    .map_err(|message| ConfigError::invalid(value_path.as_str(), message))?;
+                Ok((header_name(name, value_path)?, template))
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()
+    };
+    Ok(HeaderRules {
+        set: templates(&section.set, key_path(path, "set"))?,
+        add: templates(&section.add, key_path(path, "add"))?,
+        remove: section
+            .remove
+            .iter()
+            .enumerate()
+            .map(|(index, name)| header_name(name, format!("{}.remove[{}]", path, index)))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+//function to check the path or path prefix of a route
+fn get_route_path(route_path: &str, path: &str) -> Result<String, ConfigError> {
+    if !route_path.starts_with('/') {
+        return Err(ConfigError::invalid(path, "must start with /"));
+    }
+    Ok(route_path.to_owned())
+}
+
+//function to get a named Upstream from an upstream section
+fn get_upstream(
+    name: &str,
+    section: &UpstreamSection,
+    health_check: &HealthCheck,
+) -> Result<Upstream, ConfigError> {
+    let path = format!("upstream.{}", name);
+    //get health check settings overriding the global ones
+    let upstream_health_check = match &section.health_check {
+        Some(health_check_section) => Some(get_override_health_check(
+            health_check_section,
+            &key_path(&path, "health_check"),
+            health_check,
+        )?),
+        None => None,
+    };
+    let mut upstream = get_servers_upstream(
+        &section.server,
+        &key_path(&path, "server"),
+        section.algorithm.clone(),
+        upstream_health_check.as_ref().unwrap_or(health_check),
+    )?;
+    upstream.name = name.to_owned();
+    //servers without their own settings use the settings of the upstream
+    if let Some(upstream_health_check) = &upstream_health_check {
+        for server in upstream.servers.iter() {
+            let mut server = server.lock().unwrap();
+            if server.health_check().is_none() {
+                server.set_health_check(Some(upstream_health_check.clone()));
+            }
+        }
     }
+    upstream.health_check = upstream_health_check;
+    Ok(upstream)
+}
+
+//function to get an unnamed Upstream from a list of server sections
+fn get_servers_upstream(
+    sections: &[ServerSection],
+    servers_path: &str,
+    algorithm: Algorithm,
+    health_check: &HealthCheck,
+) -> Result<Upstream, ConfigError> {
+    if sections.is_empty() {
+        return Err(ConfigError::invalid(
+            servers_path,
+            "at least one server is required",
+        ));
+    }
+    let servers = sections
+        .iter()
+        .enumerate()
+        .map(|(index, server)| {
+            get_server(
+                server,
+                &format!("{}[{}]", servers_path, index),
+                health_check,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Upstream {
+        name: String::new(),
+        servers: Arc::new(servers),
+        //pick algorithm based on input
+        algorithm_object: get_algorithm_object(&algorithm),
+        algorithm,
+        last_picked_index: 0,
+        health_check: None,
+    })
+}
+
+//function to get a Server from a server section
+fn get_server(
+    server: &ServerSection,
+    path: &str,
+    health_check: &HealthCheck,
+) -> Result<SyncServer, ConfigError> {
+    //get server address
+    let uri = get_address(&server.address, server.port, path)?;
+    if server.weight == 0 {
+        return Err(ConfigError::invalid(
+            key_path(path, "weight"),
+            "must be at least 1",
+        ));
+    }
+    //a server without connections is never picked
+    if server.max_connections == 0 {
+        return Err(ConfigError::invalid(
+            key_path(path, "max_connections"),
+            "must be at least 1",
+        ));
+    }
+    //get health check settings overriding the ones of its upstream
+    let server_health_check = match &server.health_check {
+        Some(section) => Some(get_override_health_check(
+            section,
+            &key_path(path, "health_check"),
+            health_check,
+        )?),
+        None => None,
+    };
+    //get TLS settings of connections to server
+    let tls = server.tls.then(|| UpstreamTlsSettings {
+        ca: server.tls_ca.clone(),
+        server_name: server.tls_server_name.clone(),
+        client_cert: server.tls_client_cert.clone(),
+        client_key: server.tls_client_key.clone(),
+    });
+
+    //create new server object
+    let mut new_server = Server::new(uri, server.max_connections, server.weight);
+    new_server.set_health_check(server_health_check);
+    new_server.set_protocol(server.protocol);
+    //TLS server names routed to server (L4 passthrough)
+    new_server.set_server_names(
+        server
+            .server_names
+            .iter()
+            .map(|name| name.to_lowercase())
+            .collect(),
+    );
+    //set TLS settings of connections to server, after the protocol
+    new_server
+        .set_tls(tls)
+        .map_err(|err| ConfigError::invalid(key_path(path, "tls"), err.to_string()))?;
+    Ok(Arc::new(Mutex::new(new_server)))
+}
+
+//function to check the servers of an upstream against the TLS passthrough setting of its listener
+//passthrough forwards the TLS session of the client, so servers can not be connected to with TLS,
+//and server names are only used to route by SNI with passthrough
+fn check_tls_passthrough(
+    upstream: &Upstream,
+    servers_path: &str,
+    tls_passthrough: bool,
+) -> Result<(), ConfigError> {
+    for (index, server) in upstream.servers.iter().enumerate() {
+        let server = server.lock().unwrap();
+        let path = format!("{}[{}]", servers_path, index);
+        if tls_passthrough && server.tls().is_some() {
+            return Err(ConfigError::invalid(
+                key_path(&path, "tls"),
+                "can not be used with tls_passthrough, the TLS session of the client is forwarded as is",
+            ));
+        }
+        if !tls_passthrough && !server.server_names().is_empty() {
+            return Err(ConfigError::invalid(
+                key_path(&path, "server_names"),
+                "server_names can only be used with tls_passthrough = true",
+            ));
+        }
+    }
+    Ok(())
+}
+
+//function to get the address of the load balancer or a server
+fn get_address(host: &str, port: u16, path: &str) -> Result<Uri, ConfigError> {
+    if port == 0 {
+        return Err(ConfigError::invalid(
+            key_path(path, "port"),
+            "must be between 1 and 65535",
+        ));
+    }
+    let uri = (host.to_owned() + ":" + &port.to_string())
+        .parse::<Uri>()
+        .map_err(|err| ConfigError::invalid(key_path(path, "address"), err.to_string()))?;
+    //e.g. "http://a/b" parses with the port in the path
+    if uri.host().is_none_or(str::is_empty) || uri.port_u16() != Some(port) {
+        return Err(ConfigError::invalid(
+            key_path(path, "address"),
+            "must be a host name or IP address",
+        ));
+    }
+    Ok(uri)
+}
+
+//function to create the algorithm object of an Algorithm
+fn get_algorithm_object(algorithm: &Algorithm) -> Box<dyn AlgorithmTrait> {
+    match algorithm {
+        Algorithm::RoundRobin => Box::new(RoundRobin::new()),
+        Algorithm::WeightedRoundRobin => Box::new(WeightedRoundRobin::new()),
+        Algorithm::IpHashing => Box::new(IpHashing::new()),
+        Algorithm::LeastConnections => Box::new(LeastConnections::new()),
+        Algorithm::WeightedLeastConnections => Box::new(WeightedLeastConnections::new()),
+    }
+}
+
+//function to get TlsSettings from a TLS section
+//a certificate in the section itself comes before the [[certificate]] entries
+fn get_tls(tls: &TlsSection, path: &str) -> Result<TlsSettings, ConfigError> {
+    let mut certificates = Vec::new();
+    match (&tls.cert_chain, &tls.private_key) {
+        (Some(cert_chain), Some(private_key)) => certificates.push(CertificateSettings {
+            cert_chain: cert_chain.clone(),
+            private_key: private_key.clone(),
+            server_names: tls.server_names.clone(),
+            default: tls.default,
+        }),
+        (Some(_), None) => {
+            return Err(ConfigError::invalid(
+                key_path(path, "private_key"),
+                "missing required key",
+            ))
+        }
+        (None, Some(_)) => {
+            return Err(ConfigError::invalid(
+                key_path(path, "cert_chain"),
+                "missing required key",
+            ))
+        }
+        (None, None) => {}
+    }
+    certificates.extend(
+        tls.certificate
+            .iter()
+            .map(|certificate| CertificateSettings {
+                cert_chain: certificate.cert_chain.clone(),
+                private_key: certificate.private_key.clone(),
+                server_names: certificate.server_names.clone(),
+                default: certificate.default,
+            }),
+    );
+    if certificates.is_empty() {
+        return Err(ConfigError::invalid(
+            key_path(path, "cert_chain"),
+            "a certificate is required",
+        ));
+    }
+    Ok(TlsSettings {
+        certificates,
+        client_ca: tls.client_ca.clone(),
+    })
+}
+
+//function to get HealthCheck from a section, using defaults for missing values
+fn get_health_check(
+    section: &HealthCheckSection,
+    path: &str,
+    defaults: &HealthCheck,
+) -> Result<HealthCheck, ConfigError> {
+    let probe = match section.kind {
+        Some(ProbeKind::Tcp) => Probe::Tcp,
+        Some(ProbeKind::Http) => Probe::Http(get_http_probe(section, path, &HttpProbe::default())?),
+        //keep the default kind, but allow overriding http settings
+        None => match &defaults.probe {
+            Probe::Tcp => Probe::Tcp,
+            Probe::Http(http_probe) => Probe::Http(get_http_probe(section, path, http_probe)?),
+        },
+    };
+    //a zero interval probes in a busy loop, a zero timeout fails every probe
+    for (key, value) in [
+        ("interval_ms", section.interval_ms),
+        ("timeout_ms", section.timeout_ms),
+        ("rise", section.rise.map(u64::from)),
+        ("fall", section.fall.map(u64::from)),
+    ] {
+        if value == Some(0) {
+            return Err(ConfigError::invalid(
+                key_path(path, key),
+                "must be at least 1",
+            ));
+        }
+    }
+    Ok(HealthCheck {
+        enabled: section.enabled.unwrap_or(defaults.enabled),
+        interval: section
+            .interval_ms
+            .map_or(defaults.interval, Duration::from_millis),
+        timeout: section
+            .timeout_ms
+            .map_or(defaults.timeout, Duration::from_millis),
+        rise: section.rise.unwrap_or(defaults.rise),
+        fall: section.fall.unwrap_or(defaults.fall),
+        probe,
+    })
+}
+
+//function to get HealthCheck from the section of an upstream or server, overriding defaults
+//all servers are probed in the same rounds, so the interval can only be set globally
+fn get_override_health_check(
+    section: &HealthCheckSection,
+    path: &str,
+    defaults: &HealthCheck,
+) -> Result<HealthCheck, ConfigError> {
+    if section.interval_ms.is_some() {
+        return Err(ConfigError::invalid(
+            key_path(path, "interval_ms"),
+            "can only be set in [health_check]",
+        ));
+    }
+    get_health_check(section, path, defaults)
+}
+
+//function to get HttpProbe from a section, using defaults for missing values
+fn get_http_probe(
+    section: &HealthCheckSection,
+    path: &str,
+    defaults: &HttpProbe,
+) -> Result<HttpProbe, ConfigError> {
+    Ok(HttpProbe {
+        method: match &section.method {
+            Some(method) => method.to_uppercase().parse().map_err(|_| {
+                ConfigError::invalid(
+                    key_path(path, "method"),
+                    format!("unknown value \"{}\"", method),
+                )
+            })?,
+            None => defaults.method.clone(),
+        },
+        path: section
+            .path
+            .clone()
+            .unwrap_or_else(|| defaults.path.clone()),
+        host: section.host.clone().or_else(|| defaults.host.clone()),
+        expected_status: match &section.expected_status {
+            Some(StatusList::One(status)) => {
+                vec![get_status_range(
+                    status,
+                    &key_path(path, "expected_status"),
+                )?]
+            }
+            Some(StatusList::Many(statuses)) => statuses
+                .iter()
+                .enumerate()
+                .map(|(index, status)| {
+                    get_status_range(status, &format!("{}.expected_status[{}]", path, index))
+                })
+                .collect::<Result<_, _>>()?,
+            None => defaults.expected_status.clone(),
+        },
+        body_contains: section
+            .body_contains
+            .clone()
+            .or_else(|| defaults.body_contains.clone()),
+        body_regex: match &section.body_regex {
+            Some(regex) => Some(Regex::new(regex).map_err(|err| {
+                ConfigError::invalid(key_path(path, "body_regex"), err.to_string())
+            })?),
+            None => defaults.body_regex.clone(),
+        },
+    })
+}
+
+//function to get a status range from a code (200) or a range ("200-299")
+fn get_status_range(status: &Status, path: &str) -> Result<RangeInclusive<u16>, ConfigError> {
+    let invalid = || ConfigError::invalid(path, "expected a status like 200 or \"200-299\"");
+    let parse = |status: &str| status.trim().parse::<u16>().map_err(|_| invalid());
+    match status {
+        Status::Code(status) => {
+            let status = u16::try_from(*status).map_err(|_| invalid())?;
+            Ok(status..=status)
+        }
+        Status::Range(range) => match range.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(ConfigError::invalid(
+                        path,
+                        format!("range \"{}\" starts after it ends", range),
+                    ));
+                }
+                Ok(start..=end)
+            }
+            None => {
+                let status = parse(range)?;
+                Ok(status..=status)
+            }
+        },
+    }
+}
+
+//function to join the path of a section and a key
+fn key_path(path: &str, key: &str) -> String {
+    format!("{}.{}", path, key)
 }
@@ -0,0 +1,280 @@
+//! Environment variables in the configuration.
+//!
+//! This module replaces `${VAR}` and `${VAR:-default}` in the config file
+//! with environment variables before it is parsed, and applies `DESTON_`
+//! prefixed variables on top of the parsed file, e.g. `DESTON_LOAD_BALANCER__PORT`
+//! sets `port` of `[load_balancer]` and `DESTON_SERVER__0__ADDRESS` sets
+//! `address` of the first `[[server]]`. Values of variables are escaped for
+//! the string they are in, comments are left as they are.
+
+use toml::{Table, Value};
+
+use crate::config::error::ConfigError;
+use crate::config::schema::ConfigFile;
+
+/// Prefix of the environment variables overriding the config file
+pub const ENV_PREFIX: &str = "DESTON_";
+
+/// Separator of the keys in the name of an overriding environment variable
+pub const ENV_SEPARATOR: &str = "__";
+
+//part of the config file a placeholder is in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Context {
+    Bare,         //outside strings, the value is written as a TOML value
+    Basic,        //"basic string", the value is escaped
+    MultiBasic,   //"""multi-line basic string""", the value is escaped
+    Literal,      //'literal string', the value is written as is
+    MultiLiteral, //'''multi-line literal string''', the value is written as is
+}
+
+//replaces ${VAR} and ${VAR:-default} in contents with the value of VAR from lookup
+//the default is used if VAR is not set or empty, $${ is written as ${
+//values are escaped in strings, and written as TOML values, or as strings if they are not one, elsewhere
+//comments are left as they are, placeholders in keys are rejected
+//returns an error pointing to the line of a variable that is not set and has no default
+pub fn interpolate(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ConfigError> {
+    let bytes = contents.as_bytes();
+    let mut interpolated = String::with_capacity(contents.len());
+    let mut context = Context::Bare;
+    //open arrays and inline tables, [ or {
+    let mut nesting = Vec::new();
+    //true until the = of a key/value pair, and in table headers
+    let mut in_key = true;
+    //contents before copied are in interpolated
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        //$${ escapes the placeholder
+        if rest.starts_with(b"$${") {
+            interpolated.push_str(&contents[copied..i]);
+            interpolated.push_str("${");
+            i += 3;
+            copied = i;
+            continue;
+        }
+        if rest.starts_with(b"${") {
+            interpolated.push_str(&contents[copied..i]);
+            let line = contents[..i].matches('\n').count() + 1;
+            let invalid = |key: &str, message: &str| ConfigError::Invalid {
+                key: key.to_owned(),
+                line: Some(line),
+                message: message.to_owned(),
+            };
+
+            let placeholder = &contents[i + 2..];
+            let end = placeholder
+                .find('}')
+                .filter(|end| !placeholder[..*end].contains('\n'))
+                .ok_or_else(|| invalid("${", "missing closing }"))?;
+            let (name, default) = match placeholder[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&placeholder[..end], None),
+            };
+            let key = format!("${{{}}}", name);
+            if !is_variable_name(name) {
+                return Err(invalid(&key, "invalid environment variable name"));
+            }
+            if in_key {
+                return Err(invalid(
+                    &key,
+                    "environment variables can not be used in keys",
+                ));
+            }
+            let value = match (lookup(name).filter(|value| !value.is_empty()), default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => default.to_owned(),
+                (None, None) => return Err(invalid(&key, "environment variable is not set")),
+            };
+            write_value(&mut interpolated, context, &value)
+                .map_err(|message| invalid(&key, &message))?;
+            i += 2 + end + 1;
+            copied = i;
+            continue;
+        }
+
+        match context {
+            Context::Bare => match bytes[i] {
+                //skip comments
+                b'#' => {
+                    i = contents[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                    continue;
+                }
+                b'"' if rest.starts_with(b"\"\"\"") => {
+                    context = Context::MultiBasic;
+                    i += 3;
+                    continue;
+                }
+                b'\'' if rest.starts_with(b"'''") => {
+                    context = Context::MultiLiteral;
+                    i += 3;
+                    continue;
+                }
+                b'"' => context = Context::Basic,
+                b'\'' => context = Context::Literal,
+                b'=' => in_key = false,
+                //a new key/value pair or table header starts on every line outside arrays and inline tables
+                b'\n' if nesting.is_empty() => in_key = true,
+                //brackets of table headers are part of the key
+                b'[' if !in_key => nesting.push(b'['),
+                b']' if nesting.last() == Some(&b'[') => {
+                    nesting.pop();
+                }
+                b'{' => {
+                    nesting.push(b'{');
+                    in_key = true;
+                }
+                b'}' => {
+                    nesting.pop();
+                    in_key = false;
+                }
+                b',' => in_key = nesting.last() == Some(&b'{'),
+                _ => {}
+            },
+            Context::Basic | Context::MultiBasic if bytes[i] == b'\\' => {
+                //skip the escaped character
+                i += 2;
+                continue;
+            }
+            Context::Basic if bytes[i] == b'"' || bytes[i] == b'\n' => context = Context::Bare,
+            Context::MultiBasic if rest.starts_with(b"\"\"\"") => {
+                context = Context::Bare;
+                i += 3;
+                continue;
+            }
+            Context::Literal if bytes[i] == b'\'' || bytes[i] == b'\n' => context = Context::Bare,
+            Context::MultiLiteral if rest.starts_with(b"'''") => {
+                context = Context::Bare;
+                i += 3;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    interpolated.push_str(&contents[copied..]);
+    Ok(interpolated)
+}
+
+//writes value to interpolated so it stays in the string or TOML value of context
+//returns an error message if value can not be written in a literal string
+fn write_value(interpolated: &mut String, context: Context, value: &str) -> Result<(), String> {
+    match context {
+        Context::Bare => interpolated.push_str(&parse_value(value).to_string()),
+        Context::Basic | Context::MultiBasic => {
+            for c in value.chars() {
+                match c {
+                    '"' => interpolated.push_str("\\\""),
+                    '\\' => interpolated.push_str("\\\\"),
+                    '\n' => interpolated.push_str("\\n"),
+                    '\r' => interpolated.push_str("\\r"),
+                    '\t' => interpolated.push_str("\\t"),
+                    c if c.is_control() => interpolated.push_str(&format!("\\u{:04X}", c as u32)),
+                    c => interpolated.push(c),
+                }
+            }
+        }
+        Context::Literal if value.contains(|c: char| c == '\'' || c.is_control()) => {
+            return Err(
+                "value can not be written in a 'literal string', use a \"basic string\"".to_owned(),
+            );
+        }
+        Context::MultiLiteral if value.contains("'''") => {
+            return Err("value can not be written in a multi-line literal string".to_owned());
+        }
+        Context::Literal | Context::MultiLiteral => interpolated.push_str(value),
+    }
+    Ok(())
+}
+
+//sets the keys named by DESTON_ prefixed variables in values
+//keys are separated by __ and lowercased, numbers index arrays of tables
+//values are parsed as TOML values, e.g. 8080 or true, and are strings otherwise
+//variables not naming a section of the config file, e.g. DESTON_LOG, are ignored with a warning
+pub fn apply_overrides(
+    values: &mut Table,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<(), ConfigError> {
+    let mut vars = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect::<Vec<_>>();
+    //apply in a stable order
+    vars.sort();
+    for (name, value) in vars {
+        let keys = name[ENV_PREFIX.len()..]
+            .split(ENV_SEPARATOR)
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        if !keys[0].is_empty() && !ConfigFile::SECTIONS.contains(&keys[0].as_str()) {
+            eprintln!(
+                "Ignoring environment variable {}, {} is not a config section",
+                name, keys[0]
+            );
+            continue;
+        }
+        set_key(values, &keys, parse_value(&value))
+            .map_err(|message| ConfigError::invalid(name.as_str(), message))?;
+    }
+    Ok(())
+}
+
+//sets the value at the path of keys, creating the missing tables
+fn set_key(table: &mut Table, keys: &[String], value: Value) -> Result<(), String> {
+    let (key, keys) = match keys.split_first() {
+        Some((key, keys)) if !key.is_empty() => (key, keys),
+        _ => return Err("expected DESTON_<SECTION>__<KEY>".to_owned()),
+    };
+    if keys.is_empty() {
+        table.insert(key.clone(), value);
+        return Ok(());
+    }
+    match table
+        .entry(key.clone())
+        .or_insert_with(|| Value::Table(Table::new()))
+    {
+        Value::Table(table) => set_key(table, keys, value),
+        Value::Array(array) => {
+            //the next key is the index of a table in the array
+            let index = keys[0]
+                .parse::<usize>()
+                .map_err(|_| format!("{} is an array, expected an index", key))?;
+            let length = array.len();
+            match array.get_mut(index) {
+                Some(Value::Table(table)) if keys.len() > 1 => set_key(table, &keys[1..], value),
+                Some(element) if keys.len() == 1 => {
+                    *element = value;
+                    Ok(())
+                }
+                Some(_) => Err(format!("{}[{}] is not a table", key, index)),
+                None => Err(format!(
+                    "index {} is out of range, {} has {} entries",
+                    index, key, length
+                )),
+            }
+        }
+        _ => Err(format!("{} is not a table", key)),
+    }
+}
+
+//parses value as a TOML value, or returns it as a string
+fn parse_value(value: &str) -> Value {
+    format!("value = {}", value)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_owned()))
+}
+
+//returns true if name is a valid environment variable name
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
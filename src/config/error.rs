@@ -0,0 +1,145 @@
+//! Errors of config loading.
+//!
+//! This module defines the ConfigError type returned when a config file can
+//! not be read, is not valid TOML or holds an unknown key or invalid value.
+//! Errors about a key point to the line it is set on.
+
+use std::fmt;
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+/// Errors while loading a config
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),   //reading the config file failed
+    Parse(toml::de::Error), //the config file is not valid TOML
+    Invalid {
+        key: String,         //path of the key, e.g. server[1].port
+        line: Option<usize>, //line the key is set on, None if the key is missing
+        message: String,     //what is wrong with the key
+    },
+}
+
+impl ConfigError {
+    //creates an error about the key at path
+    pub fn invalid(key: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError::Invalid {
+            key: key.into(),
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    //returns the path of the key the error is about
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ConfigError::Invalid { key, .. } => Some(key),
+            ConfigError::Read(_) | ConfigError::Parse(_) => None,
+        }
+    }
+
+    //returns the line of the config the error is about
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            ConfigError::Invalid { line, .. } => *line,
+            ConfigError::Read(_) | ConfigError::Parse(_) => None,
+        }
+    }
+
+    //sets the line of the key the error is about from the contents of the config file
+    pub fn locate(self, contents: &str) -> Self {
+        match self {
+            ConfigError::Invalid { key, message, .. } => {
+                let line = ImDocument::parse(contents)
+                    .ok()
+                    .and_then(|document| find_span(&document, &key))
+                    .map(|offset| contents[..offset].matches('\n').count() + 1);
+                ConfigError::Invalid { key, line, message }
+            }
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(err) => write!(f, "failed to read config: {}", err),
+            ConfigError::Parse(err) => write!(f, "invalid TOML: {}", err),
+            ConfigError::Invalid {
+                key,
+                line: Some(line),
+                message,
+            } => write!(f, "line {}: {}: {}", line, key, message),
+            ConfigError::Invalid {
+                key,
+                line: None,
+                message,
+            } => write!(f, "{}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read(err) => Some(err),
+            ConfigError::Parse(err) => Some(err),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Read(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+impl From<serde_path_to_error::Error<toml::de::Error>> for ConfigError {
+    //the path of the error is the key it is about, e.g. an unknown key
+    fn from(err: serde_path_to_error::Error<toml::de::Error>) -> Self {
+        ConfigError::invalid(err.path().to_string(), err.inner().message())
+    }
+}
+
+//returns the offset of the key at path in a parsed document
+//path is made of dotted keys and array indexes, e.g. server[1].health_check.path
+fn find_span(document: &ImDocument<&str>, path: &str) -> Option<usize> {
+    let mut table: &dyn TableLike = document.as_table();
+    let mut offset = None;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let (name, index) = match segment.split_once('[') {
+            Some((name, index)) => (name, Some(index.trim_end_matches(']').parse().ok()?)),
+            None => (segment, None),
+        };
+        //point to the key itself rather than its value
+        let (key, item) = table.get_key_value(name)?;
+        offset = key.span().map(|span| span.start);
+        let last = segments.peek().is_none();
+        table = match (item, index) {
+            (Item::ArrayOfTables(tables), Some(index)) => {
+                let element = tables.get(index)?;
+                offset = element.span().map(|span| span.start).or(offset);
+                element
+            }
+            (Item::Value(Value::Array(array)), Some(index)) => {
+                let element = array.get(index)?;
+                offset = element.span().map(|span| span.start).or(offset);
+                if last {
+                    break;
+                }
+                element.as_inline_table()?
+            }
+            _ if last => break,
+            (item, _) => item.as_table_like()?,
+        };
+    }
+    offset
+}
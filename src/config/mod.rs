@@ -1,2 +1,6 @@
 #[allow(clippy::module_inception)]
 pub mod config;
+pub mod env;
+pub mod error;
+pub mod reload;
+pub mod schema;
@@ -0,0 +1,158 @@
+//! Hot reload of the configuration.
+//!
+//! This module parses the config file again on SIGHUP and applies it to the
+//! running load balancer without dropping connections. An invalid config is
+//! rejected with an error and the running config is kept.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::config::{Config, Overrides, SyncConfig};
+use crate::config::error::ConfigError;
+use crate::server::server::SyncServer;
+use crate::tls::resolver::CertResolver;
+
+/// Errors while reloading the config
+#[derive(Debug)]
+pub enum ReloadError {
+    Invalid(ConfigError),  //the config file could not be loaded
+    Apply(std::io::Error), //the config could not be applied, e.g. a certificate failed to load
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadError::Invalid(err) => write!(f, "invalid config: {}", err),
+            ReloadError::Apply(err) => write!(f, "failed to apply config: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReloadError::Invalid(err) => Some(err),
+            ReloadError::Apply(err) => Some(err),
+        }
+    }
+}
+
+//parses the config at path and applies it to config
+//the running config is kept if the new one is invalid
+pub fn reload(config: &SyncConfig, path: &Path) -> Result<(), ReloadError> {
+    reload_with(config, path, &Overrides::default())
+}
+
+//parses the config at path with overrides applied and applies it to config
+//the running config is kept if the new one is invalid
+pub fn reload_with(
+    config: &SyncConfig,
+    path: &Path,
+    overrides: &Overrides,
+) -> Result<(), ReloadError> {
+    reload_listeners(std::slice::from_ref(config), path, overrides)
+}
+
+//parses the config at path with overrides applied and applies every listener to configs
+//listeners are matched by their order in the file, adding or removing listeners needs a restart
+//the running configs are kept if the new ones are invalid or their certificates fail to load
+pub fn reload_listeners(
+    configs: &[SyncConfig],
+    path: &Path,
+    overrides: &Overrides,
+) -> Result<(), ReloadError> {
+    let mut listeners = Config::load_listeners(path, overrides).map_err(ReloadError::Invalid)?;
+    if listeners.len() != configs.len() {
+        return Err(ReloadError::Invalid(ConfigError::invalid(
+            "listener",
+            format!(
+                "found {} listeners, adding or removing listeners needs a restart",
+                listeners.len()
+            ),
+        )));
+    }
+    //load the certificates of every listener before applying any of them
+    for new in &listeners {
+        if let Some(tls) = &new.tls {
+            tls.server_config(Arc::new(CertResolver::new()))
+                .map_err(ReloadError::Apply)?;
+        }
+    }
+    share_running_servers(configs, &mut listeners);
+    for (config, new) in configs.iter().zip(listeners) {
+        config
+            .lock()
+            .unwrap()
+            .apply(new)
+            .map_err(ReloadError::Apply)?;
+    }
+    Ok(())
+}
+
+//replaces the servers of the named upstreams of listeners by the running servers at the same address
+//every listener using an upstream gets the same servers, also listeners that did not use it before
+fn share_running_servers(configs: &[SyncConfig], listeners: &mut [Config]) {
+    let mut shared: HashMap<String, Arc<Vec<SyncServer>>> = HashMap::new();
+    for new in listeners.iter_mut() {
+        for upstream in new.upstreams.iter_mut() {
+            if upstream.name.is_empty() {
+                continue;
+            }
+            let servers = shared.entry(upstream.name.clone()).or_insert_with(|| {
+                configs
+                    .iter()
+                    .find_map(|config| {
+                        let config = config.lock().unwrap();
+                        config
+                            .find_upstream(&upstream.name)
+                            .map(|running| running.merge_servers(&upstream.servers))
+                    })
+                    .map(Arc::new)
+                    .unwrap_or_else(|| upstream.servers.clone())
+            });
+            upstream.servers = servers.clone();
+        }
+    }
+}
+
+//reloads the configs of the listeners at path every time the process receives SIGHUP
+//overrides are applied again on every reload
+//runs until shutdown is signalled
+#[cfg(unix)]
+pub async fn reload_on_sighup(
+    configs: Vec<SyncConfig>,
+    path: PathBuf,
+    overrides: Overrides,
+    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(err) => {
+            eprintln!("Unable to listen for SIGHUP: {}", err);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            // Check if shutdown signal is received
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() {
+                    break;
+                }
+            }
+            // Reload the config
+            Some(()) = sighup.recv() => {
+                println!("Received SIGHUP, reloading {}...", path.display());
+                match reload_listeners(&configs, &path, &overrides) {
+                    Ok(()) => println!("Config reloaded"),
+                    Err(err) => eprintln!("Keeping current config, {}", err),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,754 @@
+//! Schema of the config file.
+//!
+//! This module defines the sections of the TOML config file as serde types.
+//! Missing keys take their defaults and unknown keys are rejected. The
+//! effective config can be converted back to these types and dumped as TOML.
+
+use http::header::HeaderName;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+use crate::config::config::{Algorithm, LayerMode};
+use crate::config::error::ConfigError;
+use crate::health_check::health_check::{HealthCheck, Probe};
+use crate::health_check::outlier_detection::OutlierDetection;
+use crate::load_balancer::router::{PathMatch, Route, RouteMatching};
+use crate::load_balancer::upstream::Upstream;
+use crate::server::forwarded::ForwardedHeaders;
+use crate::server::headers::{HeaderRules, HeaderTemplate};
+use crate::server::pool::ConnectionPool;
+use crate::server::server::{Protocol, Server};
+use crate::tls::tls::{CertificateSettings, TlsSettings};
+
+/// Contents of a config file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_balancer: Option<LoadBalancerSection>, //[load_balancer], the only listener
+    pub connection_pool: ConnectionPoolSection, //[connection_pool]
+    pub health_check: HealthCheckSection,       //[health_check]
+    pub outlier_detection: OutlierDetectionSection, //[outlier_detection]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub server: Vec<ServerSection>, //[[server]], servers of [load_balancer]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub upstream: BTreeMap<String, UpstreamSection>, //[upstream.<name>], named pools of servers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub listener: Vec<LoadBalancerSection>, //[[listener]], instead of [load_balancer]
+}
+
+/// Settings of a load balancer listener
+///
+/// Servers are set in the section for `[[listener]]` blocks and in the
+/// `[[server]]` blocks for `[load_balancer]`, or taken from a named upstream.
+/// An L7 listener with routes may have no servers, requests matching no route
+/// are then rejected.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoadBalancerSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>, //name of the listener in logs
+    pub address: String,
+    pub port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>, //name of the upstream, instead of servers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<Algorithm>, //algorithm of the servers, round robin if None
+    pub layer: LayerMode,
+    pub tls_passthrough: bool,
+    pub client_hello_timeout_ms: u64,
+    pub queue_size: usize,
+    pub queue_timeout_ms: u64,
+    pub max_connect_attempts: u32,
+    pub upstream_timeout_ms: u64,
+    pub error_body: String,
+    pub error_content_type: String,
+    pub route_matching: RouteMatching,
+    pub forwarded_headers: ForwardedHeaders,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<String>, //addresses or networks of proxies whose forwarded headers are kept (L7)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsSection>, //TLS termination, plain HTTP if None (L7)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub route: Vec<RouteSection>, //[[listener.route]], routes of requests to upstreams (L7)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub server: Vec<ServerSection>, //[[listener.server]]
+}
+
+impl Default for LoadBalancerSection {
+    fn default() -> Self {
+        Self {
+            name: None,
+            address: "localhost".to_owned(),
+            port: 8080,
+            upstream: None,
+            algorithm: None,
+            layer: LayerMode::L4,
+            tls_passthrough: false,
+            client_hello_timeout_ms: 5000,
+            queue_size: 100,
+            queue_timeout_ms: 5000,
+            max_connect_attempts: 3,
+            upstream_timeout_ms: 30000,
+            error_body: "{status} {reason}".to_owned(),
+            error_content_type: "text/plain; charset=utf-8".to_owned(),
+            route_matching: RouteMatching::FirstMatch,
+            forwarded_headers: ForwardedHeaders::All,
+            trusted_proxies: Vec::new(),
+            tls: None,
+            route: Vec::new(),
+            server: Vec::new(),
+        }
+    }
+}
+
+/// Route of requests to an upstream (L7)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSection {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_regex: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    pub upstream: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub preserve_host: bool,
+    #[serde(default, skip_serializing_if = "HeaderRulesSection::is_empty")]
+    pub request_headers: HeaderRulesSection, //rewrites of forwarded requests
+    #[serde(default, skip_serializing_if = "HeaderRulesSection::is_empty")]
+    pub response_headers: HeaderRulesSection, //rewrites of responses
+}
+
+/// Headers set, added and removed by a route, values may contain variables
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeaderRulesSection {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub add: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+}
+
+impl HeaderRulesSection {
+    //returns true if there are no rules, to skip serializing them
+    fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// TLS termination settings, a certificate in the section itself comes first
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_chain: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub server_names: Vec<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub default: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub certificate: Vec<CertificateSection>, //[[load_balancer.tls.certificate]]
+}
+
+/// Certificate presented for some server names
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CertificateSection {
+    pub cert_chain: PathBuf,
+    pub private_key: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub server_names: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub default: bool,
+}
+
+/// Pooling of connections to servers (L7)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionPoolSection {
+    pub max_idle: usize,
+    pub idle_timeout_ms: u64,
+    pub max_lifetime_ms: u64,
+}
+
+impl Default for ConnectionPoolSection {
+    fn default() -> Self {
+        (&ConnectionPool::default()).into()
+    }
+}
+
+/// Health check settings, missing keys are inherited
+///
+/// The global section inherits the defaults, the section of an upstream
+/// inherits the global section and the section of a server inherits the
+/// section of its upstream.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheckSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rise: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fall: Option<u32>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ProbeKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_status: Option<StatusList>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_regex: Option<String>,
+}
+
+/// Health check probe kinds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbeKind {
+    Tcp,  //TCP connect
+    Http, //HTTP request
+}
+
+/// Expected statuses of an HTTP health check, one or a list
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StatusList {
+    One(Status),
+    Many(Vec<Status>),
+}
+
+/// Expected status, a code like 200 or a range like "200-299"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Status {
+    Code(i64),
+    Range(String),
+}
+
+/// Outlier detection settings
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutlierDetectionSection {
+    pub enabled: bool,
+    pub consecutive_failures: u32,
+    pub failure_rate_percent: u32,
+    pub window_size: usize,
+    pub base_ejection_ms: u64,
+    pub max_ejection_ms: u64,
+    pub max_ejection_percent: u32,
+}
+
+impl Default for OutlierDetectionSection {
+    fn default() -> Self {
+        (&OutlierDetection::default()).into()
+    }
+}
+
+/// Named pool of servers
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamSection {
+    pub algorithm: Algorithm,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckSection>, //overrides of the global health check
+    pub server: Vec<ServerSection>, //[[upstream.<name>.server]]
+}
+
+/// Settings of a backend server
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub address: String,
+    pub port: u16,
+    pub max_connections: u32,
+    pub weight: usize,
+    pub protocol: Protocol,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub server_names: Vec<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub tls: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ca: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_server_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_client_cert: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_client_key: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheckSection>, //overrides of the health check of its upstream
+}
+
+impl Default for ServerSection {
+    fn default() -> Self {
+        Self {
+            address: "localhost".to_owned(),
+            port: 3000,
+            max_connections: 1000,
+            weight: 1,
+            protocol: Protocol::Http1,
+            server_names: Vec::new(),
+            tls: false,
+            tls_ca: None,
+            tls_server_name: None,
+            tls_client_cert: None,
+            tls_client_key: None,
+            health_check: None,
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Top-level sections of a config file
+    pub const SECTIONS: &'static [&'static str] = &[
+        "load_balancer",
+        "connection_pool",
+        "health_check",
+        "outlier_detection",
+        "server",
+        "upstream",
+        "listener",
+    ];
+
+    /// Parses a config file
+    ///
+    /// Returns an error pointing to the line of the first unknown key or value of the wrong type
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        Self::from_table(contents.parse::<Table>()?, contents)
+    }
+
+    /// Creates a config file from the values parsed from contents
+    ///
+    /// contents is used to point errors to their line
+    pub fn from_table(values: Table, contents: &str) -> Result<Self, ConfigError> {
+        serde_path_to_error::deserialize(Value::Table(values))
+            .map_err(|err| ConfigError::from(err).locate(contents))
+    }
+
+    /// Returns the listeners of the config file with their paths in the file
+    ///
+    /// A config file has either a `[load_balancer]` with `[[server]]` blocks, or
+    /// `[[listener]]` blocks with their own servers. Returns the listener
+    /// sections with their servers, the path of each section and the path of
+    /// its servers.
+    pub fn listeners(&self) -> Result<Vec<(LoadBalancerSection, String, String)>, ConfigError> {
+        if self.listener.is_empty() {
+            let mut load_balancer = self.load_balancer.clone().unwrap_or_default();
+            if !load_balancer.server.is_empty() {
+                return Err(ConfigError::invalid(
+                    "load_balancer.server",
+                    "servers of [load_balancer] are [[server]] blocks",
+                ));
+            }
+            load_balancer.server = self.server.clone();
+            return Ok(vec![(
+                load_balancer,
+                "load_balancer".to_owned(),
+                "server".to_owned(),
+            )]);
+        }
+        if self.load_balancer.is_some() {
+            return Err(ConfigError::invalid(
+                "load_balancer",
+                "can not be used with [[listener]]",
+            ));
+        }
+        if !self.server.is_empty() {
+            return Err(ConfigError::invalid(
+                "server",
+                "can not be used with [[listener]], use [[listener.server]]",
+            ));
+        }
+        Ok(self
+            .listener
+            .iter()
+            .enumerate()
+            .map(|(index, listener)| {
+                let path = format!("listener[{}]", index);
+                let servers_path = format!("{}.server", path);
+                (listener.clone(), path, servers_path)
+            })
+            .collect())
+    }
+
+    /// Serializes the config file to TOML
+    pub fn to_toml(&self) -> String {
+        //every section is a table of plain values, serializing can not fail
+        toml::to_string(self).expect("config is serializable")
+    }
+}
+
+impl From<&TlsSettings> for TlsSection {
+    fn from(tls: &TlsSettings) -> Self {
+        Self {
+            client_ca: tls.client_ca.clone(),
+            certificate: tls.certificates.iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&CertificateSettings> for CertificateSection {
+    fn from(certificate: &CertificateSettings) -> Self {
+        Self {
+            cert_chain: certificate.cert_chain.clone(),
+            private_key: certificate.private_key.clone(),
+            server_names: certificate.server_names.clone(),
+            default: certificate.default,
+        }
+    }
+}
+
+impl From<&ConnectionPool> for ConnectionPoolSection {
+    fn from(pool: &ConnectionPool) -> Self {
+        Self {
+            max_idle: pool.max_idle,
+            idle_timeout_ms: pool.idle_timeout.as_millis() as u64,
+            max_lifetime_ms: pool.max_lifetime.as_millis() as u64,
+        }
+    }
+}
+
+impl HealthCheckSection {
+    //returns the section of the health check of an upstream or server, without the global interval
+    fn from_override(health_check: &HealthCheck) -> Self {
+        Self {
+            interval_ms: None,
+            ..health_check.into()
+        }
+    }
+}
+
+impl From<&HealthCheck> for HealthCheckSection {
+    fn from(health_check: &HealthCheck) -> Self {
+        let mut section = Self {
+            enabled: Some(health_check.enabled),
+            interval_ms: Some(health_check.interval.as_millis() as u64),
+            timeout_ms: Some(health_check.timeout.as_millis() as u64),
+            rise: Some(health_check.rise),
+            fall: Some(health_check.fall),
+            kind: Some(ProbeKind::Tcp),
+            ..Self::default()
+        };
+        if let Probe::Http(probe) = &health_check.probe {
+            section.kind = Some(ProbeKind::Http);
+            section.method = Some(probe.method.to_string());
+            section.path = Some(probe.path.clone());
+            section.host = probe.host.clone();
+            section.expected_status = Some(StatusList::Many(
+                probe
+                    .expected_status
+                    .iter()
+                    .map(|range| match (range.start(), range.end()) {
+                        (start, end) if start == end => Status::Code(i64::from(*start)),
+                        (start, end) => Status::Range(format!("{}-{}", start, end)),
+                    })
+                    .collect(),
+            ));
+            section.body_contains = probe.body_contains.clone();
+            section.body_regex = probe
+                .body_regex
+                .as_ref()
+                .map(|regex| regex.as_str().to_owned());
+        }
+        section
+    }
+}
+
+impl From<&OutlierDetection> for OutlierDetectionSection {
+    fn from(outlier_detection: &OutlierDetection) -> Self {
+        Self {
+            enabled: outlier_detection.enabled,
+            consecutive_failures: outlier_detection.consecutive_failures,
+            failure_rate_percent: outlier_detection.failure_rate_percent,
+            window_size: outlier_detection.window_size,
+            base_ejection_ms: outlier_detection.base_ejection.as_millis() as u64,
+            max_ejection_ms: outlier_detection.max_ejection.as_millis() as u64,
+            max_ejection_percent: outlier_detection.max_ejection_percent,
+        }
+    }
+}
+
+impl From<&Server> for ServerSection {
+    fn from(server: &Server) -> Self {
+        let tls = server.tls();
+        Self {
+            address: server.host().to_owned(),
+            port: server.port(),
+            max_connections: server.max_connections(),
+            weight: server.weight,
+            protocol: server.protocol(),
+            server_names: server.server_names().to_vec(),
+            tls: tls.is_some(),
+            tls_ca: tls.and_then(|tls| tls.ca.clone()),
+            tls_server_name: tls.and_then(|tls| tls.server_name.clone()),
+            tls_client_cert: tls.and_then(|tls| tls.client_cert.clone()),
+            tls_client_key: tls.and_then(|tls| tls.client_key.clone()),
+            health_check: server.health_check().map(HealthCheckSection::from_override),
+        }
+    }
+}
+
+impl From<&Upstream> for UpstreamSection {
+    fn from(upstream: &Upstream) -> Self {
+        Self {
+            algorithm: upstream.algorithm.clone(),
+            health_check: upstream
+                .health_check
+                .as_ref()
+                .map(HealthCheckSection::from_override),
+            server: upstream
+                .servers
+                .iter()
+                .map(|server| {
+                    let server = server.lock().unwrap();
+                    let mut section = ServerSection::from(&*server);
+                    //settings inherited from the upstream are written once
+                    if server.health_check() == upstream.health_check.as_ref() {
+                        section.health_check = None;
+                    }
+                    section
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&Route> for RouteSection {
+    fn from(route: &Route) -> Self {
+        let (path, path_prefix, path_regex) = match &route.path {
+            Some(PathMatch::Exact(exact)) => (Some(exact.clone()), None, None),
+            Some(PathMatch::Prefix(prefix)) => (None, Some(prefix.clone()), None),
+            Some(PathMatch::Regex(regex)) => (None, None, Some(regex.as_str().to_owned())),
+            None => (None, None, None),
+        };
+        Self {
+            hosts: route.hosts.clone(),
+            path,
+            path_prefix,
+            path_regex,
+            methods: route
+                .methods
+                .iter()
+                .map(|method| method.as_str().to_owned())
+                .collect(),
+            headers: route
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str().to_owned(), value.clone()))
+                .collect(),
+            upstream: route.upstream.clone(),
+            preserve_host: route.rewrite.preserve_host,
+            request_headers: (&route.rewrite.request).into(),
+            response_headers: (&route.rewrite.response).into(),
+        }
+    }
+}
+
+impl From<&HeaderRules> for HeaderRulesSection {
+    fn from(rules: &HeaderRules) -> Self {
+        let templates = |headers: &[(HeaderName, HeaderTemplate)]| {
+            headers
+                .iter()
+                .map(|(name, template)| (name.as_str().to_owned(), template.as_str().to_owned()))
+                .collect()
+        };
+        Self {
+            set: templates(&rules.set),
+            add: templates(&rules.add),
+            remove: rules
+                .remove
+                .iter()
+                .map(|name| name.as_str().to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Enums written as names in the config file
+///
+/// Names are parsed case-insensitively and written in their canonical form.
+pub trait Named: Sized {
+    //returns the canonical name of the value
+    fn name(&self) -> &'static str;
+
+    //returns the value with the given name, or None if unknown
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+impl Named for Algorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::RoundRobin => "round_robin",
+            Algorithm::WeightedRoundRobin => "weighted_round_robin",
+            Algorithm::IpHashing => "ip_hashing",
+            Algorithm::LeastConnections => "least_connections",
+            Algorithm::WeightedLeastConnections => "weighted_least_connections",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "roundrobin" | "round_robin" => Some(Algorithm::RoundRobin),
+            "weightedroundrobin" | "weighted_round_robin" => Some(Algorithm::WeightedRoundRobin),
+            "iphashing" | "ip_hashing" => Some(Algorithm::IpHashing),
+            "leastconnections" | "least_connections" => Some(Algorithm::LeastConnections),
+            "weightedleastconnections" | "weighted_least_connections" => {
+                Some(Algorithm::WeightedLeastConnections)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Named for LayerMode {
+    fn name(&self) -> &'static str {
+        match self {
+            LayerMode::L4 => "L4",
+            LayerMode::L7 => "L7",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "l4" | "layer4" | "layer_4" => Some(LayerMode::L4),
+            "l7" | "layer7" | "layer_7" => Some(LayerMode::L7),
+            _ => None,
+        }
+    }
+}
+
+impl Named for Protocol {
+    fn name(&self) -> &'static str {
+        match self {
+            Protocol::Http1 => "http1",
+            Protocol::H2c => "h2c",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "http1" | "http/1.1" => Some(Protocol::Http1),
+            "h2c" | "http2" | "grpc" => Some(Protocol::H2c),
+            _ => None,
+        }
+    }
+}
+
+impl Named for ProbeKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ProbeKind::Tcp => "tcp",
+            ProbeKind::Http => "http",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "tcp" => Some(ProbeKind::Tcp),
+            "http" => Some(ProbeKind::Http),
+            _ => None,
+        }
+    }
+}
+
+impl Named for RouteMatching {
+    fn name(&self) -> &'static str {
+        match self {
+            RouteMatching::FirstMatch => "first_match",
+            RouteMatching::LongestPrefix => "longest_prefix",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "firstmatch" | "first_match" => Some(RouteMatching::FirstMatch),
+            "longestprefix" | "longest_prefix" => Some(RouteMatching::LongestPrefix),
+            _ => None,
+        }
+    }
+}
+
+impl Named for ForwardedHeaders {
+    fn name(&self) -> &'static str {
+        match self {
+            ForwardedHeaders::All => "all",
+            ForwardedHeaders::Forwarded => "forwarded",
+            ForwardedHeaders::XForwarded => "x_forwarded",
+            ForwardedHeaders::None => "none",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "all" => Some(ForwardedHeaders::All),
+            "forwarded" => Some(ForwardedHeaders::Forwarded),
+            "xforwarded" | "x_forwarded" => Some(ForwardedHeaders::XForwarded),
+            "none" => Some(ForwardedHeaders::None),
+            _ => None,
+        }
+    }
+}
+
+//implements Serialize and Deserialize for Named enums
+macro_rules! impl_serde_named {
+    ($($t:ty),*) => {
+        $(
+            impl Serialize for $t {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_str(self.name())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let name = String::deserialize(deserializer)?;
+                    <$t>::from_name(&name).ok_or_else(|| {
+                        de::Error::custom(format!("unknown value \"{}\"", name))
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_named!(
+    Algorithm,
+    LayerMode,
+    Protocol,
+    ProbeKind,
+    RouteMatching,
+    ForwardedHeaders
+);
+
+//returns true if value is false, to skip serializing it
+fn is_false(value: &bool) -> bool {
+    !*value
+}
@@ -0,0 +1,276 @@
+//! Active health checking of backend servers.
+//!
+//! This module periodically probes every backend server, either with a TCP
+//! connect or an HTTP request, and marks servers alive or dead once enough
+//! consecutive probes agree, so the load balancing algorithms stop sending
+//! traffic to dead servers.
+
+use http::header::{HOST, USER_AGENT};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::{Method, Request, StatusCode};
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use crate::config::config::SyncConfig;
+use crate::server::server::{Server, SyncServer};
+
+/// Health check settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthCheck {
+    pub enabled: bool,      //are health checks enabled?
+    pub interval: Duration, //time between health checks
+    pub timeout: Duration,  //time a single probe may take
+    pub rise: u32,          //consecutive successes to mark a server alive
+    pub fall: u32,          //consecutive failures to mark a server dead
+    pub probe: Probe,       //how servers are probed
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(1),
+            rise: 2,
+            fall: 3,
+            probe: Probe::Tcp,
+        }
+    }
+}
+
+/// Health check probe options
+#[derive(Clone, Debug, PartialEq)]
+pub enum Probe {
+    Tcp,             //server is healthy if a TCP connection can be established
+    Http(HttpProbe), //server is healthy if an HTTP request gets the expected response
+}
+
+/// HTTP health check probe settings
+#[derive(Clone, Debug)]
+pub struct HttpProbe {
+    pub method: Method,                            //request method
+    pub path: String,                              //request path
+    pub host: Option<String>,                      //Host header, server host if None
+    pub expected_status: Vec<RangeInclusive<u16>>, //status ranges counted as healthy
+    pub body_contains: Option<String>,             //substring the body must contain
+    pub body_regex: Option<Regex>,                 //regex the body must match
+}
+
+impl Default for HttpProbe {
+    fn default() -> Self {
+        Self {
+            method: Method::GET,
+            path: "/".to_owned(),
+            host: None,
+            expected_status: vec![200..=399],
+            body_contains: None,
+            body_regex: None,
+        }
+    }
+}
+
+impl PartialEq for HttpProbe {
+    //regexes are compared by their pattern
+    fn eq(&self, other: &Self) -> bool {
+        self.method == other.method
+            && self.path == other.path
+            && self.host == other.host
+            && self.expected_status == other.expected_status
+            && self.body_contains == other.body_contains
+            && self.body_regex.as_ref().map(Regex::as_str)
+                == other.body_regex.as_ref().map(Regex::as_str)
+    }
+}
+
+impl HttpProbe {
+    //returns true if status is one of the expected statuses
+    pub fn is_expected_status(&self, status: StatusCode) -> bool {
+        self.expected_status
+            .iter()
+            .any(|range| range.contains(&status.as_u16()))
+    }
+
+    //returns true if body contains the expected substring and matches the expected regex
+    pub fn is_expected_body(&self, body: &str) -> bool {
+        self.body_contains
+            .as_ref()
+            .is_none_or(|expected| body.contains(expected.as_str()))
+            && self
+                .body_regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(body))
+    }
+
+    //returns true if the body has to be read to judge the response
+    fn checks_body(&self) -> bool {
+        self.body_contains.is_some() || self.body_regex.is_some()
+    }
+}
+
+/// Background task probing the servers of the configs of one or more listeners
+pub struct HealthChecker {
+    configs: Vec<SyncConfig>,
+}
+
+impl HealthChecker {
+    //creates and returns a new HealthChecker of a single listener
+    pub fn new(config: SyncConfig) -> Self {
+        Self::for_listeners(vec![config])
+    }
+
+    //creates and returns a new HealthChecker of every listener
+    //servers of upstreams shared by listeners are probed once per round
+    pub fn for_listeners(configs: Vec<SyncConfig>) -> Self {
+        Self { configs }
+    }
+
+    //runs health checks every interval until shutdown is signalled
+    //settings are read again every round, so reloaded settings apply, including enabling checks
+    pub async fn run(self, mut shutdown_rx: tokio::sync::watch::Receiver<bool>) {
+        //the global settings are shared by all listeners
+        let Some(first) = self.configs.first() else {
+            return;
+        };
+        loop {
+            let settings = { first.lock().unwrap().health_check.clone() };
+            self.check_all(&settings).await;
+
+            tokio::select! {
+                // Check if shutdown signal is received
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        break;
+                    }
+                }
+                // Wait for next round of health checks
+                _ = tokio::time::sleep(settings.interval) => {}
+            }
+        }
+    }
+
+    //probes the servers of all upstreams concurrently and records the results
+    async fn check_all(&self, settings: &HealthCheck) {
+        let mut servers = Vec::<SyncServer>::new();
+        for config in self.configs.iter() {
+            let config = config.lock().unwrap();
+            for server in config
+                .upstreams
+                .iter()
+                .flat_map(|upstream| upstream.servers.iter())
+            {
+                if !servers.iter().any(|other| Arc::ptr_eq(other, server)) {
+                    servers.push(server.clone());
+                }
+            }
+        }
+
+        let mut probes = JoinSet::new();
+        for server in servers.iter() {
+            let server = server.clone();
+            //server settings override the settings of its upstream and the global settings
+            let settings = {
+                let server = server.lock().unwrap();
+                server.health_check().unwrap_or(settings).clone()
+            };
+            if !settings.enabled {
+                continue;
+            }
+            probes.spawn(async move {
+                let healthy = probe(&server, &settings).await;
+                (server, healthy, settings)
+            });
+        }
+
+        while let Some(result) = probes.join_next().await {
+            let Ok((server, healthy, settings)) = result else {
+                continue;
+            };
+            let (changed, host, port) = {
+                let mut server = server.lock().unwrap();
+                let changed = server.record_health_check(healthy, settings.rise, settings.fall);
+                (changed, server.host().to_owned(), server.port())
+            };
+            if !changed {
+                continue;
+            }
+            if healthy {
+                println!("Server {}:{} is back up", host, port);
+                //wake connections waiting for a server
+                for config in self.configs.iter() {
+                    let released = { config.lock().unwrap().queue.released() };
+                    released.notify_waiters();
+                }
+            } else {
+                eprintln!("Server {}:{} is down", host, port);
+            }
+        }
+    }
+}
+
+//probes a server with the configured probe
+//returns true if the probe succeeded within the timeout
+async fn probe(server: &SyncServer, settings: &HealthCheck) -> bool {
+    let (host, port) = {
+        let server = server.lock().unwrap();
+        (server.host().to_owned(), server.port())
+    };
+    let result = match &settings.probe {
+        Probe::Tcp => timeout(settings.timeout, probe_tcp(&host, port)).await,
+        Probe::Http(http_probe) => {
+            timeout(settings.timeout, probe_http(server, &host, http_probe)).await
+        }
+    };
+    matches!(result, Ok(true))
+}
+
+//probes a server with a TCP connect
+//returns true if the connection was established
+async fn probe_tcp(host: &str, port: u16) -> bool {
+    TcpStream::connect((host, port)).await.is_ok()
+}
+
+//probes a server with an HTTP request
+//returns true if the response has an expected status and body
+async fn probe_http(server: &SyncServer, host: &str, http_probe: &HttpProbe) -> bool {
+    //build the probe request
+    let Ok(req) = Request::builder()
+        .method(http_probe.method.clone())
+        .uri(http_probe.path.as_str())
+        .header(HOST, http_probe.host.as_deref().unwrap_or(host))
+        .header(USER_AGENT, "deston-health-check")
+        .body(
+            Empty::<Bytes>::new()
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+    else {
+        return false;
+    };
+
+    //send the request over the same client as forwarded requests
+    let protocol = { server.lock().unwrap().protocol() };
+    let Ok(stream) = Server::connect(server).await else {
+        return false;
+    };
+    let Ok(resp) = Server::send_request(stream, protocol, req).await else {
+        return false;
+    };
+    if !http_probe.is_expected_status(resp.status()) {
+        return false;
+    }
+    if !http_probe.checks_body() {
+        return true;
+    }
+
+    //read the body and match it
+    let Ok(body) = resp.into_body().collect().await else {
+        return false;
+    };
+    http_probe.is_expected_body(&String::from_utf8_lossy(&body.to_bytes()))
+}
@@ -0,0 +1,3 @@
+#[allow(clippy::module_inception)]
+pub mod health_check;
+pub mod outlier_detection;
@@ -0,0 +1,127 @@
+//! Passive health checking of backend servers.
+//!
+//! This module watches the results of live traffic and ejects servers that fail
+//! too often, either after a number of consecutive failures or when their
+//! failure rate crosses a threshold. Ejections last exponentially longer for
+//! servers that keep failing, and never eject more than a share of the servers.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::config::SyncConfig;
+use crate::server::server::{Server, SyncServer};
+
+/// Outlier detection settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutlierDetection {
+    pub enabled: bool,             //is outlier detection enabled?
+    pub consecutive_failures: u32, //consecutive failures to eject a server
+    pub failure_rate_percent: u32, //failure rate to eject a server, 0 disables it
+    pub window_size: usize,        //latest results the failure rate is computed from
+    pub base_ejection: Duration,   //duration of the first ejection
+    pub max_ejection: Duration,    //max duration of an ejection
+    pub max_ejection_percent: u32, //max share of servers ejected at once
+}
+
+impl Default for OutlierDetection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            consecutive_failures: 5,
+            failure_rate_percent: 0,
+            window_size: 20,
+            base_ejection: Duration::from_secs(30),
+            max_ejection: Duration::from_secs(300),
+            max_ejection_percent: 50,
+        }
+    }
+}
+
+impl OutlierDetection {
+    //returns how long a server ejected ejections times before is ejected now
+    //the duration doubles with every ejection up to max_ejection
+    pub fn ejection_duration(&self, ejections: u32) -> Duration {
+        self.base_ejection
+            .saturating_mul(2u32.saturating_pow(ejections))
+            .min(self.max_ejection)
+    }
+}
+
+/// Feeds results of live traffic into outlier detection
+pub struct OutlierDetector;
+
+impl OutlierDetector {
+    //records the result of a connection to server and ejects it if it is an outlier
+    pub fn report(config: &SyncConfig, server: &SyncServer, success: bool) {
+        //lock config before servers, like picking a server does
+        let config = config.lock().unwrap();
+        let settings = &config.outlier_detection;
+
+        //count servers of its upstream already ejected
+        let servers = config
+            .upstreams
+            .iter()
+            .find(|upstream| upstream.contains(server))
+            .map(|upstream| upstream.servers.clone())
+            .unwrap_or_default();
+        let ejected = servers
+            .iter()
+            .filter(|other| !Arc::ptr_eq(other, server) && other.lock().unwrap().is_ejected())
+            .count();
+        let total = servers.len();
+
+        let mut server = server.lock().unwrap();
+        server.record_result(success, settings.window_size);
+        if !settings.enabled {
+            return;
+        }
+
+        if success {
+            //forget past ejections once server stayed healthy for max_ejection
+            if let Some(ejected_until) = server.ejected_until() {
+                if Instant::now() > ejected_until + settings.max_ejection {
+                    server.reset_ejections();
+                }
+            }
+            return;
+        }
+
+        if server.is_ejected() || !is_outlier(&server, settings) {
+            return;
+        }
+        //never eject more than max_ejection_percent of servers
+        if (ejected + 1) * 100 > settings.max_ejection_percent as usize * total {
+            return;
+        }
+        let duration = settings.ejection_duration(server.ejections());
+        server.eject(duration);
+        //wake connections waiting for a server once the ejection expires
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let released = config.queue.released();
+            runtime.spawn(async move {
+                tokio::time::sleep(duration).await;
+                released.notify_waiters();
+            });
+        }
+        eprintln!(
+            "Server {}:{} ejected for {:?} by outlier detection",
+            server.host(),
+            server.port(),
+            duration
+        );
+    }
+}
+
+//returns true if server failed too often
+fn is_outlier(server: &Server, settings: &OutlierDetection) -> bool {
+    if server.consecutive_failures() >= settings.consecutive_failures {
+        return true;
+    }
+    let results = server.recent_results();
+    if settings.failure_rate_percent == 0 || results.len() < settings.window_size {
+        return false;
+    }
+    let failures = results.iter().filter(|success| !**success).count();
+    failures * 100 >= settings.failure_rate_percent as usize * results.len()
+}
@@ -6,9 +6,12 @@
 //!
 //! ## Modules
 //!
+//! - `cli`: Command-line interface
 //! - `config`: Configuration parsing and management
+//! - `health_check`: Active health checking of backend servers
 //! - `load_balancer`: Load balancer trait and implementations (Layer 4 and Layer 7)
 //! - `server`: Backend server management and request handling
+//! - `tls`: TLS termination and TLS to backend servers
 //!
 //! ## Example
 //!
@@ -21,16 +24,25 @@
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     let config = Config::new(Path::new("config.toml"));
+//!     let config = match Config::load(Path::new("config.toml")) {
+//!         Ok(config) => config,
+//!         Err(err) => {
+//!             eprintln!("{}", err);
+//!             return;
+//!         }
+//!     };
 //!     let lb = Layer4::new(Arc::new(Mutex::new(config)));
 //!     let (_, shutdown_rx) = tokio::sync::watch::channel(false);
 //!     let _ = lb.start(shutdown_rx).await;
 //! }
 //! ```
 
+pub mod cli;
 pub mod config;
+pub mod health_check;
 pub mod load_balancer;
 pub mod server;
+pub mod tls;
 
 // Re-export Arc for convenience
 pub use std::sync::Arc;
@@ -0,0 +1,57 @@
+//! Least Connections load balancing algorithm.
+//!
+//! Sends each request to the server with the fewest alive connections, so
+//! long-lived sessions do not pile up on a single server.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::load_balancer::algorithm::algorithm::Algorithm;
+use crate::server::server::SyncServer;
+
+/// Least Connections algorithm implementation
+pub struct LeastConnections {
+    index: usize,
+}
+
+impl Algorithm for LeastConnections {
+    //creates and returns new LeastConnections
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self { index: 0 }
+    }
+
+    //picks next server
+    //scans servers starting at index and returns the one with least connections
+    //servers that can not accept a connection are skipped
+    //ties are broken by scan order, so idle servers are picked in turn
+    fn pick_server(
+        &mut self,
+        servers: Arc<Vec<SyncServer>>,
+        _: SocketAddr,
+    ) -> Option<(usize, SyncServer)> {
+        let mut picked: Option<(usize, u32)> = None;
+        for offset in 0..servers.len() {
+            let index = (self.index + offset) % servers.len();
+            //get connections of server, skipping servers that can not accept a connection
+            let connections = {
+                let server = servers[index].lock().unwrap();
+                if !server.is_available() {
+                    continue;
+                }
+                server.connections()
+            };
+            match picked {
+                Some((_, least)) if connections >= least => {}
+                _ => picked = Some((index, connections)),
+            }
+        }
+        let (index, _) = picked?;
+        //start next scan after picked server
+        self.index = (index + 1) % servers.len();
+        //return index and server
+        Some((index, servers[index].clone()))
+    }
+}
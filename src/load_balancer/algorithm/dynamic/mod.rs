@@ -0,0 +1,2 @@
+pub mod least_connections;
+pub mod weighted_least_connections;
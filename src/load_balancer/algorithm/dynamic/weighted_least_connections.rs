@@ -0,0 +1,61 @@
+//! Weighted Least Connections load balancing algorithm.
+//!
+//! Sends each request to the server with the lowest ratio of alive connections
+//! to weight. Servers with higher weights are expected to hold proportionally
+//! more connections.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::load_balancer::algorithm::algorithm::Algorithm;
+use crate::server::server::SyncServer;
+
+/// Weighted Least Connections algorithm implementation
+pub struct WeightedLeastConnections {
+    index: usize,
+}
+
+impl Algorithm for WeightedLeastConnections {
+    //creates and returns new WeightedLeastConnections
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self { index: 0 }
+    }
+
+    //picks next server
+    //scans servers starting at index and returns the one with least connections per weight
+    //servers with weight 0 or that can not accept a connection are never picked
+    fn pick_server(
+        &mut self,
+        servers: Arc<Vec<SyncServer>>,
+        _: SocketAddr,
+    ) -> Option<(usize, SyncServer)> {
+        let mut picked: Option<(usize, u64, u64)> = None;
+        for offset in 0..servers.len() {
+            let index = (self.index + offset) % servers.len();
+            //get connections and weight of server, skipping servers that can not accept a connection
+            let (connections, weight) = {
+                let server = servers[index].lock().unwrap();
+                if !server.is_available() {
+                    continue;
+                }
+                (server.connections() as u64, server.weight as u64)
+            };
+            if weight == 0 {
+                continue;
+            }
+            //compare connections / weight without dividing
+            match picked {
+                Some((_, least, least_weight)) if connections * least_weight >= least * weight => {}
+                _ => picked = Some((index, connections, weight)),
+            }
+        }
+        let (index, _, _) = picked?;
+        //start next scan after picked server
+        self.index = (index + 1) % servers.len();
+        //return index and server
+        Some((index, servers[index].clone()))
+    }
+}
@@ -1,3 +1,4 @@
 #[allow(clippy::module_inception)]
 pub mod algorithm;
+pub mod dynamic;
 pub mod r#static;
@@ -24,19 +24,27 @@ impl Algorithm for IpHashing {
 
     //picks next server
     //hashes client ip address, picks and returns resultig server
+    //if that server can not accept a connection, the next available server is picked
     fn pick_server(
         &mut self,
         servers: Arc<Vec<SyncServer>>,
         client_addr: SocketAddr,
     ) -> Option<(usize, SyncServer)> {
+        if servers.is_empty() {
+            return None;
+        }
         //create hasher
         let mut hasher = Sha256::new();
         //hash client_addr
         hasher.update(client_addr.to_string().as_bytes());
         let result = hasher.finalize();
         //get index from result
-        let index = (usize::from_be_bytes(result[0..8].try_into().unwrap())) % servers.len();
-        //return index and server
-        Some((index, servers[index].clone()))
+        let hashed_index = (usize::from_be_bytes(result[0..8].try_into().unwrap())) % servers.len();
+        //probe from hashed index for an available server
+        (0..servers.len())
+            .map(|offset| (hashed_index + offset) % servers.len())
+            .find(|&index| servers[index].lock().unwrap().is_available())
+            //return index and server
+            .map(|index| (index, servers[index].clone()))
     }
 }
@@ -23,18 +23,23 @@ impl Algorithm for RoundRobin {
     }
 
     //picks next server
-    //picks server at index, increments index and returns the index and server
+    //picks first available server from index, moves index past it and returns the index and server
     fn pick_server(
         &mut self,
         servers: Arc<Vec<SyncServer>>,
         _: SocketAddr,
     ) -> Option<(usize, SyncServer)> {
-        //pick server
-        let server = servers[self.index].clone();
-        let index = self.index;
-        //increment index
-        self.index = (self.index + 1) % servers.len();
-        //return index and server
-        Some((index, server))
+        for offset in 0..servers.len() {
+            let index = (self.index + offset) % servers.len();
+            //skip servers that can not accept a connection
+            if !servers[index].lock().unwrap().is_available() {
+                continue;
+            }
+            //increment index
+            self.index = (index + 1) % servers.len();
+            //return index and server
+            return Some((index, servers[index].clone()));
+        }
+        None
     }
 }
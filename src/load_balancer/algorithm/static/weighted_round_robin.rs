@@ -26,18 +26,28 @@ impl Algorithm for WeightedRoundRobin {
 
     //picks next server
     //picks server at index wtr to weight, increments index and returns the index and server
+    //servers that can not accept a connection are skipped
     fn pick_server(
         &mut self,
         servers: Arc<Vec<SyncServer>>,
         _: SocketAddr,
     ) -> Option<(usize, SyncServer)> {
-        loop {
+        if servers.is_empty() {
+            return None;
+        }
+        //keep index in range if the server list shrank
+        self.index %= servers.len();
+        //every server is visited at most once after the current one
+        for _ in 0..=servers.len() {
             //get server
             let server = &servers[self.index];
-            //get weight of server
-            let server_weight = { server.lock().unwrap().weight };
+            //get weight and availability of server
+            let (server_weight, available) = {
+                let server = server.lock().unwrap();
+                (server.weight, server.is_available())
+            };
 
-            if self.curr_weight < server_weight {
+            if available && self.curr_weight < server_weight {
                 self.curr_weight += 1;
                 return Some((self.index, server.clone()));
             }
@@ -45,5 +55,6 @@ impl Algorithm for WeightedRoundRobin {
             self.curr_weight = 0;
             self.index = (self.index + 1) % servers.len();
         }
+        None
     }
 }
@@ -3,11 +3,15 @@
 //! This module provides a Layer 4 load balancer that operates at the transport layer,
 //! forwarding raw TCP connections between clients and backend servers.
 
-use tokio::net::TcpListener;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
 
 use crate::config::config::SyncConfig;
-use crate::load_balancer::load_balancer;
-use crate::server::server::Server;
+use crate::health_check::outlier_detection::OutlierDetector;
+use crate::load_balancer::load_balancer::{self, LoadBalancer};
+use crate::server::server::{Server, SyncServer};
+use crate::tls::client_hello::{matches_server_name, read_client_hello};
 
 /// Layer 4 (TCP) Load Balancer
 pub struct Layer4 {
@@ -33,8 +37,9 @@ impl load_balancer::LoadBalancer for Layer4 {
             let config = self.config.lock().unwrap();
             config.load_balancer_address.clone()
         };
-        let host = lb_address.host().unwrap();
-        let port = lb_address.port_u16().unwrap();
+        let (Some(host), Some(port)) = (lb_address.host(), lb_address.port_u16()) else {
+            return Err(format!("invalid listener address {}", lb_address).into());
+        };
 
         //create a TcpListener and binds it to load balancer address
         let listener = TcpListener::bind((host, port)).await?;
@@ -59,16 +64,7 @@ impl load_balancer::LoadBalancer for Layer4 {
                             let config_clone = self.config.clone();
 
                             //spawn a tokio task to server multiple connections concurrently
-                            tokio::task::spawn(async move {
-                                //pick a server
-                                let server = Self::pick_server(config_clone, addr)
-                                    .await
-                                    .expect("No server");
-                                //call Server::transfer_data to transfer data between server and client
-                                if let Err(err) = Server::transfer_data(server, stream).await {
-                                    eprintln!("Error transferring data {:?}", err);
-                                }
-                            });
+                            tokio::task::spawn(Self::serve_connection(config_clone, stream, addr));
                         }
                         Err(e) => {
                             eprintln!("Error accepting connection: {:?}", e);
@@ -81,3 +77,97 @@ impl load_balancer::LoadBalancer for Layer4 {
         Ok(())
     }
 }
+
+impl Layer4 {
+    //serves a single client connection
+    //with TLS passthrough, reads the ClientHello to route the connection by its SNI hostname
+    //calls connect_server to connect to a server and Server::transfer_data to transfer data between them
+    async fn serve_connection(config: SyncConfig, mut stream: TcpStream, addr: SocketAddr) {
+        let (tls_passthrough, client_hello_timeout, upstream) = {
+            let config = config.lock().unwrap();
+            (
+                config.tls_passthrough,
+                config.client_hello_timeout,
+                config.upstream.clone().unwrap_or_default(),
+            )
+        };
+
+        //read the ClientHello, its bytes are replayed to the server
+        let (initial_data, excluded) = if tls_passthrough {
+            match timeout(client_hello_timeout, read_client_hello(&mut stream)).await {
+                Ok(Ok((initial_data, server_name))) => {
+                    let excluded = Self::excluded_servers(&config, server_name.as_deref());
+                    (initial_data, excluded)
+                }
+                Ok(Err(err)) => {
+                    eprintln!("Error reading ClientHello from {}: {:?}", addr, err);
+                    return;
+                }
+                Err(_) => {
+                    eprintln!("No ClientHello from {} in time, closing connection", addr);
+                    return;
+                }
+            }
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        //pick a server and connect to it, the client stream is closed if that fails
+        let (connection, server_stream) =
+            match Self::connect_server(config.clone(), &upstream, addr, excluded).await {
+                Ok(connected) => connected,
+                Err(err) => {
+                    eprintln!("{} for {}, closing connection", err, addr);
+                    return;
+                }
+            };
+        //a connected stream is the success of a TCP connection
+        OutlierDetector::report(&config, connection.server(), true);
+
+        //call Server::transfer_data to transfer data between server and client
+        if let Err(err) =
+            Server::transfer_data(connection, stream, server_stream, initial_data).await
+        {
+            eprintln!("Error transferring data {:?}", err);
+        }
+    }
+
+    //returns the servers a connection for server_name must not be routed to
+    //servers naming server_name exactly come first, then servers matching it through a wildcard,
+    //then servers without server_names, which also get connections without SNI
+    fn excluded_servers(config: &SyncConfig, server_name: Option<&str>) -> Vec<SyncServer> {
+        let servers = { config.lock().unwrap().servers() };
+        //rank of every server, lower is better, None if it does not serve server_name
+        let ranks: Vec<Option<u8>> = servers
+            .iter()
+            .map(|server| {
+                let server = server.lock().unwrap();
+                let names = server.server_names();
+                if names.is_empty() {
+                    return Some(2);
+                }
+                let server_name = server_name?;
+                if names
+                    .iter()
+                    .any(|name| !name.starts_with("*.") && matches_server_name(name, server_name))
+                {
+                    Some(0)
+                } else if names
+                    .iter()
+                    .any(|name| matches_server_name(name, server_name))
+                {
+                    Some(1)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let best = ranks.iter().flatten().min().copied();
+        servers
+            .iter()
+            .zip(ranks)
+            .filter(|(_, rank)| best.is_none() || *rank != best)
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+}
@@ -2,15 +2,30 @@
 //!
 //! This module provides a Layer 7 load balancer that operates at the application layer,
 //! forwarding HTTP requests with the ability to inspect and modify headers.
+//! Connections may optionally be TLS terminated.
 
-use hyper::server::conn::http1;
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Bytes, Incoming};
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
-use tokio::net::TcpListener;
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
 
 use crate::config::config::SyncConfig;
+use crate::health_check::outlier_detection::OutlierDetector;
 use crate::load_balancer::load_balancer::LoadBalancer;
-use crate::server::server::Server;
+use crate::server::error::ProxyError;
+use crate::server::headers::{next_request_id, RequestContext};
+use crate::server::pool::PooledConnection;
+use crate::server::server::{ClientInfo, Server};
+use crate::tls::client_cert::ClientCert;
 
 /// Layer 7 (HTTP) Load Balancer
 #[allow(dead_code)]
@@ -26,24 +41,40 @@ impl LoadBalancer for Layer7 {
 
     //starts layer 7 load balancer
     //will listen to incoming requests at given address
-    //calls pick_server to pick a server when user sends a request
+    //routes every request to an upstream and calls pick_server to pick a server of it
     //calls Server::handle_request to forward request to the server
     async fn start(
         &self,
         mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        //load balancer address from config
-        let lb_address = {
+        //load balancer address and TLS settings from config
+        let (lb_address, tls, cert_resolver) = {
             let config = self.config.lock().unwrap();
-            config.load_balancer_address.clone()
+            (
+                config.load_balancer_address.clone(),
+                config.tls.clone(),
+                config.cert_resolver.clone(),
+            )
+        };
+        let (Some(host), Some(port)) = (lb_address.host(), lb_address.port_u16()) else {
+            return Err(format!("invalid listener address {}", lb_address).into());
+        };
+
+        //load certificates before accepting connections
+        let tls_acceptor = match tls {
+            Some(tls) => Some(tls.acceptor(cert_resolver)?),
+            None => None,
         };
-        let host = lb_address.host().unwrap();
-        let port = lb_address.port_u16().unwrap();
 
         //create a TcpListener and binds it to load balancer address
         let listener = TcpListener::bind((host, port)).await?;
 
-        println!("Layer 7 Load Balancer listening on {}:{}", host, port);
+        println!(
+            "Layer 7 Load Balancer listening on {}:{}{}",
+            host,
+            port,
+            if tls_acceptor.is_some() { " (TLS)" } else { "" }
+        );
 
         //loop to continuously accept incoming connections
         loop {
@@ -59,38 +90,16 @@ impl LoadBalancer for Layer7 {
                 result = listener.accept() => {
                     match result {
                         Ok((stream, addr)) => {
-                            let io = TokioIo::new(stream);
-
                             //clone the server list to safely share across multiple threads
                             let config_clone = self.config.clone();
 
                             //spawn a tokio task to server multiple connections concurrently
-                            tokio::task::spawn(async move {
-                                if let Err(err) = http1::Builder::new()
-                                    .preserve_header_case(true)
-                                    .title_case_headers(true)
-                                    //bind the incoming connection to handle_request
-                                    .serve_connection(
-                                        io,
-                                        service_fn(move |req| {
-                                            //clone the server list to safely share across multiple threads
-                                            let config_clone = config_clone.clone();
-                                            async move {
-                                                //pick a server
-                                                let config_clone = config_clone.clone();
-                                                let server = Self::pick_server(config_clone, addr)
-                                                    .await
-                                                    .expect("No server");
-                                                //call Server::handle_request to forward the request to server
-                                                Server::handle_request(server, req, addr).await
-                                            }
-                                        }),
-                                    )
-                                    .await
-                                {
-                                    eprintln!("Error serving connection: {:?}", err);
-                                }
-                            });
+                            tokio::task::spawn(Self::serve_connection(
+                                config_clone,
+                                stream,
+                                addr,
+                                tls_acceptor.clone(),
+                            ));
                         }
                         Err(e) => {
                             eprintln!("Error accepting connection: {:?}", e);
@@ -103,3 +112,175 @@ impl LoadBalancer for Layer7 {
         Ok(())
     }
 }
+
+impl Layer7 {
+    //serves a single client connection
+    //terminates TLS first if tls_acceptor is set
+    async fn serve_connection(
+        config: SyncConfig,
+        stream: TcpStream,
+        addr: SocketAddr,
+        tls_acceptor: Option<TlsAcceptor>,
+    ) {
+        //the address the client connected to, sent as by= in Forwarded
+        let local_addr = match stream.local_addr() {
+            Ok(local_addr) => local_addr,
+            Err(err) => {
+                eprintln!("Error getting local address of {}: {}", addr, err);
+                return;
+            }
+        };
+        //clients not finishing the handshake in time are closed, like clients not sending a ClientHello in L4
+        let handshake_timeout = { config.lock().unwrap().client_hello_timeout };
+        match tls_acceptor {
+            Some(tls_acceptor) => {
+                match timeout(handshake_timeout, tls_acceptor.accept(stream)).await {
+                    Ok(Ok(tls_stream)) => {
+                        //the certificate was verified during the handshake if client_ca is set
+                        let cert = tls_stream
+                            .get_ref()
+                            .1
+                            .peer_certificates()
+                            .and_then(|certs| certs.first())
+                            .and_then(ClientCert::from_der)
+                            .map(Arc::new);
+                        let client = ClientInfo {
+                            addr,
+                            local_addr,
+                            proto: "https",
+                            cert,
+                        };
+                        Self::serve_http(config, tls_stream, client).await;
+                    }
+                    Ok(Err(err)) => {
+                        eprintln!("TLS handshake with {} failed: {}", addr, err);
+                    }
+                    Err(_) => {
+                        eprintln!("No TLS handshake from {} in time, closing connection", addr);
+                    }
+                }
+            }
+            None => {
+                let client = ClientInfo {
+                    addr,
+                    local_addr,
+                    proto: "http",
+                    cert: None,
+                };
+                Self::serve_http(config, stream, client).await;
+            }
+        }
+    }
+
+    //serves HTTP/1.1 and HTTP/2 with prior knowledge (h2c) on a client connection
+    //calls serve_request for every request
+    async fn serve_http<I>(config: SyncConfig, io: I, client: ClientInfo)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .preserve_header_case(true)
+            .title_case_headers(true);
+        if let Err(err) = builder
+            //bind the incoming connection to serve_request
+            .serve_connection(
+                TokioIo::new(io),
+                service_fn(move |req| Self::serve_request(config.clone(), req, client.clone())),
+            )
+            .await
+        {
+            eprintln!("Error serving connection: {:?}", err);
+        }
+    }
+
+    //serves a single request
+    //responds with an error response if the request could not be forwarded
+    async fn serve_request(
+        config: SyncConfig,
+        req: Request<Incoming>,
+        client: ClientInfo,
+    ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Infallible> {
+        match Self::proxy_request(config.clone(), req, &client).await {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                eprintln!(
+                    "Responding {} to {}: {}",
+                    err.status().as_u16(),
+                    client.addr,
+                    err
+                );
+                let config = config.lock().unwrap();
+                Ok(err.to_response(&config.error_body, &config.error_content_type))
+            }
+        }
+    }
+
+    //forwards a single request
+    //calls connect_server_with to get a pooled connection to a server and Server::handle_request to forward the request to it
+    async fn proxy_request(
+        config: SyncConfig,
+        req: Request<Incoming>,
+        client: &ClientInfo,
+    ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, ProxyError> {
+        //route the request to an upstream, the upstream of the listener if no route matches
+        let (pool, forwarding, route) = {
+            let config = config.lock().unwrap();
+            let route = match config.router.route(&req) {
+                Some(route) => Some((route.upstream.clone(), route.rewrite.clone())),
+                None => config
+                    .upstream
+                    .clone()
+                    .map(|upstream| (upstream, Default::default())),
+            };
+            (
+                config.connection_pool.clone(),
+                config.forwarding.clone(),
+                route,
+            )
+        };
+        let (upstream_name, rewrite) = route.ok_or(ProxyError::NoRoute)?;
+        let context = RequestContext {
+            id: next_request_id(),
+            upstream: upstream_name,
+            rewrite,
+            forwarding,
+        };
+
+        //pick a server of the upstream and reuse or open a connection to it
+        let (connection, upstream) = Self::connect_server_with(
+            config.clone(),
+            &context.upstream,
+            client.addr,
+            Vec::new(),
+            |server| PooledConnection::checkout(server, pool.clone()),
+        )
+        .await?;
+
+        //call Server::handle_request to forward the request to server within upstream_timeout
+        //report the result to outlier detection, 5xx counts as failure
+        let upstream_timeout = { config.lock().unwrap().upstream_timeout };
+        let server = connection.server().clone();
+        let result = match timeout(
+            upstream_timeout,
+            Server::handle_request(connection, upstream, req, client, &context),
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => Err(ProxyError::Timeout),
+        };
+        match &result {
+            Ok(response) => {
+                let success = !response.status().is_server_error();
+                OutlierDetector::report(&config, &server, success);
+            }
+            Err(err) if err.is_server_failure() => {
+                OutlierDetector::report(&config, &server, false);
+            }
+            Err(_) => {}
+        }
+        result
+    }
+}
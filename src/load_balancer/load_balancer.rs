@@ -3,10 +3,33 @@
 //! This module defines the core LoadBalancer trait and provides implementations
 //! for Layer 4 (TCP) and Layer 7 (HTTP) load balancing.
 
+use std::fmt;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use crate::config::config::SyncConfig;
-use crate::server::server::SyncServer;
+use crate::health_check::outlier_detection::OutlierDetector;
+use crate::server::server::{ConnectionGuard, Server, SyncServer};
+use crate::server::stream::ServerStream;
+
+/// Errors while picking and connecting to a server
+#[derive(Debug)]
+pub enum ConnectError {
+    NoServer,                //no server available
+    Connect(std::io::Error), //connecting to the last tried server failed
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::NoServer => write!(f, "no server available"),
+            ConnectError::Connect(err) => write!(f, "failed to connect to server: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
 
 /// LoadBalancer trait defining the interface for load balancer implementations
 #[allow(async_fn_in_trait)]
@@ -23,22 +46,157 @@ pub trait LoadBalancer {
         shutdown_rx: tokio::sync::watch::Receiver<bool>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
-    /// Picks a server based on the configured algorithm to handle an incoming request
+    /// Picks a server of upstream and connects to it, failing over to other servers on connect errors
+    ///
+    /// Tries at most `max_connect_attempts` different servers and reports every
+    /// failed attempt to outlier detection, the caller reports the result of the
+    /// connection it got once it is known. Servers in `excluded` are never picked.
+    /// Returns the connection reserved on the server and the connected stream,
+    /// encrypted if the server uses TLS
+    async fn connect_server(
+        config: SyncConfig,
+        upstream: &str,
+        client_addr: SocketAddr,
+        excluded: Vec<SyncServer>,
+    ) -> Result<(ConnectionGuard, ServerStream), ConnectError> {
+        Self::connect_server_with(
+            config,
+            upstream,
+            client_addr,
+            excluded,
+            |server| async move { Server::connect(&server).await },
+        )
+        .await
+    }
+
+    /// Picks a server of upstream and connects to it using connect, failing over to other servers on connect errors
+    ///
+    /// Same as `connect_server`, but lets the caller decide how to connect,
+    /// e.g. by reusing a pooled connection.
+    /// Returns the connection reserved on the server and the value returned by connect
+    async fn connect_server_with<T, F, Fut>(
+        config: SyncConfig,
+        upstream: &str,
+        client_addr: SocketAddr,
+        excluded: Vec<SyncServer>,
+        connect: F,
+    ) -> Result<(ConnectionGuard, T), ConnectError>
+    where
+        F: Fn(SyncServer) -> Fut,
+        Fut: Future<Output = std::io::Result<T>>,
+    {
+        let max_attempts = { config.lock().unwrap().max_connect_attempts.max(1) };
+        //excluded servers are skipped like servers that were tried already
+        let mut tried = excluded;
+        let mut last_error = None;
+
+        for _ in 0..max_attempts {
+            //pick a server that was not tried yet
+            let Some(connection) =
+                Self::pick_server(config.clone(), upstream, client_addr, &tried).await
+            else {
+                break;
+            };
+            let server = connection.server().clone();
+
+            //connect to the server and report failures to outlier detection
+            match connect(server.clone()).await {
+                Ok(stream) => return Ok((connection, stream)),
+                Err(err) => {
+                    OutlierDetector::report(&config, &server, false);
+                    let server_locked = server.lock().unwrap();
+                    eprintln!(
+                        "Error connecting to server {}:{} {:?}",
+                        server_locked.host(),
+                        server_locked.port(),
+                        err
+                    );
+                    last_error = Some(err);
+                }
+            }
+            tried.push(server);
+        }
+
+        Err(last_error.map_or(ConnectError::NoServer, ConnectError::Connect))
+    }
+
+    /// Picks a server of upstream based on its algorithm to handle an incoming request
+    ///
+    /// Servers in `tried` are never picked.
+    /// If every server is saturated, waits in the queue until a connection is released.
+    /// Returns Some(connection) reserved on the picked server, None if no server
+    /// became available before the queue overflowed or timed out, or if the
+    /// upstream no longer exists
+    async fn pick_server(
+        config: SyncConfig,
+        upstream: &str,
+        client_addr: SocketAddr,
+        tried: &[SyncServer],
+    ) -> Option<ConnectionGuard> {
+        //pick a server right away if one is available
+        if let Some(connection) = Self::try_pick_server(&config, upstream, client_addr, tried) {
+            return Some(connection);
+        }
+        let queue = {
+            let config = config.lock().unwrap();
+            //no need to wait if every server was tried
+            if config
+                .find_upstream(upstream)?
+                .servers
+                .iter()
+                .all(|server| tried.iter().any(|tried| Arc::ptr_eq(server, tried)))
+            {
+                return None;
+            }
+            config.queue.clone()
+        };
+        //wait for a connection to be released
+        queue
+            .wait(|| Self::try_pick_server(&config, upstream, client_addr, tried))
+            .await
+    }
+
+    /// Picks a server of upstream based on its algorithm and reserves a connection to it
     ///
-    /// Returns Some(server) if a server is available, None otherwise
-    async fn pick_server(config: SyncConfig, client_addr: SocketAddr) -> Option<SyncServer> {
-        //lock config
+    /// Servers in `tried` are never picked.
+    /// Returns Some(connection) if a server is available, None otherwise
+    fn try_pick_server(
+        config: &SyncConfig,
+        upstream: &str,
+        client_addr: SocketAddr,
+        tried: &[SyncServer],
+    ) -> Option<ConnectionGuard> {
+        //lock config so picking and reserving a connection is atomic
         let mut config = config.lock().unwrap();
-        //get servers
-        let servers = config.servers.clone();
-        //call Algorithm::pick_server and return the server
-        let (index, server) = config
+        let released = config.queue.released();
+        let upstream = config
+            .upstreams
+            .iter_mut()
+            .find(|other| other.name == upstream)?;
+        //get servers that were not tried yet
+        let servers = if tried.is_empty() {
+            upstream.servers.clone()
+        } else {
+            Arc::new(
+                upstream
+                    .servers
+                    .iter()
+                    .filter(|server| !tried.iter().any(|tried| Arc::ptr_eq(server, tried)))
+                    .cloned()
+                    .collect(),
+            )
+        };
+        //call Algorithm::pick_server to get the server
+        let (_, server) = upstream
             .algorithm_object
-            .pick_server(servers, client_addr)
-            .unwrap();
+            .pick_server(servers, client_addr)?;
         //update index
-        config.last_picked_index = index;
-        //return picked server
-        Some(server)
+        upstream.last_picked_index = upstream
+            .servers
+            .iter()
+            .position(|other| Arc::ptr_eq(other, &server))
+            .unwrap_or_default();
+        //reserve a connection on the picked server
+        Some(ConnectionGuard::new(server).notify_on_release(released))
     }
 }
@@ -3,3 +3,6 @@ pub mod layer4;
 pub mod layer7;
 #[allow(clippy::module_inception)]
 pub mod load_balancer;
+pub mod queue;
+pub mod router;
+pub mod upstream;
@@ -0,0 +1,101 @@
+//! Wait queue for connections when every server is saturated.
+//!
+//! When all servers have reached their `max_connections`, incoming connections
+//! wait in a bounded queue until a connection is released or the timeout expires.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{timeout_at, Instant};
+
+/// Bounded queue of connections waiting for a server to become available
+pub struct WaitQueue {
+    capacity: usize,           //max number of waiting connections
+    timeout: Duration,         //max time a connection waits
+    waiting: Arc<AtomicUsize>, //number of waiting connections
+    released: Arc<Notify>,     //notified when a connection is released
+}
+
+impl WaitQueue {
+    //creates and returns a new WaitQueue
+    pub fn new(capacity: usize, timeout: Duration) -> Self {
+        Self::with_released(capacity, timeout, Arc::new(Notify::new()))
+    }
+
+    //creates and returns a new WaitQueue woken by released, which other queues may share
+    pub fn with_released(capacity: usize, timeout: Duration, released: Arc<Notify>) -> Self {
+        Self {
+            capacity,
+            timeout,
+            waiting: Arc::new(AtomicUsize::new(0)),
+            released,
+        }
+    }
+
+    //returns a new WaitQueue with other limits, woken by the same released connections
+    //connections waiting in this queue keep waiting in it and count towards the new capacity
+    pub fn resized(&self, capacity: usize, timeout: Duration) -> Self {
+        Self {
+            capacity,
+            timeout,
+            waiting: self.waiting.clone(),
+            released: self.released.clone(),
+        }
+    }
+
+    //returns max number of waiting connections
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    //returns max time a connection waits
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    //returns the notifier to signal when a connection is released
+    pub fn released(&self) -> Arc<Notify> {
+        self.released.clone()
+    }
+
+    //returns number of waiting connections
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    //calls try_pick until it returns a value
+    //waits for a released connection between attempts, every waiting connection tries again on a release
+    //returns None if the queue is full or the timeout expires
+    pub async fn wait<T>(&self, mut try_pick: impl FnMut() -> Option<T>) -> Option<T> {
+        //take a place in the queue
+        if self
+            .waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waiting| {
+                (waiting < self.capacity).then_some(waiting + 1)
+            })
+            .is_err()
+        {
+            return None;
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let result = loop {
+            //register for a notification before trying so a release is never missed
+            let notified = self.released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(value) = try_pick() {
+                break Some(value);
+            }
+            if timeout_at(deadline, notified).await.is_err() {
+                break None;
+            }
+        };
+
+        //leave the queue
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+}
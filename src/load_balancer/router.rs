@@ -0,0 +1,132 @@
+//! Routing of Layer 7 (HTTP) requests to upstreams.
+//!
+//! A listener may have routes matching requests by host, path, method and
+//! headers. A request is forwarded to the upstream of the first matching route,
+//! or of the matching route with the longest path, and to the upstream of the
+//! listener if no route matches.
+
+use http::header::{HeaderName, HOST};
+use http::{Method, Request};
+use regex::Regex;
+use std::sync::Arc;
+
+use crate::server::headers::HeaderRewrite;
+use crate::tls::client_hello::matches_server_name;
+
+/// How a route is picked when several routes match a request
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RouteMatching {
+    #[default]
+    FirstMatch, //the first matching route
+    LongestPrefix, //the matching route with the longest path
+}
+
+/// Path matched by a route
+#[derive(Clone, Debug)]
+pub enum PathMatch {
+    Exact(String),  //the path is equal
+    Prefix(String), //the path starts with the prefix, at a segment boundary
+    Regex(Regex),   //the path matches the regex
+}
+
+impl PathMatch {
+    //returns true if path matches
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            PathMatch::Exact(exact) => path == exact,
+            //"/api" matches "/api" and "/api/users" but not "/apiary"
+            PathMatch::Prefix(prefix) => path.strip_prefix(prefix.as_str()).is_some_and(|rest| {
+                rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/')
+            }),
+            PathMatch::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+/// Rule forwarding the requests it matches to an upstream
+///
+/// Every condition that is set must match, hosts and methods match if any of
+/// them matches.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub hosts: Vec<String>,      //exact or wildcard hosts, any host if empty
+    pub path: Option<PathMatch>, //path, any path if None
+    pub methods: Vec<Method>,    //methods, any method if empty
+    pub headers: Vec<(HeaderName, String)>, //headers the request must have with the value
+    pub upstream: String,        //name of the upstream of matching requests
+    pub rewrite: Arc<HeaderRewrite>, //header rewrites of matching requests and their responses
+}
+
+impl Route {
+    //returns true if the route matches req
+    pub fn matches<B>(&self, req: &Request<B>) -> bool {
+        if !self.hosts.is_empty() {
+            let Some(host) = request_host(req) else {
+                return false;
+            };
+            if !self
+                .hosts
+                .iter()
+                .any(|pattern| matches_server_name(pattern, host))
+            {
+                return false;
+            }
+        }
+        if let Some(path) = &self.path {
+            if !path.matches(req.uri().path()) {
+                return false;
+            }
+        }
+        if !self.methods.is_empty() && !self.methods.contains(req.method()) {
+            return false;
+        }
+        self.headers.iter().all(|(name, value)| {
+            req.headers()
+                .get_all(name)
+                .iter()
+                .any(|other| other.as_bytes() == value.as_bytes())
+        })
+    }
+
+    //returns the rank of the path of the route, higher is more specific
+    //exact paths rank above prefixes of the same length, regexes rank last
+    fn path_rank(&self) -> (usize, u8) {
+        match &self.path {
+            Some(PathMatch::Exact(exact)) => (exact.len(), 2),
+            Some(PathMatch::Prefix(prefix)) => (prefix.len(), 1),
+            Some(PathMatch::Regex(_)) | None => (0, 0),
+        }
+    }
+}
+
+/// Routes of a listener
+#[derive(Clone, Debug, Default)]
+pub struct Router {
+    pub matching: RouteMatching, //how a route is picked among matching routes
+    pub routes: Vec<Route>,      //routes in the order of the config
+}
+
+impl Router {
+    //returns the route req is forwarded by, None if no route matches
+    //with longest prefix matching, the first of the routes with the longest path wins
+    pub fn route<B>(&self, req: &Request<B>) -> Option<&Route> {
+        let mut matching = self.routes.iter().filter(|route| route.matches(req));
+        match self.matching {
+            RouteMatching::FirstMatch => matching.next(),
+            RouteMatching::LongestPrefix => matching.rev().max_by_key(|route| route.path_rank()),
+        }
+    }
+}
+
+//returns the host of req without its port, from the uri (HTTP/2) or the Host header
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    if let Some(host) = req.uri().host() {
+        return Some(host);
+    }
+    let host = req.headers().get(HOST)?.to_str().ok()?;
+    //keep the brackets of IPv6 addresses
+    match host.strip_prefix('[') {
+        Some(rest) => rest.find(']').map(|end| &host[..end + 2]),
+        None => host.split(':').next(),
+    }
+}
@@ -0,0 +1,85 @@
+//! Pools of backend servers.
+//!
+//! An upstream is a pool of servers with its own algorithm and health check
+//! settings. A listener forwards to its own servers, or to a named upstream
+//! defined in `[upstream.<name>]`. Listeners using the same named upstream
+//! share its servers, so connection limits, ejections and health are per
+//! server, while every listener picks servers with its own algorithm state.
+
+use std::sync::Arc;
+
+use crate::config::config::Algorithm;
+use crate::health_check::health_check::HealthCheck;
+use crate::load_balancer::algorithm::algorithm::Algorithm as AlgorithmTrait;
+use crate::server::server::SyncServer;
+
+/// Pool of servers picked by an algorithm
+pub struct Upstream {
+    pub name: String, //name of the upstream, empty for the servers of a listener
+    pub servers: Arc<Vec<SyncServer>>, //thread safe vector of servers
+    pub algorithm: Algorithm, //algorithm to pick server
+    pub last_picked_index: usize, //index of last picked server
+    pub algorithm_object: Box<dyn AlgorithmTrait>, //algorithm object
+    pub health_check: Option<HealthCheck>, //health check settings overriding the global ones
+}
+
+impl Upstream {
+    //returns true if server belongs to the upstream
+    pub fn contains(&self, server: &SyncServer) -> bool {
+        self.servers.iter().any(|other| Arc::ptr_eq(other, server))
+    }
+
+    //applies an upstream parsed again from the same file
+    //servers at the same address keep their state and take the new settings
+    pub fn apply(&mut self, new: Upstream) {
+        //keep servers at the same address, add the new ones
+        let servers = self.merge_servers(&new.servers);
+        for server in servers.iter() {
+            if !self.contains(server) {
+                let server = server.lock().unwrap();
+                println!("Server {}:{} added", server.host(), server.port());
+            }
+        }
+        for server in self.servers.iter() {
+            if !servers.iter().any(|kept| Arc::ptr_eq(kept, server)) {
+                let server = server.lock().unwrap();
+                println!("Server {}:{} removed", server.host(), server.port());
+            }
+        }
+        self.servers = Arc::new(servers);
+        self.last_picked_index = 0;
+
+        //a new algorithm starts without state
+        if new.algorithm != self.algorithm {
+            self.algorithm_object = new.algorithm_object;
+            self.algorithm = new.algorithm;
+        }
+        self.health_check = new.health_check;
+    }
+
+    //returns new servers, replaced by the servers of the upstream at the same address
+    //replaced servers take the settings of the new ones
+    pub fn merge_servers(&self, new: &[SyncServer]) -> Vec<SyncServer> {
+        new.iter()
+            .map(|new_server| {
+                //servers already merged for another listener are kept as they are
+                if self.contains(new_server) {
+                    return new_server.clone();
+                }
+                let new_server_locked = new_server.lock().unwrap();
+                let existing = self.servers.iter().find(|server| {
+                    let server = server.lock().unwrap();
+                    server.host() == new_server_locked.host()
+                        && server.port() == new_server_locked.port()
+                });
+                match existing {
+                    Some(server) => {
+                        server.lock().unwrap().update_settings(&new_server_locked);
+                        server.clone()
+                    }
+                    None => new_server.clone(),
+                }
+            })
+            .collect()
+    }
+}
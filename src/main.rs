@@ -1,30 +1,77 @@
-mod config;
-mod load_balancer;
-mod server;
-use crate::load_balancer::load_balancer::LoadBalancer;
-use config::config::{Config, LayerMode};
-use load_balancer::layer4::Layer4;
-use load_balancer::layer7::Layer7;
-use std::path::Path;
+use clap::Parser;
+use deston::cli::cli::{Cli, Command, ConfigArgs};
+use deston::config::config::{Config, LayerMode, SyncConfig};
+#[cfg(unix)]
+use deston::config::reload::reload_on_sighup;
+use deston::health_check::health_check::HealthChecker;
+use deston::load_balancer::layer4::Layer4;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use std::process::ExitCode;
 
 use std::sync::{Arc, Mutex};
 use tokio::signal;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 
 #[tokio::main]
-async fn main() {
-    let config = Config::new(Path::new("config.toml"));
-    let layer_mode = config.layer_mode.clone();
-    let config_arc = Arc::new(Mutex::new(config));
+async fn main() -> ExitCode {
+    match Cli::parse().into_command() {
+        Command::Run(args) => run(args).await,
+        Command::Check(args) => check(args),
+    }
+}
+
+//loads the configs of the listeners of args, printing the error if they are invalid
+fn load_listeners(args: &ConfigArgs) -> Option<Vec<Config>> {
+    match Config::load_listeners(&args.config, &args.overrides()) {
+        Ok(listeners) => Some(listeners),
+        Err(err) => {
+            eprintln!("Invalid config {}: {}", args.config.display(), err);
+            None
+        }
+    }
+}
+
+//validates the config and prints the effective config
+fn check(args: ConfigArgs) -> ExitCode {
+    let Some(listeners) = load_listeners(&args) else {
+        return ExitCode::FAILURE;
+    };
+    //certificates are loaded when the listener starts, load them now to validate them
+    for config in &listeners {
+        if let Some(tls) = &config.tls {
+            if let Err(err) = tls.server_config(config.cert_resolver.clone()) {
+                eprintln!("Invalid config {}: TLS: {}", args.config.display(), err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    print!("{}", Config::listeners_to_file(&listeners).to_toml());
+    ExitCode::SUCCESS
+}
+
+//runs the load balancer of every listener until shutdown
+async fn run(args: ConfigArgs) -> ExitCode {
+    let Some(listeners) = load_listeners(&args) else {
+        return ExitCode::FAILURE;
+    };
+    let configs = listeners
+        .into_iter()
+        .map(|config| Arc::new(Mutex::new(config)))
+        .collect::<Vec<_>>();
 
     // Create a channel for graceful shutdown
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let shutdown_tx = Arc::new(shutdown_tx);
 
     // Spawn a task to handle shutdown signals
+    let signal_shutdown_tx = shutdown_tx.clone();
     tokio::spawn(async move {
         match signal::ctrl_c().await {
             Ok(()) => {
                 println!("\nReceived shutdown signal, initiating graceful shutdown...");
-                let _ = shutdown_tx.send(true);
+                let _ = signal_shutdown_tx.send(true);
             }
             Err(err) => {
                 eprintln!("Unable to listen for shutdown signal: {}", err);
@@ -32,22 +79,61 @@ async fn main() {
         }
     });
 
+    // Spawn a task to reload the config on SIGHUP
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(
+        configs.clone(),
+        args.config.clone(),
+        args.overrides(),
+        shutdown_rx.clone(),
+    ));
+
+    // Start the health checks of all listeners, shared servers are probed once
+    tokio::spawn(HealthChecker::for_listeners(configs.clone()).run(shutdown_rx.clone()));
+
+    // Start every listener
+    let mut listeners = JoinSet::new();
+    for config in configs {
+        listeners.spawn(start_listener(config, shutdown_rx.clone()));
+    }
+
+    // Stop the other listeners if one fails
+    let mut failed = false;
+    while let Some(result) = listeners.join_next().await {
+        if !matches!(result, Ok(Ok(()))) {
+            failed = true;
+            let _ = shutdown_tx.send(true);
+        }
+    }
+
+    if failed {
+        return ExitCode::FAILURE;
+    }
+    println!("Load balancer shut down gracefully.");
+    ExitCode::SUCCESS
+}
+
+//starts the load balancer of a listener, printing the error if it fails
+async fn start_listener(config: SyncConfig, shutdown_rx: watch::Receiver<bool>) -> Result<(), ()> {
+    let (layer_mode, name) = {
+        let config = config.lock().unwrap();
+        (config.layer_mode.clone(), config.name.clone())
+    };
+    let name = name.map(|name| format!(" {}", name)).unwrap_or_default();
     match layer_mode {
         LayerMode::L4 => {
-            println!("Starting Layer 4 (TCP) Load Balancer...");
-            let lb = Layer4::new(config_arc);
-            if let Err(e) = lb.start(shutdown_rx).await {
-                eprintln!("Error starting Layer 4 load balancer: {:?}", e);
-            }
+            println!("Starting Layer 4 (TCP) Load Balancer{}...", name);
+            let lb = Layer4::new(config);
+            lb.start(shutdown_rx).await.map_err(|e| {
+                eprintln!("Error starting Layer 4 load balancer{}: {:?}", name, e);
+            })
         }
         LayerMode::L7 => {
-            println!("Starting Layer 7 (HTTP) Load Balancer...");
-            let lb = Layer7::new(config_arc);
-            if let Err(e) = lb.start(shutdown_rx).await {
-                eprintln!("Error starting Layer 7 load balancer: {:?}", e);
-            }
+            println!("Starting Layer 7 (HTTP) Load Balancer{}...", name);
+            let lb = Layer7::new(config);
+            lb.start(shutdown_rx).await.map_err(|e| {
+                eprintln!("Error starting Layer 7 load balancer{}: {:?}", name, e);
+            })
         }
     }
-
-    println!("Load balancer shut down gracefully.");
 }
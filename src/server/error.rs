@@ -0,0 +1,124 @@
+//! Errors of the Layer 7 (HTTP) request path.
+//!
+//! This module defines the ProxyError type returned while forwarding a request
+//! and maps every error to the HTTP response sent back to the client.
+
+use http::header::{HeaderValue, InvalidHeaderValue, CONTENT_TYPE};
+use http::uri::InvalidUriParts;
+use http_body_util::{combinators::BoxBody, BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Response, StatusCode};
+use std::fmt;
+
+use crate::load_balancer::load_balancer::ConnectError;
+
+/// Errors while forwarding a request to a server
+#[derive(Debug)]
+pub enum ProxyError {
+    NoRoute,                           //no route matches the request
+    NoServer,                          //no server available
+    Connect(std::io::Error),           //connecting to the server failed
+    Upstream(hyper::Error),            //sending the request or receiving the response failed
+    Timeout,                           //server did not respond in time
+    InvalidHeader(InvalidHeaderValue), //a forwarded header could not be built
+    InvalidUri(InvalidUriParts),       //the forwarded uri could not be built
+}
+
+impl ProxyError {
+    //returns the status of the response sent to the client
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ProxyError::NoRoute => StatusCode::NOT_FOUND,
+            ProxyError::NoServer => StatusCode::SERVICE_UNAVAILABLE,
+            ProxyError::Connect(_) | ProxyError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::InvalidHeader(_) | ProxyError::InvalidUri(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    //returns true if the error was caused by the server, for outlier detection
+    pub fn is_server_failure(&self) -> bool {
+        matches!(
+            self,
+            ProxyError::Connect(_) | ProxyError::Upstream(_) | ProxyError::Timeout
+        )
+    }
+
+    //returns the response sent to the client
+    //{status} and {reason} in body are replaced by the status code and reason
+    pub fn to_response(
+        &self,
+        body: &str,
+        content_type: &str,
+    ) -> Response<BoxBody<Bytes, hyper::Error>> {
+        let status = self.status();
+        let body = body
+            .replace("{status}", status.as_str())
+            .replace("{reason}", status.canonical_reason().unwrap_or_default());
+        let mut response = Response::new(
+            Full::new(Bytes::from(body))
+                .map_err(|never| match never {})
+                .boxed(),
+        );
+        *response.status_mut() = status;
+        if let Ok(content_type) = HeaderValue::from_str(content_type) {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        response
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::NoRoute => write!(f, "no route matches the request"),
+            ProxyError::NoServer => write!(f, "no server available"),
+            ProxyError::Connect(err) => write!(f, "failed to connect to server: {}", err),
+            ProxyError::Upstream(err) => write!(f, "request to server failed: {}", err),
+            ProxyError::Timeout => write!(f, "server did not respond in time"),
+            ProxyError::InvalidHeader(err) => write!(f, "invalid forwarded header: {}", err),
+            ProxyError::InvalidUri(err) => write!(f, "invalid forwarded uri: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProxyError::Connect(err) => Some(err),
+            ProxyError::Upstream(err) => Some(err),
+            ProxyError::InvalidHeader(err) => Some(err),
+            ProxyError::InvalidUri(err) => Some(err),
+            ProxyError::NoRoute | ProxyError::NoServer | ProxyError::Timeout => None,
+        }
+    }
+}
+
+impl From<ConnectError> for ProxyError {
+    fn from(err: ConnectError) -> Self {
+        match err {
+            ConnectError::NoServer => ProxyError::NoServer,
+            ConnectError::Connect(err) => ProxyError::Connect(err),
+        }
+    }
+}
+
+impl From<hyper::Error> for ProxyError {
+    fn from(err: hyper::Error) -> Self {
+        ProxyError::Upstream(err)
+    }
+}
+
+impl From<InvalidHeaderValue> for ProxyError {
+    fn from(err: InvalidHeaderValue) -> Self {
+        ProxyError::InvalidHeader(err)
+    }
+}
+
+impl From<InvalidUriParts> for ProxyError {
+    fn from(err: InvalidUriParts) -> Self {
+        ProxyError::InvalidUri(err)
+    }
+}
@@ -0,0 +1,216 @@
+//! Forwarded and X-Forwarded-* headers of forwarded requests (L7).
+//!
+//! Servers behind the load balancer learn the address of the client and the
+//! scheme, host and port it used from the `Forwarded` header (RFC 7239) and the
+//! `X-Forwarded-For`, `-Proto`, `-Host` and `-Port` headers. Values sent by
+//! trusted proxies are kept and appended to, values sent by any other client
+//! are replaced.
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue, FORWARDED};
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::server::server::ClientInfo;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+const X_FORWARDED_PORT: HeaderName = HeaderName::from_static("x-forwarded-port");
+
+/// Headers added to forwarded requests
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ForwardedHeaders {
+    #[default]
+    All, //Forwarded and X-Forwarded-*
+    Forwarded,  //Forwarded only
+    XForwarded, //X-Forwarded-* only
+    None,       //neither, headers sent by untrusted clients are still removed
+}
+
+/// Address or network of proxies whose forwarded headers are trusted
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrustedProxy {
+    addr: IpAddr,   //address of the network
+    prefix_len: u8, //length of the network prefix, all bits for a single address
+}
+
+impl TrustedProxy {
+    //parses an address, e.g. 10.0.0.1, or a network, e.g. 10.0.0.0/8
+    //returns an error message if the address or prefix length is invalid
+    pub fn parse(proxy: &str) -> Result<Self, String> {
+        let (addr, prefix_len) = match proxy.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (proxy, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid address \"{}\"", addr))?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_len)
+                .ok_or_else(|| format!("prefix length must be between 0 and {}", max_len))?,
+            None => max_len,
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    //returns true if addr is the address or within the network
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        //IPv4 clients of dual-stack listeners have IPv4-mapped IPv6 addresses
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max_len = if self.addr.is_ipv4() { 32 } else { 128 };
+        if self.prefix_len == max_len {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix_len)
+        }
+    }
+}
+
+/// Forwarded headers settings of a listener
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Forwarding {
+    pub headers: ForwardedHeaders, //headers added to forwarded requests
+    pub trusted_proxies: Vec<TrustedProxy>, //proxies whose forwarded headers are kept
+}
+
+impl Forwarding {
+    //returns true if the forwarded headers sent by addr are trusted
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|proxy| proxy.contains(addr))
+    }
+
+    //replaces the forwarded headers of a request from client, or appends to them if client is trusted
+    //host is the Host of the client request, empty if none
+    pub fn apply(
+        &self,
+        headers: &mut HeaderMap,
+        client: &ClientInfo,
+        host: &str,
+    ) -> Result<(), InvalidHeaderValue> {
+        //values sent by a trusted proxy describe the original client, others may be spoofed
+        if !self.is_trusted(client.addr.ip()) {
+            for name in [
+                &FORWARDED,
+                &X_FORWARDED_FOR,
+                &X_FORWARDED_PROTO,
+                &X_FORWARDED_HOST,
+                &X_FORWARDED_PORT,
+            ] {
+                headers.remove(name);
+            }
+        }
+
+        if matches!(
+            self.headers,
+            ForwardedHeaders::All | ForwardedHeaders::Forwarded
+        ) {
+            //for, by, host and proto of this hop
+            let mut element = format!(
+                "for={};by={}",
+                quote(&node(client.addr.ip(), None)),
+                quote(&node(
+                    client.local_addr.ip(),
+                    Some(client.local_addr.port())
+                ))
+            );
+            if !host.is_empty() {
+                element.push_str(&format!(";host={}", quote(host)));
+            }
+            element.push_str(&format!(";proto={}", client.proto));
+            let forwarded = append(headers, &FORWARDED, &element)?;
+            headers.insert(FORWARDED, forwarded);
+        }
+
+        if matches!(
+            self.headers,
+            ForwardedHeaders::All | ForwardedHeaders::XForwarded
+        ) {
+            let client_ip = client.addr.ip().to_canonical().to_string();
+            let forwarded_for = append(headers, &X_FORWARDED_FOR, &client_ip)?;
+            headers.insert(X_FORWARDED_FOR, forwarded_for);
+            //proto, host and port set by a trusted proxy are those the original client used
+            if !headers.contains_key(&X_FORWARDED_PROTO) {
+                headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(client.proto));
+            }
+            if !headers.contains_key(&X_FORWARDED_HOST) && !host.is_empty() {
+                headers.insert(X_FORWARDED_HOST, HeaderValue::from_str(host)?);
+            }
+            if !headers.contains_key(&X_FORWARDED_PORT) {
+                headers.insert(X_FORWARDED_PORT, client.local_addr.port().into());
+            }
+        }
+        Ok(())
+    }
+}
+
+//returns the values of the list header name with value appended, separated by commas
+fn append(
+    headers: &HeaderMap,
+    name: &HeaderName,
+    value: &str,
+) -> Result<HeaderValue, InvalidHeaderValue> {
+    let mut list = Vec::new();
+    for previous in headers.get_all(name) {
+        list.extend_from_slice(previous.as_bytes());
+        list.extend_from_slice(b", ");
+    }
+    list.extend_from_slice(value.as_bytes());
+    HeaderValue::from_bytes(&list)
+}
+
+//returns a node of the Forwarded header, IPv6 addresses are in brackets
+fn node(addr: IpAddr, port: Option<u16>) -> String {
+    let addr = match addr.to_canonical() {
+        IpAddr::V4(addr) => addr.to_string(),
+        IpAddr::V6(addr) => format!("[{}]", addr),
+    };
+    match port {
+        Some(port) => format!("{}:{}", addr, port),
+        None => addr,
+    }
+}
+
+//returns value as a token, or as a quoted string if it has other characters, e.g. : or [
+fn quote(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if is_token {
+        return value.to_owned();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,212 @@
+//! Header rewrites of requests and responses (L7).
+//!
+//! A route may set, add and remove headers of the requests it forwards and of
+//! their responses. Header values are templates where `{client_ip}` style
+//! variables are replaced by values of the request being forwarded.
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::server::forwarded::Forwarding;
+use crate::server::server::ClientInfo;
+
+/// Variables of header templates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variable {
+    ClientIp,   //IP address of the client
+    ClientPort, //port of the client
+    Scheme,     //scheme used by the client, http or https
+    Host,       //Host of the client request
+    RequestId,  //unique id of the request
+    Upstream,   //name of the upstream the request is forwarded to
+    Server,     //address of the server the request is forwarded to
+}
+
+impl Variable {
+    //returns the variable with the given name, or None if unknown
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "client_ip" => Some(Variable::ClientIp),
+            "client_port" => Some(Variable::ClientPort),
+            "scheme" => Some(Variable::Scheme),
+            "host" => Some(Variable::Host),
+            "request_id" => Some(Variable::RequestId),
+            "upstream" => Some(Variable::Upstream),
+            "server" => Some(Variable::Server),
+            _ => None,
+        }
+    }
+}
+
+/// Values of the variables of a forwarded request
+pub struct Variables<'a> {
+    pub client: &'a ClientInfo, //client connection of the request
+    pub host: &'a str,          //Host of the client request, empty if none
+    pub request_id: &'a str,    //unique id of the request
+    pub upstream: &'a str,      //name of the upstream, empty for the servers of the listener
+    pub server: &'a str,        //address of the server, host:port
+}
+
+impl Variables<'_> {
+    //writes the value of variable to value
+    fn write(&self, variable: Variable, value: &mut String) {
+        let _ = match variable {
+            Variable::ClientIp => write!(value, "{}", self.client.addr.ip()),
+            Variable::ClientPort => write!(value, "{}", self.client.addr.port()),
+            Variable::Scheme => write!(value, "{}", self.client.proto),
+            Variable::Host => write!(value, "{}", self.host),
+            Variable::RequestId => write!(value, "{}", self.request_id),
+            Variable::Upstream => write!(value, "{}", self.upstream),
+            Variable::Server => write!(value, "{}", self.server),
+        };
+    }
+}
+
+//part of a header template
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),       //written as is
+    Variable(Variable), //replaced by the value of the variable
+}
+
+/// Header value with variables, e.g. `{client_ip}`
+///
+/// `{{` and `}}` are written as `{` and `}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderTemplate {
+    template: String, //template as written in the config
+    parts: Vec<Part>, //parsed template
+}
+
+impl HeaderTemplate {
+    //parses a template
+    //returns an error message if a variable is unknown or a brace is not closed
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("missing closing }".to_owned()),
+                        }
+                    }
+                    let variable = Variable::from_name(&name)
+                        .ok_or_else(|| format!("unknown variable {{{}}}", name))?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Variable(variable));
+                }
+                '}' => return Err("unmatched }, write }} for a literal }".to_owned()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self {
+            template: template.to_owned(),
+            parts,
+        })
+    }
+
+    //returns the template as written in the config
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    //returns the header value with the variables replaced
+    pub fn render(&self, variables: &Variables) -> Result<HeaderValue, InvalidHeaderValue> {
+        let mut value = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => value.push_str(text),
+                Part::Variable(variable) => variables.write(*variable, &mut value),
+            }
+        }
+        HeaderValue::from_str(&value)
+    }
+}
+
+/// Headers set, added and removed
+///
+/// Headers are removed first, then set, replacing every value, then added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderRules {
+    pub set: Vec<(HeaderName, HeaderTemplate)>, //headers replaced
+    pub add: Vec<(HeaderName, HeaderTemplate)>, //headers appended to existing values
+    pub remove: Vec<HeaderName>,                //headers removed
+}
+
+impl HeaderRules {
+    //returns true if there are no rules
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.add.is_empty() && self.remove.is_empty()
+    }
+
+    //applies the rules to headers
+    pub fn apply(
+        &self,
+        headers: &mut HeaderMap,
+        variables: &Variables,
+    ) -> Result<(), InvalidHeaderValue> {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, template) in &self.set {
+            headers.insert(name.clone(), template.render(variables)?);
+        }
+        for (name, template) in &self.add {
+            headers.append(name.clone(), template.render(variables)?);
+        }
+        Ok(())
+    }
+}
+
+/// Header rewrites of the requests forwarded by a route and of their responses
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderRewrite {
+    pub preserve_host: bool, //forward the Host of the client instead of the server address
+    pub request: HeaderRules, //rules of forwarded requests
+    pub response: HeaderRules, //rules of responses sent back to the client
+}
+
+/// Request being forwarded, with the header rewrites of its route
+#[derive(Clone, Debug, Default)]
+pub struct RequestContext {
+    pub id: String,                  //unique id of the request
+    pub upstream: String,            //name of the upstream, empty for the servers of the listener
+    pub rewrite: Arc<HeaderRewrite>, //header rewrites of the route
+    pub forwarding: Arc<Forwarding>, //forwarded headers settings of the listener
+}
+
+//returns a new unique request id
+//ids start from the time the process started, so they stay unique across restarts
+pub fn next_request_id() -> String {
+    static START: OnceLock<u64> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let start = *START.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_micros() as u64)
+            .unwrap_or_default()
+    });
+    format!("{:x}-{:x}", start, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
@@ -1,2 +1,7 @@
+pub mod error;
+pub mod forwarded;
+pub mod headers;
+pub mod pool;
 #[allow(clippy::module_inception)]
 pub mod server;
+pub mod stream;
@@ -0,0 +1,252 @@
+//! Pooling of idle connections to backend servers.
+//!
+//! Layer 7 reuses connections to a server across requests and clients instead
+//! of opening a new TCP connection for every request. An HTTP/1.1 connection
+//! goes back to the pool of its server once its response is done, while an
+//! h2c connection is shared by concurrent requests as multiplexed streams.
+//! Idle connections are closed once they were idle or alive for too long.
+
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Bytes, Incoming};
+use hyper::client::conn::{http1, http2, TrySendError};
+use hyper::{Request, Response};
+use std::time::Duration;
+use tokio::time::{timeout, Instant};
+
+use crate::server::error::ProxyError;
+use crate::server::server::{Protocol, Server, SyncServer};
+
+//type alias for the body of requests forwarded to servers
+type UpstreamBody = BoxBody<Bytes, hyper::Error>;
+
+/// Sending half of a connection to a server
+pub enum UpstreamSender {
+    Http1(http1::SendRequest<UpstreamBody>), //HTTP/1.1, one request at a time
+    Http2(http2::SendRequest<UpstreamBody>), //h2c, multiplexed requests
+}
+
+impl UpstreamSender {
+    //waits until the connection can send a request
+    pub async fn ready(&mut self) -> Result<(), hyper::Error> {
+        match self {
+            UpstreamSender::Http1(sender) => sender.ready().await,
+            UpstreamSender::Http2(sender) => sender.ready().await,
+        }
+    }
+
+    //returns true if the connection is closed
+    pub fn is_closed(&self) -> bool {
+        match self {
+            UpstreamSender::Http1(sender) => sender.is_closed(),
+            UpstreamSender::Http2(sender) => sender.is_closed(),
+        }
+    }
+
+    //returns true if the connection is shared by concurrent requests
+    pub fn is_multiplexed(&self) -> bool {
+        matches!(self, UpstreamSender::Http2(_))
+    }
+
+    //sends a request and returns the response from the server
+    pub async fn send_request(
+        &mut self,
+        req: Request<UpstreamBody>,
+    ) -> Result<Response<Incoming>, hyper::Error> {
+        match self {
+            UpstreamSender::Http1(sender) => sender.send_request(req).await,
+            UpstreamSender::Http2(sender) => sender.send_request(req).await,
+        }
+    }
+
+    //sends a request and returns the response from the server
+    //the error holds the request if it was not sent
+    pub async fn try_send_request(
+        &mut self,
+        req: Request<UpstreamBody>,
+    ) -> Result<Response<Incoming>, TrySendError<Request<UpstreamBody>>> {
+        match self {
+            UpstreamSender::Http1(sender) => sender.try_send_request(req).await,
+            UpstreamSender::Http2(sender) => sender.try_send_request(req).await,
+        }
+    }
+}
+
+/// Connection pool settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionPool {
+    pub max_idle: usize, //max idle connections kept per server, 0 disables pooling
+    pub idle_timeout: Duration, //time a connection may stay idle
+    pub max_lifetime: Duration, //time a connection may be reused, zero for no limit
+}
+
+impl Default for ConnectionPool {
+    fn default() -> Self {
+        Self {
+            max_idle: 32,
+            idle_timeout: Duration::from_secs(90),
+            max_lifetime: Duration::ZERO,
+        }
+    }
+}
+
+impl ConnectionPool {
+    //returns true if an idle connection must be closed instead of reused
+    pub fn is_expired(&self, connection: &IdleConnection) -> bool {
+        connection.sender.is_closed()
+            || connection.idle_since.elapsed() >= self.idle_timeout
+            || (!self.max_lifetime.is_zero() && connection.created.elapsed() >= self.max_lifetime)
+    }
+}
+
+/// Idle connection waiting in the pool of a server
+pub struct IdleConnection {
+    sender: UpstreamSender,
+    created: Instant,    //time the connection was opened
+    idle_since: Instant, //time the connection was last used
+}
+
+impl IdleConnection {
+    //returns a new handle to a multiplexed connection and marks it used
+    //returns None for connections that can only send one request at a time
+    pub fn share(&mut self) -> Option<IdleConnection> {
+        let UpstreamSender::Http2(sender) = &self.sender else {
+            return None;
+        };
+        self.idle_since = Instant::now();
+        Some(IdleConnection {
+            sender: UpstreamSender::Http2(sender.clone()),
+            created: self.created,
+            idle_since: self.idle_since,
+        })
+    }
+}
+
+/// Connection to a server taken from its pool or newly opened
+///
+/// HTTP/1.1 connections go back to the pool of the server when dropped, once
+/// their response is done. Multiplexed connections stay in the pool while used
+pub struct PooledConnection {
+    server: SyncServer,
+    pool: ConnectionPool,
+    sender: Option<UpstreamSender>,
+    created: Instant, //time the connection was opened
+    reused: bool,     //was the connection taken from the pool?
+}
+
+impl PooledConnection {
+    //takes an idle connection to server from its pool, or opens a new one
+    pub async fn checkout(server: SyncServer, pool: ConnectionPool) -> std::io::Result<Self> {
+        //concurrent requests to an h2c server wait for a single new connection to share
+        let (protocol, opening) = {
+            let server_locked = server.lock().unwrap();
+            (server_locked.protocol(), server_locked.opening())
+        };
+        let _opening = match protocol {
+            Protocol::H2c => Some(opening.lock_owned().await),
+            Protocol::Http1 => None,
+        };
+
+        let idle = { server.lock().unwrap().take_idle_connection(&pool) };
+        if let Some(idle) = idle {
+            return Ok(Self {
+                server,
+                pool,
+                sender: Some(idle.sender),
+                created: idle.created,
+                reused: true,
+            });
+        }
+
+        let sender = Self::open(&server, &pool).await?;
+        Ok(Self {
+            server,
+            pool,
+            sender: Some(sender),
+            created: Instant::now(),
+            reused: false,
+        })
+    }
+
+    //opens a new connection to server
+    //multiplexed connections are shared through the pool right away
+    async fn open(server: &SyncServer, pool: &ConnectionPool) -> std::io::Result<UpstreamSender> {
+        let protocol = { server.lock().unwrap().protocol() };
+        let stream = Server::connect(server).await?;
+        let sender = Server::handshake(stream, protocol)
+            .await
+            .map_err(std::io::Error::other)?;
+
+        if let UpstreamSender::Http2(shared) = &sender {
+            let now = Instant::now();
+            let idle = IdleConnection {
+                sender: UpstreamSender::Http2(shared.clone()),
+                created: now,
+                idle_since: now,
+            };
+            server.lock().unwrap().put_idle_connection(idle, pool);
+        }
+        Ok(sender)
+    }
+
+    //sends a request over the connection and returns the response from the server
+    //a reused connection may have been closed by the server while idle,
+    //so a request that was not sent yet is retried once on a new connection
+    pub async fn send_request(
+        &mut self,
+        req: Request<UpstreamBody>,
+    ) -> Result<Response<Incoming>, ProxyError> {
+        let sender = self.sender.as_mut().unwrap();
+        let req = if sender.ready().await.is_ok() {
+            match sender.try_send_request(req).await {
+                Ok(resp) => return Ok(resp),
+                Err(mut err) => match err.take_message() {
+                    Some(req) if self.reused => req,
+                    _ => return Err(err.into_error().into()),
+                },
+            }
+        } else if self.reused {
+            req
+        } else {
+            return Err(ProxyError::Connect(std::io::Error::from(
+                std::io::ErrorKind::ConnectionReset,
+            )));
+        };
+
+        //retry on a new connection
+        self.sender = Some(
+            Self::open(&self.server, &self.pool)
+                .await
+                .map_err(ProxyError::Connect)?,
+        );
+        self.created = Instant::now();
+        self.reused = false;
+        Ok(self.sender.as_mut().unwrap().send_request(req).await?)
+    }
+}
+
+impl Drop for PooledConnection {
+    //returns an HTTP/1.1 connection to the pool of the server once its response is done
+    fn drop(&mut self) {
+        let Some(mut sender) = self.sender.take() else {
+            return;
+        };
+        if self.pool.max_idle == 0 || sender.is_closed() || sender.is_multiplexed() {
+            return;
+        }
+        let server = self.server.clone();
+        let pool = self.pool.clone();
+        let created = self.created;
+        tokio::spawn(async move {
+            //the connection is ready again once the response was read completely,
+            //connections closed before that are dropped
+            if let Ok(Ok(())) = timeout(pool.idle_timeout, sender.ready()).await {
+                let idle = IdleConnection {
+                    sender,
+                    created,
+                    idle_since: Instant::now(),
+                };
+                server.lock().unwrap().put_idle_connection(idle, &pool);
+            }
+        });
+    }
+}
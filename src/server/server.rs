@@ -3,44 +3,72 @@
 //! This module defines the Server struct and provides methods for handling
 //! both Layer 4 (TCP) and Layer 7 (HTTP) connections.
 
-use http::header::{HeaderValue, FORWARDED};
+use http::header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, HOST, TE};
+use http::uri::{Authority, Parts, Scheme};
 use http_body_util::{combinators::BoxBody, BodyExt};
 use hyper::body::{Bytes, Incoming};
-use hyper::client::conn::http1::Builder;
-use hyper::{Request, Response, Uri};
-use hyper_util::rt::TokioIo;
+use hyper::client::conn::{http1, http2};
+use hyper::{Request, Response, Uri, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::collections::VecDeque;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tokio::io::{copy, split, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio::time::Instant;
 use tokio::try_join;
 
+use crate::health_check::health_check::HealthCheck;
+use crate::server::error::ProxyError;
+use crate::server::headers::{RequestContext, Variables};
+use crate::server::pool::{ConnectionPool, IdleConnection, PooledConnection, UpstreamSender};
+use crate::server::stream::ServerStream;
+use crate::tls::client_cert::ClientCert;
+use crate::tls::upstream::{UpstreamTls, UpstreamTlsSettings};
+
+//headers carrying the identity of a client authenticated with a certificate (mTLS)
+const X_CLIENT_CERT_SUBJECT: &str = "x-client-cert-subject";
+const X_CLIENT_CERT_SAN: &str = "x-client-cert-san";
+const X_CLIENT_CERT_FINGERPRINT: &str = "x-client-cert-fingerprint";
+
 //type alias for a thread-safe, synchronized Server using Arc and Mutex
 pub type SyncServer = Arc<Mutex<Server>>;
 
+/// Client connection a request was received on (L7)
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,              //address of client
+    pub local_addr: SocketAddr,        //address of the listener the client connected to
+    pub proto: &'static str,           //scheme used by client, http or https
+    pub cert: Option<Arc<ClientCert>>, //verified certificate of client (mTLS)
+}
+
+/// Protocol used to forward requests to a server (L7)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Http1, //HTTP/1.1
+    H2c,   //HTTP/2 with prior knowledge, e.g. gRPC
+}
+
 /// Backend server representation with connection tracking and health metrics
-#[derive(Clone)]
 pub struct Server {
     #[allow(dead_code)]
     uri: Uri, //uri of server
     host: String, //hostname of server
     port: u16,    //port at which server is running
 
-    #[allow(dead_code)]
-    max_connections: u32, //max connections server can handle
-    #[allow(dead_code)]
-    connections: u32, //number of alive connections
-    #[allow(dead_code)]
-    total_connections: u32, //total connections server has served
-    #[allow(dead_code)]
-    successful_connections: u32, //total successful connections server has served
-    #[allow(dead_code)]
-    failed_connections: u32, //total failed connections
+    max_connections: u32,           //max connections server can handle
+    connections: u32,               //number of alive connections
+    total_connections: u32,         //total connections server has served
+    successful_connections: u32,    //total successful connections server has served
+    failed_connections: u32,        //total failed connections
+    consecutive_failures: u32,      //failed connections since the latest successful one
+    recent_results: VecDeque<bool>, //results of latest connections, true if successful
 
     #[allow(dead_code)]
     last_request_time: SystemTime, //time of latest request
-    #[allow(dead_code)]
     last_health_check: SystemTime, //time of latest health check
 
     #[allow(dead_code)]
@@ -48,18 +76,29 @@ pub struct Server {
     #[allow(dead_code)]
     avg_response_time: f64, //average response time
 
-    #[allow(dead_code)]
-    is_alive: bool, //is server alive?
+    is_alive: bool,                    //is server alive?
+    health_streak: u32,                //consecutive health checks disagreeing with is_alive
+    health_check: Option<HealthCheck>, //health check settings overriding the global ones
+    ejected_until: Option<Instant>,    //end of latest ejection by outlier detection
+    ejections: u32,                    //consecutive ejections by outlier detection
+
+    protocol: Protocol,                 //protocol of forwarded requests (L7)
+    server_names: Vec<String>,          //TLS server names routed to server (L4 passthrough)
+    tls: Option<UpstreamTlsSettings>,   //TLS settings of connections to server
+    tls_connector: Option<UpstreamTls>, //encrypts connections to server, built from tls
+    idle_connections: VecDeque<IdleConnection>, //pooled connections, most recently used last
+    opening: Arc<tokio::sync::Mutex<()>>, //held while opening a connection to share
 
     pub weight: usize, //for weighted algorithms
 }
 
 impl Server {
     //creates and returns a new server
+    //uri is expected to have a host and a port, as checked when the config is loaded
     pub fn new(uri: Uri, max_connections: u32, weight: usize) -> Self {
         Self {
-            host: uri.host().unwrap().to_string(),
-            port: uri.port_u16().unwrap(),
+            host: uri.host().unwrap_or_default().to_string(),
+            port: uri.port_u16().unwrap_or_default(),
             uri,
 
             max_connections,
@@ -67,6 +106,8 @@ impl Server {
             total_connections: 0,
             successful_connections: 0,
             failed_connections: 0,
+            consecutive_failures: 0,
+            recent_results: VecDeque::new(),
 
             last_request_time: SystemTime::now(),
             last_health_check: SystemTime::now(),
@@ -75,31 +116,283 @@ impl Server {
             avg_response_time: 0.0,
 
             is_alive: true,
+            health_streak: 0,
+            health_check: None,
+            ejected_until: None,
+            ejections: 0,
+
+            protocol: Protocol::Http1,
+            server_names: Vec::new(),
+            tls: None,
+            tls_connector: None,
+            idle_connections: VecDeque::new(),
+            opening: Arc::new(tokio::sync::Mutex::new(())),
 
             weight,
         }
     }
 
-    //establishes connection with server and transfers data between server and client
-    pub async fn transfer_data(
-        server: SyncServer,
-        client_stream: TcpStream,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        //get host and port value from server
-        let (host, port) = {
+    //returns number of alive connections
+    pub fn connections(&self) -> u32 {
+        self.connections
+    }
+
+    //returns total connections server has served
+    pub fn total_connections(&self) -> u32 {
+        self.total_connections
+    }
+
+    //returns max connections server can handle
+    pub fn max_connections(&self) -> u32 {
+        self.max_connections
+    }
+
+    //returns total successful connections server has served
+    pub fn successful_connections(&self) -> u32 {
+        self.successful_connections
+    }
+
+    //returns total failed connections
+    pub fn failed_connections(&self) -> u32 {
+        self.failed_connections
+    }
+
+    //returns failed connections since the latest successful one
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    //returns results of latest connections, true if successful
+    pub fn recent_results(&self) -> &VecDeque<bool> {
+        &self.recent_results
+    }
+
+    //records the result of a connection
+    //keeps at most window_size latest results
+    pub fn record_result(&mut self, success: bool, window_size: usize) {
+        if success {
+            self.successful_connections += 1;
+            self.consecutive_failures = 0;
+        } else {
+            self.failed_connections += 1;
+            self.consecutive_failures += 1;
+        }
+        self.recent_results.push_back(success);
+        while self.recent_results.len() > window_size {
+            self.recent_results.pop_front();
+        }
+    }
+
+    //returns true if server is ejected by outlier detection
+    pub fn is_ejected(&self) -> bool {
+        self.ejected_until
+            .is_some_and(|ejected_until| Instant::now() < ejected_until)
+    }
+
+    //returns end of latest ejection by outlier detection
+    pub fn ejected_until(&self) -> Option<Instant> {
+        self.ejected_until
+    }
+
+    //returns consecutive ejections by outlier detection
+    pub fn ejections(&self) -> u32 {
+        self.ejections
+    }
+
+    //ejects server for duration and clears the failures that caused it
+    pub fn eject(&mut self, duration: Duration) {
+        self.ejected_until = Some(Instant::now() + duration);
+        self.ejections += 1;
+        self.consecutive_failures = 0;
+        self.recent_results.clear();
+    }
+
+    //resets consecutive ejections once server recovered
+    pub fn reset_ejections(&mut self) {
+        self.ejections = 0;
+    }
+
+    //returns hostname of server
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    //returns port of server
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    //returns true if server is marked alive by health checks
+    pub fn is_alive(&self) -> bool {
+        self.is_alive
+    }
+
+    //returns time of latest health check
+    pub fn last_health_check(&self) -> SystemTime {
+        self.last_health_check
+    }
+
+    //returns health check settings of server, if they override the global ones
+    pub fn health_check(&self) -> Option<&HealthCheck> {
+        self.health_check.as_ref()
+    }
+
+    //sets health check settings overriding the global ones
+    pub fn set_health_check(&mut self, health_check: Option<HealthCheck>) {
+        self.health_check = health_check;
+    }
+
+    //records the result of a health check
+    //server is marked alive after rise consecutive successes and dead after fall consecutive failures
+    //returns true if the server state changed
+    pub fn record_health_check(&mut self, healthy: bool, rise: u32, fall: u32) -> bool {
+        self.last_health_check = SystemTime::now();
+        if healthy == self.is_alive {
+            self.health_streak = 0;
+            return false;
+        }
+        self.health_streak += 1;
+        let threshold = if healthy { rise } else { fall };
+        if self.health_streak < threshold {
+            return false;
+        }
+        self.is_alive = healthy;
+        self.health_streak = 0;
+        true
+    }
+
+    //returns protocol of forwarded requests
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    //sets protocol of forwarded requests
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    //returns TLS server names routed to server
+    pub fn server_names(&self) -> &[String] {
+        &self.server_names
+    }
+
+    //sets TLS server names routed to server
+    pub fn set_server_names(&mut self, server_names: Vec<String>) {
+        self.server_names = server_names;
+    }
+
+    //returns TLS settings of connections to server, None for plain TCP
+    pub fn tls(&self) -> Option<&UpstreamTlsSettings> {
+        self.tls.as_ref()
+    }
+
+    //sets TLS settings of connections to server and loads their certificates
+    //h2c servers are offered h2 with ALPN, so set the protocol first
+    pub fn set_tls(&mut self, tls: Option<UpstreamTlsSettings>) -> std::io::Result<()> {
+        let alpn: &[&[u8]] = match self.protocol {
+            Protocol::Http1 => &[b"http/1.1"],
+            Protocol::H2c => &[b"h2"],
+        };
+        self.tls_connector = match &tls {
+            Some(settings) => Some(settings.connector(&self.host, alpn)?),
+            None => None,
+        };
+        self.tls = tls;
+        Ok(())
+    }
+
+    //copies the settings of other, a server at the same address from a reloaded config
+    //keeps the connections and health of server, idle connections are closed if
+    //the protocol or TLS settings changed
+    pub fn update_settings(&mut self, other: &Server) {
+        if self.protocol != other.protocol || self.tls != other.tls {
+            self.idle_connections.clear();
+        }
+        self.max_connections = other.max_connections;
+        self.weight = other.weight;
+        self.health_check = other.health_check.clone();
+        self.protocol = other.protocol;
+        self.server_names = other.server_names.clone();
+        self.tls = other.tls.clone();
+        self.tls_connector = other.tls_connector.clone();
+    }
+
+    //returns the lock held while opening a connection to share
+    pub fn opening(&self) -> Arc<tokio::sync::Mutex<()>> {
+        self.opening.clone()
+    }
+
+    //returns number of idle connections in the pool of server
+    pub fn idle_connections(&self) -> usize {
+        self.idle_connections.len()
+    }
+
+    //takes the most recently used idle connection that can be reused
+    //multiplexed connections stay in the pool and are shared instead
+    //closes expired idle connections
+    pub fn take_idle_connection(&mut self, pool: &ConnectionPool) -> Option<IdleConnection> {
+        self.idle_connections
+            .retain(|connection| !pool.is_expired(connection));
+        match self.protocol {
+            Protocol::Http1 => self.idle_connections.pop_back(),
+            Protocol::H2c => self.idle_connections.back_mut()?.share(),
+        }
+    }
+
+    //puts a connection back into the pool
+    //closes expired idle connections and the oldest ones above max_idle
+    pub fn put_idle_connection(&mut self, connection: IdleConnection, pool: &ConnectionPool) {
+        self.idle_connections.push_back(connection);
+        self.idle_connections
+            .retain(|connection| !pool.is_expired(connection));
+        while self.idle_connections.len() > pool.max_idle {
+            self.idle_connections.pop_front();
+        }
+    }
+
+    //returns true if server can accept another connection
+    pub fn is_available(&self) -> bool {
+        self.is_alive && !self.is_ejected() && self.connections < self.max_connections
+    }
+
+    //establishes a TCP connection with server, encrypted if server uses TLS
+    pub async fn connect(server: &SyncServer) -> std::io::Result<ServerStream> {
+        //get host, port and TLS connector value from server
+        let (host, port, tls_connector) = {
             let server_locked = server.lock().unwrap();
-            (server_locked.host.clone(), server_locked.port)
+            (
+                server_locked.host.clone(),
+                server_locked.port,
+                server_locked.tls_connector.clone(),
+            )
         };
 
         //create a new server stream
-        let server_stream = TcpStream::connect((host.as_str(), port)).await?;
+        let stream = TcpStream::connect((host.as_str(), port)).await?;
+        match tls_connector {
+            Some(tls_connector) => Ok(ServerStream::Tls(Box::new(
+                tls_connector.connect(stream).await?,
+            ))),
+            None => Ok(ServerStream::Plain(stream)),
+        }
+    }
 
+    //transfers data between server and client
+    //initial_data already read from the client is sent to the server first
+    //the connection is released once both directions are done
+    pub async fn transfer_data(
+        _connection: ConnectionGuard,
+        client_stream: TcpStream,
+        server_stream: ServerStream,
+        initial_data: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         //split server and client streams into read and write streams
         let (mut client_read, mut client_write) = split(client_stream);
         let (mut server_read, mut server_write) = split(server_stream);
 
         //transfer data from client to server
         let client_to_server = tokio::spawn(async move {
+            server_write.write_all(&initial_data).await?;
             copy(&mut client_read, &mut server_write).await?;
             server_write.shutdown().await
         });
@@ -117,70 +410,247 @@ impl Server {
     }
 
     //handle_request handles incoming request and forwards it to a server
+    //applies the header rewrites of the route of the request to the request and the response
     //returns the response from the server
-    #[allow(dead_code)]
+    //the connection is released once the response body is done
     pub async fn handle_request(
-        server: SyncServer,
+        connection: ConnectionGuard,
+        mut upstream: PooledConnection,
         mut req: Request<Incoming>,
-        addr: SocketAddr,
-    ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
-        //get host, port and uri value from server
-        let (host, port, uri) = {
-            let server_locked = server.lock().unwrap();
+        client: &ClientInfo,
+        context: &RequestContext,
+    ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, ProxyError> {
+        //get host, port, uri, protocol and TLS value from server
+        let (host, port, uri, protocol, tls) = {
+            let server_locked = connection.server().lock().unwrap();
             (
                 server_locked.host.clone(),
                 server_locked.port,
                 server_locked.uri.clone(),
+                server_locked.protocol,
+                server_locked.tls.is_some(),
             )
         };
+        let rewrite = &context.rewrite;
+
+        //Host of the client, in the uri for HTTP/2 clients
+        let client_host = match req.headers().get(HOST) {
+            Some(host) => host.to_str().ok().map(str::to_owned),
+            None => req.uri().authority().map(|authority| authority.to_string()),
+        }
+        .unwrap_or_default();
+        let server_address = format!("{}:{}", host, port);
+        let variables = Variables {
+            client,
+            host: &client_host,
+            request_id: &context.id,
+            upstream: &context.upstream,
+            server: &server_address,
+        };
+
+        //HTTP/1.1 expects an origin-form uri, HTTP/2 an absolute one
+        let mut parts = Parts::default();
+        parts.path_and_query = req.uri().path_and_query().cloned();
+        match protocol {
+            Protocol::Http1 => *req.version_mut() = Version::HTTP_11,
+            Protocol::H2c => {
+                *req.version_mut() = Version::HTTP_2;
+                parts.scheme = Some(if tls { Scheme::HTTPS } else { Scheme::HTTP });
+                parts.authority = rewrite
+                    .preserve_host
+                    .then(|| client_host.parse::<Authority>().ok())
+                    .flatten()
+                    .or_else(|| uri.authority().cloned());
+            }
+        }
+        *req.uri_mut() = Uri::from_parts(parts)?;
 
         //update the headers
         let headers = req.headers_mut();
-        //update host in header
-        let new_host_header = HeaderValue::from_str(host.as_str()).unwrap();
-        headers.insert("host", new_host_header);
-        //add FORWARDED to the headers
-        headers.insert(
-            FORWARDED,
-            HeaderValue::from_str(
-                format!(
-                    "by={}; for={}; host={}; proto={}",
-                    //by: load balancer address
-                    "127.0.0.1:8000",
-                    //for: client address
-                    addr,
-                    //host: server address
-                    uri,
-                    //prototype: http1
-                    "http1"
-                )
-                .as_str(),
-            )
-            .unwrap(),
-        );
+        //hop-by-hop headers only apply to the connection with the client
+        remove_hop_by_hop_headers(headers);
+        //update host in header, unless the route preserves the Host of the client
+        let new_host_header = if rewrite.preserve_host && !client_host.is_empty() {
+            HeaderValue::from_str(&client_host)?
+        } else {
+            HeaderValue::from_str(host.as_str())?
+        };
+        headers.insert(HOST, new_host_header);
+        //add Forwarded and X-Forwarded-* to the headers, or append to those of a trusted proxy
+        context.forwarding.apply(headers, client, &client_host)?;
+        //add the identity of the client certificate, headers sent by the client are never trusted
+        headers.remove(X_CLIENT_CERT_SUBJECT);
+        headers.remove(X_CLIENT_CERT_SAN);
+        headers.remove(X_CLIENT_CERT_FINGERPRINT);
+        if let Some(cert) = &client.cert {
+            headers.insert(
+                X_CLIENT_CERT_SUBJECT,
+                HeaderValue::from_bytes(cert.subject.as_bytes())?,
+            );
+            if !cert.sans.is_empty() {
+                headers.insert(
+                    X_CLIENT_CERT_SAN,
+                    HeaderValue::from_bytes(cert.sans.join(", ").as_bytes())?,
+                );
+            }
+            headers.insert(
+                X_CLIENT_CERT_FINGERPRINT,
+                HeaderValue::from_str(&cert.fingerprint)?,
+            );
+        }
+        //apply the header rules of the route
+        rewrite.request.apply(headers, &variables)?;
+
+        //forward the request and await the server response
+        let mut resp = upstream.send_request(req.map(|b| b.boxed())).await?;
+        remove_hop_by_hop_headers(resp.headers_mut());
+        rewrite.response.apply(resp.headers_mut(), &variables)?;
+
+        //convert Incoming into BoxBody and return the response
+        //the connection moves into the body so it is released and pooled once the body is dropped
+        Ok(resp.map(|b| {
+            b.map_frame(move |frame| {
+                let _ = (&connection, &upstream);
+                frame
+            })
+            .boxed()
+        }))
+    }
+
+    //send_request sends a request over HTTP/1 or HTTP/2 on a stream connected to the server
+    //returns the response from the server
+    pub async fn send_request(
+        stream: ServerStream,
+        protocol: Protocol,
+        req: Request<BoxBody<Bytes, hyper::Error>>,
+    ) -> Result<Response<Incoming>, hyper::Error> {
+        let mut sender = Self::handshake(stream, protocol).await?;
+
+        //await the server response
+        sender.send_request(req).await
+    }
 
-        //create a new stream to communicate with server
-        let stream = TcpStream::connect((host.as_str(), port)).await.unwrap();
+    //handshake starts an HTTP/1 or HTTP/2 connection on a stream connected to the server
+    //returns the sender used to send requests over the connection
+    pub async fn handshake(
+        stream: ServerStream,
+        protocol: Protocol,
+    ) -> Result<UpstreamSender, hyper::Error> {
         let io = TokioIo::new(stream);
 
-        //create an Hyper client
-        let (mut sender, conn) = Builder::new()
-            .preserve_header_case(true)
-            .title_case_headers(true)
-            .handshake(io)
-            .await?;
-
-        //spawn a task to poll the connection
-        tokio::task::spawn(async move {
-            if let Err(err) = conn.await {
-                println!("Connection failed: {:?}", err);
+        match protocol {
+            Protocol::Http1 => {
+                //create an Hyper client
+                let (sender, conn) = http1::Builder::new()
+                    .preserve_header_case(true)
+                    .title_case_headers(true)
+                    .handshake(io)
+                    .await?;
+
+                //spawn a task to poll the connection
+                tokio::task::spawn(async move {
+                    if let Err(err) = conn.await {
+                        println!("Connection failed: {:?}", err);
+                    }
+                });
+                Ok(UpstreamSender::Http1(sender))
             }
-        });
+            Protocol::H2c => {
+                //create an Hyper client, streams are spawned on the tokio executor
+                let (sender, conn) = http2::Builder::new(TokioExecutor::new())
+                    .handshake(io)
+                    .await?;
 
-        //await the server response
-        let resp = sender.send_request(req).await?;
+                //spawn a task to poll the connection
+                tokio::task::spawn(async move {
+                    if let Err(err) = conn.await {
+                        println!("Connection failed: {:?}", err);
+                    }
+                });
+                Ok(UpstreamSender::Http2(sender))
+            }
+        }
+    }
+}
 
-        //convert Incoming into BoxBody and return the response
-        Ok(resp.map(|b| b.boxed()))
+//removes hop-by-hop headers, including the ones listed in the Connection header
+//TE: trailers is kept as gRPC requires it
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let trailers = headers
+        .get_all(TE)
+        .iter()
+        .any(|value| value.as_bytes().eq_ignore_ascii_case(b"trailers"));
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in [
+        "connection",
+        "keep-alive",
+        "proxy-connection",
+        "te",
+        "trailer",
+        "transfer-encoding",
+        "upgrade",
+    ] {
+        headers.remove(name);
+    }
+    if trailers {
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+    }
+}
+
+/// Tracks an alive connection to a server
+///
+/// Increments the connection counters of the server when created and
+/// decrements the alive connections when dropped
+pub struct ConnectionGuard {
+    server: SyncServer,
+    released: Option<Arc<Notify>>, //notified when the connection is released
+}
+
+impl ConnectionGuard {
+    //creates a new guard and increments connections of the server
+    pub fn new(server: SyncServer) -> Self {
+        {
+            let mut server_locked = server.lock().unwrap();
+            server_locked.connections += 1;
+            server_locked.total_connections += 1;
+        }
+        Self {
+            server,
+            released: None,
+        }
+    }
+
+    //notifies released when the guard is dropped
+    pub fn notify_on_release(mut self, released: Arc<Notify>) -> Self {
+        self.released = Some(released);
+        self
+    }
+
+    //returns the server of the connection
+    pub fn server(&self) -> &SyncServer {
+        &self.server
+    }
+}
+
+impl Drop for ConnectionGuard {
+    //decrements connections of the server and wakes the waiting connections
+    //every waiting connection is woken, as waiting connections of other upstreams can not use it
+    fn drop(&mut self) {
+        {
+            let mut server_locked = self.server.lock().unwrap();
+            server_locked.connections = server_locked.connections.saturating_sub(1);
+        }
+        if let Some(released) = &self.released {
+            released.notify_waiters();
+        }
     }
 }
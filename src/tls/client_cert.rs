@@ -0,0 +1,68 @@
+//! Identity of clients authenticated with a certificate.
+//!
+//! When the Layer 7 listener requires client certificates, this module
+//! extracts the subject, subject alternative names and fingerprint of the
+//! verified certificate, which are forwarded to servers as headers.
+
+use rustls::pki_types::CertificateDer;
+use sha2::{Digest, Sha256};
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// Verified certificate presented by a client
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientCert {
+    pub subject: String,     //distinguished name, e.g. CN=partner,O=Example
+    pub sans: Vec<String>,   //subject alternative names, e.g. DNS:partner.example.com
+    pub fingerprint: String, //SHA-256 of the DER certificate, lowercase hex
+}
+
+impl ClientCert {
+    //parses the leaf certificate presented by a client
+    //returns None if the certificate can not be parsed
+    pub fn from_der(cert: &CertificateDer<'_>) -> Option<Self> {
+        let (_, parsed) = X509Certificate::from_der(cert.as_ref()).ok()?;
+
+        let sans = match parsed.subject_alternative_name() {
+            Ok(Some(extension)) => extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(format_general_name)
+                .collect(),
+            _ => Vec::new(),
+        };
+        let fingerprint = Sha256::digest(cert.as_ref())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        Some(Self {
+            subject: parsed.subject().to_string(),
+            sans,
+            fingerprint,
+        })
+    }
+}
+
+//formats a subject alternative name with its type, e.g. DNS:example.com
+//returns None for name types that are not forwarded
+fn format_general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+        GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+        GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+        GeneralName::IPAddress(bytes) => match bytes.len() {
+            4 => {
+                let octets: [u8; 4] = (*bytes).try_into().ok()?;
+                Some(format!("IP:{}", std::net::Ipv4Addr::from(octets)))
+            }
+            16 => {
+                let octets: [u8; 16] = (*bytes).try_into().ok()?;
+                Some(format!("IP:{}", std::net::Ipv6Addr::from(octets)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
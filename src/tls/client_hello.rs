@@ -0,0 +1,163 @@
+//! Peeking at the TLS ClientHello for passthrough routing.
+//!
+//! Layer 4 reads the first bytes of a connection without decrypting them and
+//! extracts the SNI hostname from the ClientHello, so the connection can be
+//! routed by hostname. The bytes read are replayed to the picked server.
+
+use std::io;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+const RECORD_HEADER_LEN: usize = 5; //content type, version and length of a TLS record
+const HANDSHAKE_HEADER_LEN: usize = 4; //type and length of a handshake message
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+const MAX_CLIENT_HELLO_LEN: usize = 64 * 1024; //bytes read at most before giving up
+
+/// Result of parsing the first bytes of a connection
+#[derive(Debug, PartialEq)]
+pub enum ClientHello {
+    Incomplete,             //more bytes are needed
+    NotTls,                 //the connection does not start with a ClientHello
+    Parsed(Option<String>), //the SNI hostname, if the client sent one
+}
+
+//reads from stream until the ClientHello is complete
+//returns the bytes read, to be replayed to the server, and the SNI hostname
+//connections not starting with a ClientHello have no hostname
+pub async fn read_client_hello(stream: &mut TcpStream) -> io::Result<(Vec<u8>, Option<String>)> {
+    let mut buf = Vec::with_capacity(1024);
+    loop {
+        match parse_client_hello(&buf) {
+            ClientHello::Parsed(server_name) => return Ok((buf, server_name)),
+            ClientHello::NotTls => return Ok((buf, None)),
+            ClientHello::Incomplete if buf.len() >= MAX_CLIENT_HELLO_LEN => return Ok((buf, None)),
+            ClientHello::Incomplete => {
+                if stream.read_buf(&mut buf).await? == 0 {
+                    //client closed before sending a complete ClientHello
+                    return Ok((buf, None));
+                }
+            }
+        }
+    }
+}
+
+//parses the SNI hostname from the ClientHello at the start of buf
+//the ClientHello may span several TLS records
+pub fn parse_client_hello(buf: &[u8]) -> ClientHello {
+    //join the payloads of the leading handshake records
+    let mut handshake = Vec::new();
+    let mut rest = buf;
+    loop {
+        if rest.len() < RECORD_HEADER_LEN {
+            return ClientHello::Incomplete;
+        }
+        if rest[0] != CONTENT_TYPE_HANDSHAKE || rest[1] != 0x03 {
+            return ClientHello::NotTls;
+        }
+        let record_len = u16::from_be_bytes([rest[3], rest[4]]) as usize;
+        if rest.len() < RECORD_HEADER_LEN + record_len {
+            return ClientHello::Incomplete;
+        }
+        handshake.extend_from_slice(&rest[RECORD_HEADER_LEN..RECORD_HEADER_LEN + record_len]);
+        rest = &rest[RECORD_HEADER_LEN + record_len..];
+
+        if handshake.len() >= HANDSHAKE_HEADER_LEN {
+            if handshake[0] != HANDSHAKE_CLIENT_HELLO {
+                return ClientHello::NotTls;
+            }
+            let message_len =
+                u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if handshake.len() >= HANDSHAKE_HEADER_LEN + message_len {
+                let message = &handshake[HANDSHAKE_HEADER_LEN..HANDSHAKE_HEADER_LEN + message_len];
+                return match parse_server_name(message) {
+                    Some(server_name) => ClientHello::Parsed(server_name),
+                    None => ClientHello::NotTls,
+                };
+            }
+        }
+    }
+}
+
+//parses the SNI hostname from the body of a ClientHello message
+//returns None if the message is malformed
+fn parse_server_name(message: &[u8]) -> Option<Option<String>> {
+    let mut reader = Reader(message);
+    reader.skip(2 + 32)?; //client version and random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.skip(cipher_suites_len)?;
+    let compression_methods_len = reader.u8()? as usize;
+    reader.skip(compression_methods_len)?;
+    if reader.0.is_empty() {
+        //no extensions
+        return Some(None);
+    }
+
+    let extensions_len = reader.u16()? as usize;
+    let mut extensions = Reader(reader.take(extensions_len)?);
+    while !extensions.0.is_empty() {
+        let extension_type = extensions.u16()?;
+        let extension_len = extensions.u16()? as usize;
+        let mut extension = Reader(extensions.take(extension_len)?);
+        if extension_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+
+        let list_len = extension.u16()? as usize;
+        let mut names = Reader(extension.take(list_len)?);
+        while !names.0.is_empty() {
+            let name_type = names.u8()?;
+            let name_len = names.u16()? as usize;
+            let name = names.take(name_len)?;
+            if name_type == NAME_TYPE_HOST_NAME {
+                let name = std::str::from_utf8(name).ok()?;
+                return Some(Some(name.to_lowercase()));
+            }
+        }
+    }
+    Some(None)
+}
+
+//reads big-endian integers and byte strings from a buffer
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+//returns true if server_name matches pattern, an exact name or a wildcard (*.example.com)
+//wildcards match a single label
+pub fn matches_server_name(pattern: &str, server_name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let server_name = server_name.trim_end_matches('.').to_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(parent) => server_name
+            .split_once('.')
+            .is_some_and(|(_, server_parent)| server_parent == parent),
+        None => pattern == server_name,
+    }
+}
@@ -0,0 +1,6 @@
+pub mod client_cert;
+pub mod client_hello;
+pub mod resolver;
+#[allow(clippy::module_inception)]
+pub mod tls;
+pub mod upstream;
@@ -0,0 +1,115 @@
+//! Certificate selection by SNI hostname.
+//!
+//! This module picks the certificate presented to a client from the hostname
+//! it sent in the TLS ClientHello. Names match exactly or through a wildcard
+//! (`*.example.com` matches `api.example.com` but not `example.com`), and a
+//! default certificate is used for unknown or missing names. Certificates can
+//! be reloaded at any time; handshakes already done keep their certificate.
+
+use rustls::crypto::ring::sign::any_supported_type;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use crate::tls::tls::{load_certs, load_private_key, CertificateSettings, TlsSettings};
+
+/// Certificates indexed by server name
+#[derive(Debug, Default)]
+struct Certificates {
+    exact: HashMap<String, Arc<CertifiedKey>>, //certificates by full server name
+    wildcard: HashMap<String, Arc<CertifiedKey>>, //certificates by name without the first label
+    default: Option<Arc<CertifiedKey>>,        //certificate for unknown names
+}
+
+impl Certificates {
+    //returns the certificate for server_name, or the default one
+    fn get(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let Some(server_name) = server_name else {
+            return self.default.clone();
+        };
+        let server_name = server_name.trim_end_matches('.').to_lowercase();
+        if let Some(certificate) = self.exact.get(&server_name) {
+            return Some(certificate.clone());
+        }
+        if let Some((_, parent)) = server_name.split_once('.') {
+            if let Some(certificate) = self.wildcard.get(parent) {
+                return Some(certificate.clone());
+            }
+        }
+        self.default.clone()
+    }
+}
+
+/// Resolves the certificate of a TLS handshake from the SNI hostname
+#[derive(Debug, Default)]
+pub struct CertResolver {
+    certificates: RwLock<Arc<Certificates>>,
+}
+
+impl CertResolver {
+    //creates and returns a new resolver without certificates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //loads the certificates of settings, replacing the current ones
+    //the current certificates are kept if any certificate fails to load
+    pub fn load(&self, settings: &TlsSettings) -> io::Result<()> {
+        if settings.certificates.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no certificate configured",
+            ));
+        }
+        let mut certificates = Certificates::default();
+        //the first certificate marked default, or the first one
+        let default_index = settings
+            .certificates
+            .iter()
+            .position(|certificate| certificate.default)
+            .unwrap_or(0);
+        for (index, certificate_settings) in settings.certificates.iter().enumerate() {
+            let certificate = load_certified_key(certificate_settings)?;
+            for server_name in &certificate_settings.server_names {
+                let server_name = server_name.to_lowercase();
+                match server_name.strip_prefix("*.") {
+                    Some(parent) => certificates
+                        .wildcard
+                        .entry(parent.to_owned())
+                        .or_insert_with(|| certificate.clone()),
+                    None => certificates
+                        .exact
+                        .entry(server_name)
+                        .or_insert_with(|| certificate.clone()),
+                };
+            }
+            if index == default_index {
+                certificates.default = Some(certificate);
+            }
+        }
+
+        *self.certificates.write().unwrap() = Arc::new(certificates);
+        Ok(())
+    }
+
+    //returns the certificate presented to a client asking for server_name
+    pub fn certificate(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        self.certificates.read().unwrap().get(server_name)
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.certificate(client_hello.server_name())
+    }
+}
+
+//loads a certificate chain and its private key
+fn load_certified_key(settings: &CertificateSettings) -> io::Result<Arc<CertifiedKey>> {
+    let cert_chain = load_certs(&settings.cert_chain)?;
+    let private_key = load_private_key(&settings.private_key)?;
+    let signing_key = any_supported_type(&private_key).map_err(io::Error::other)?;
+    Ok(Arc::new(CertifiedKey::new(cert_chain, signing_key)))
+}
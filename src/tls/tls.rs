@@ -0,0 +1,94 @@
+//! TLS termination for the Layer 7 listener.
+//!
+//! This module loads PEM certificates and private keys and builds the rustls
+//! configuration used to accept TLS connections, negotiating HTTP/2 or
+//! HTTP/1.1 with ALPN. The certificate of every connection is picked by the
+//! SNI resolver, and clients can be required to present a certificate.
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+
+use crate::tls::resolver::CertResolver;
+
+/// TLS termination settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsSettings {
+    pub certificates: Vec<CertificateSettings>, //certificates picked by SNI
+    pub client_ca: Option<PathBuf>, //PEM file with the CAs client certificates must be signed by
+}
+
+/// Certificate presented to clients asking for one of its server names
+#[derive(Clone, Debug, PartialEq)]
+pub struct CertificateSettings {
+    pub cert_chain: PathBuf,  //PEM file with the certificate chain, leaf first
+    pub private_key: PathBuf, //PEM file with the private key
+    pub server_names: Vec<String>, //exact or wildcard (*.example.com) names
+    pub default: bool,        //presented for unknown names, defaults to the first certificate
+}
+
+impl TlsSettings {
+    //loads the certificates into resolver and builds the rustls server config
+    //advertises h2 and http/1.1 with ALPN
+    //with client_ca, handshakes without a client certificate signed by it are rejected
+    pub fn server_config(&self, resolver: Arc<CertResolver>) -> io::Result<Arc<ServerConfig>> {
+        resolver.load(self)?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(client_ca)? {
+                    roots.add(cert).map_err(io::Error::other)?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .map_err(io::Error::other)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut server_config = builder.with_cert_resolver(resolver);
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(server_config))
+    }
+
+    //builds the acceptor used to terminate TLS connections
+    //certificates reloaded into resolver apply to new connections
+    pub fn acceptor(&self, resolver: Arc<CertResolver>) -> io::Result<TlsAcceptor> {
+        Ok(TlsAcceptor::from(self.server_config(resolver)?))
+    }
+}
+
+//loads every certificate of a PEM file
+pub fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificate found in {}", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+
+//loads the first private key of a PEM file
+pub fn load_private_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no private key found in {}", path.display()),
+        )
+    })
+}
@@ -0,0 +1,84 @@
+//! TLS and mutual TLS to backend servers.
+//!
+//! This module builds the rustls client configuration used to encrypt the
+//! connections from the load balancer to a server. Servers are verified
+//! against a configured CA bundle or the bundled web PKI roots, and a client
+//! certificate can be presented to servers requiring mutual TLS.
+
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, RootCertStore};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+use crate::tls::tls::{load_certs, load_private_key};
+
+/// TLS settings of the connections to a server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpstreamTlsSettings {
+    pub ca: Option<PathBuf>, //PEM file with the CA certificates, defaults to the web PKI roots
+    pub server_name: Option<String>, //name sent as SNI and verified, defaults to the server address
+    pub client_cert: Option<PathBuf>, //PEM file with the client certificate chain (mTLS)
+    pub client_key: Option<PathBuf>, //PEM file with the private key of the client certificate (mTLS)
+}
+
+impl UpstreamTlsSettings {
+    //loads the certificates and builds the connector to a server at host
+    //offers the alpn protocols to the server
+    pub fn connector(&self, host: &str, alpn: &[&[u8]]) -> io::Result<UpstreamTls> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca {
+            Some(ca) => {
+                for cert in load_certs(ca)? {
+                    roots.add(cert).map_err(io::Error::other)?;
+                }
+            }
+            None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+        }
+
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(io::Error::other)?
+                .with_root_certificates(roots);
+        let mut client_config = match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), Some(client_key)) => builder
+                .with_client_auth_cert(load_certs(client_cert)?, load_private_key(client_key)?)
+                .map_err(io::Error::other)?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "client_cert and client_key must be set together",
+                ))
+            }
+        };
+        client_config.alpn_protocols = alpn.iter().map(|proto| proto.to_vec()).collect();
+
+        let server_name = self.server_name.as_deref().unwrap_or(host).to_owned();
+        Ok(UpstreamTls {
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name: ServerName::try_from(server_name)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        })
+    }
+}
+
+/// Connector encrypting the connections to a server
+#[derive(Clone)]
+pub struct UpstreamTls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>, //name sent as SNI and verified
+}
+
+impl UpstreamTls {
+    //performs the TLS handshake with the server on stream
+    pub async fn connect(&self, stream: TcpStream) -> io::Result<TlsStream<TcpStream>> {
+        self.connector
+            .connect(self.server_name.clone(), stream)
+            .await
+    }
+}
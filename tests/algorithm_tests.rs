@@ -3,10 +3,13 @@ use std::sync::{Arc, Mutex};
 
 // Import algorithm modules from main crate
 use deston::load_balancer::algorithm::algorithm::Algorithm;
+use deston::load_balancer::algorithm::dynamic::{
+    least_connections::LeastConnections, weighted_least_connections::WeightedLeastConnections,
+};
 use deston::load_balancer::algorithm::r#static::{
     ip_hashing::IpHashing, round_robin::RoundRobin, weighted_round_robin::WeightedRoundRobin,
 };
-use deston::server::server::Server;
+use deston::server::server::{ConnectionGuard, Server};
 use hyper::Uri;
 
 // Helper function to create test servers
@@ -22,6 +25,19 @@ fn create_test_servers(count: usize, weights: Option<Vec<usize>>) -> Arc<Vec<Arc
     Arc::new(servers)
 }
 
+// Helper to create test servers that accept a single connection each
+fn create_limited_servers(count: usize) -> Arc<Vec<Arc<Mutex<Server>>>> {
+    let servers: Vec<Arc<Mutex<Server>>> = (0..count)
+        .map(|i| {
+            let uri = format!("http://127.0.0.1:{}", 3000 + i)
+                .parse::<Uri>()
+                .unwrap();
+            Arc::new(Mutex::new(Server::new(uri, 1, 1)))
+        })
+        .collect();
+    Arc::new(servers)
+}
+
 // Helper to create a test socket address
 fn test_addr() -> SocketAddr {
     "127.0.0.1:5000".parse().unwrap()
@@ -141,6 +157,170 @@ fn test_ip_hashing_distribution() {
     );
 }
 
+#[test]
+fn test_least_connections_picks_least_loaded() {
+    let mut algorithm = LeastConnections::new();
+    let servers = create_test_servers(3, None);
+
+    // Servers 0 and 1 hold connections, server 2 is idle
+    let _guards = [
+        ConnectionGuard::new(servers[0].clone()),
+        ConnectionGuard::new(servers[0].clone()),
+        ConnectionGuard::new(servers[1].clone()),
+    ];
+
+    let (index, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+    assert_eq!(index, 2);
+}
+
+#[test]
+fn test_least_connections_rotates_ties() {
+    let mut algorithm = LeastConnections::new();
+    let servers = create_test_servers(3, None);
+
+    // With no connections, idle servers should be picked in turn
+    let (index1, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+    let (index2, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+    let (index3, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+
+    assert_eq!(index1, 0);
+    assert_eq!(index2, 1);
+    assert_eq!(index3, 2);
+}
+
+#[test]
+fn test_least_connections_follows_released_connections() {
+    let mut algorithm = LeastConnections::new();
+    let servers = create_test_servers(2, None);
+
+    // Hold a connection on server 1 and release the one on server 0
+    let guard0 = ConnectionGuard::new(servers[0].clone());
+    let _guard1 = ConnectionGuard::new(servers[1].clone());
+    drop(guard0);
+
+    assert_eq!(servers[0].lock().unwrap().connections(), 0);
+    assert_eq!(servers[0].lock().unwrap().total_connections(), 1);
+    for _ in 0..3 {
+        let (index, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+        assert_eq!(index, 0);
+    }
+}
+
+#[test]
+fn test_weighted_least_connections_respects_weights() {
+    let mut algorithm = WeightedLeastConnections::new();
+    let servers = create_test_servers(2, Some(vec![3, 1]));
+
+    // Server 0 holds 2 connections with weight 3, server 1 holds 1 with weight 1
+    let _guards = [
+        ConnectionGuard::new(servers[0].clone()),
+        ConnectionGuard::new(servers[0].clone()),
+        ConnectionGuard::new(servers[1].clone()),
+    ];
+
+    let (index, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+    assert_eq!(index, 0);
+}
+
+#[test]
+fn test_weighted_least_connections_skips_zero_weight() {
+    let mut algorithm = WeightedLeastConnections::new();
+    let servers = create_test_servers(2, Some(vec![0, 1]));
+
+    for _ in 0..3 {
+        let (index, _) = algorithm.pick_server(servers.clone(), test_addr()).unwrap();
+        assert_eq!(index, 1);
+    }
+}
+
+#[test]
+fn test_algorithms_skip_saturated_servers() {
+    let algorithms: Vec<Box<dyn Algorithm>> = vec![
+        Box::new(RoundRobin::new()),
+        Box::new(WeightedRoundRobin::new()),
+        Box::new(IpHashing::new()),
+        Box::new(LeastConnections::new()),
+        Box::new(WeightedLeastConnections::new()),
+    ];
+
+    for mut algorithm in algorithms {
+        let servers = create_limited_servers(3);
+
+        // Saturate servers 0 and 2, only server 1 can accept a connection
+        let _guards = [
+            ConnectionGuard::new(servers[0].clone()),
+            ConnectionGuard::new(servers[2].clone()),
+        ];
+
+        for port in 5000..5005 {
+            let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let (index, _) = algorithm.pick_server(servers.clone(), addr).unwrap();
+            assert_eq!(index, 1);
+        }
+    }
+}
+
+#[test]
+fn test_algorithms_return_none_when_all_saturated() {
+    let algorithms: Vec<Box<dyn Algorithm>> = vec![
+        Box::new(RoundRobin::new()),
+        Box::new(WeightedRoundRobin::new()),
+        Box::new(IpHashing::new()),
+        Box::new(LeastConnections::new()),
+        Box::new(WeightedLeastConnections::new()),
+    ];
+
+    for mut algorithm in algorithms {
+        let servers = create_limited_servers(2);
+        let _guards = [
+            ConnectionGuard::new(servers[0].clone()),
+            ConnectionGuard::new(servers[1].clone()),
+        ];
+
+        assert!(algorithm
+            .pick_server(servers.clone(), test_addr())
+            .is_none());
+    }
+}
+
+#[test]
+fn test_algorithms_skip_dead_servers() {
+    let algorithms: Vec<Box<dyn Algorithm>> = vec![
+        Box::new(RoundRobin::new()),
+        Box::new(WeightedRoundRobin::new()),
+        Box::new(IpHashing::new()),
+        Box::new(LeastConnections::new()),
+        Box::new(WeightedLeastConnections::new()),
+    ];
+
+    for mut algorithm in algorithms {
+        let servers = create_test_servers(3, None);
+
+        // Mark servers 1 and 2 dead
+        servers[1].lock().unwrap().record_health_check(false, 1, 1);
+        servers[2].lock().unwrap().record_health_check(false, 1, 1);
+
+        for port in 5000..5005 {
+            let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let (index, _) = algorithm.pick_server(servers.clone(), addr).unwrap();
+            assert_eq!(index, 0);
+        }
+    }
+}
+
+#[test]
+fn test_server_availability() {
+    let uri = "http://127.0.0.1:3000".parse::<Uri>().unwrap();
+    let server = Arc::new(Mutex::new(Server::new(uri, 2, 1)));
+
+    let guard1 = ConnectionGuard::new(server.clone());
+    assert!(server.lock().unwrap().is_available());
+    let _guard2 = ConnectionGuard::new(server.clone());
+    assert!(!server.lock().unwrap().is_available());
+    drop(guard1);
+    assert!(server.lock().unwrap().is_available());
+}
+
 #[test]
 fn test_server_creation() {
     let uri = "http://127.0.0.1:3000".parse::<Uri>().unwrap();
@@ -0,0 +1,141 @@
+use clap::Parser;
+use deston::cli::cli::{Cli, Command};
+use deston::config::config::{Config, LayerMode, Overrides};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+
+// Helper to write a config file, returns its path
+fn write_config(name: &str, config_content: &str) -> PathBuf {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    config_path
+}
+
+#[test]
+fn test_cli_parses_subcommands() {
+    let cli = Cli::try_parse_from([
+        "deston",
+        "run",
+        "--config",
+        "/etc/deston/a.toml",
+        "--listen",
+        "0.0.0.0:9000",
+        "--layer",
+        "l7",
+    ])
+    .unwrap();
+    let Command::Run(args) = cli.into_command() else {
+        panic!("expected run");
+    };
+    assert_eq!(args.config, Path::new("/etc/deston/a.toml"));
+    assert_eq!(
+        args.overrides(),
+        Overrides {
+            address: Some("0.0.0.0".to_owned()),
+            port: Some(9000),
+            layer: Some(LayerMode::L7),
+        }
+    );
+
+    let cli = Cli::try_parse_from(["deston", "check", "-c", "b.toml"]).unwrap();
+    let Command::Check(args) = cli.into_command() else {
+        panic!("expected check");
+    };
+    assert_eq!(args.config, Path::new("b.toml"));
+    assert_eq!(args.overrides(), Overrides::default());
+
+    // Without a subcommand the load balancer runs with config.toml
+    let cli = Cli::try_parse_from(["deston"]).unwrap();
+    let Command::Run(args) = cli.into_command() else {
+        panic!("expected run");
+    };
+    assert_eq!(args.config, Path::new("config.toml"));
+}
+
+#[test]
+fn test_cli_rejects_invalid_overrides() {
+    for listen in ["9000", ":9000", "0.0.0.0:0", "0.0.0.0:http"] {
+        assert!(Cli::try_parse_from(["deston", "run", "--listen", listen]).is_err());
+    }
+    assert!(Cli::try_parse_from(["deston", "run", "--layer", "L5"]).is_err());
+    let cli = Cli::try_parse_from(["deston", "run", "--listen", "[::1]:9000"]).unwrap();
+    let Command::Run(args) = cli.into_command() else {
+        panic!("expected run");
+    };
+    assert_eq!(args.overrides().address.as_deref(), Some("[::1]"));
+}
+
+#[test]
+fn test_config_overrides() {
+    let config_path = write_config(
+        "test_config_overrides",
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 8080
+layer = "L4"
+
+[[server]]
+port = 3000
+"#,
+    );
+    let overrides = Overrides {
+        address: Some("0.0.0.0".to_owned()),
+        port: Some(9000),
+        layer: Some(LayerMode::L7),
+    };
+    let config = Config::load_with(&config_path, &overrides).unwrap();
+    assert_eq!(config.load_balancer_address.to_string(), "0.0.0.0:9000");
+    assert_eq!(config.layer_mode, LayerMode::L7);
+
+    // Settings that are not overridden come from the file
+    let overrides = Overrides {
+        port: Some(9001),
+        ..Overrides::default()
+    };
+    let config = Config::load_with(&config_path, &overrides).unwrap();
+    assert_eq!(config.load_balancer_address.to_string(), "127.0.0.1:9001");
+    assert_eq!(config.layer_mode, LayerMode::L4);
+
+    fs::remove_file(config_path).ok();
+}
+
+#[test]
+fn test_check_prints_effective_config() {
+    let config_path = write_config(
+        "test_check_prints_effective_config",
+        r#"
+[load_balancer]
+algorithm = "LeastConnections"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#,
+    );
+    let output = Process::new(env!("CARGO_BIN_EXE_deston"))
+        .args(["check", "--listen", "127.0.0.1:9100", "--config"])
+        .arg(&config_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("address = \"127.0.0.1\"\nport = 9100\n"));
+    assert!(stdout.contains("algorithm = \"least_connections\""));
+    assert!(stdout.contains("max_connections = 1000"));
+
+    // Invalid configs exit with an error pointing to the line
+    fs::write(&config_path, "[[server]]\nport = 3000\nweight = 0\n").unwrap();
+    let output = Process::new(env!("CARGO_BIN_EXE_deston"))
+        .args(["check", "--config"])
+        .arg(&config_path)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("line 3: server[0].weight: must be at least 1"));
+
+    fs::remove_file(config_path).ok();
+}
@@ -1,4 +1,8 @@
 use deston::config::config::{Algorithm, Config, LayerMode};
+use deston::config::error::ConfigError;
+use deston::config::schema::{
+    ConfigFile, HealthCheckSection, LoadBalancerSection, ProbeKind, ServerSection,
+};
 use std::fs;
 use std::path::Path;
 
@@ -27,20 +31,21 @@ weight = 2
     let config_path = "/tmp/test_config.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Verify load balancer address
     assert_eq!(config.load_balancer_address.to_string(), "127.0.0.1:8080");
 
     // Verify servers
-    assert_eq!(config.servers.len(), 2);
+    let servers = config.servers();
+    assert_eq!(servers.len(), 2);
 
     // Verify first server
-    let server1 = config.servers[0].lock().unwrap();
+    let server1 = servers[0].lock().unwrap();
     assert_eq!(server1.weight, 1);
 
     // Verify second server
-    let server2 = config.servers[1].lock().unwrap();
+    let server2 = servers[1].lock().unwrap();
     assert_eq!(server2.weight, 2);
 
     // Clean up
@@ -57,6 +62,12 @@ fn test_config_algorithm_case_insensitive() {
         ("WeightedRoundRobin", Algorithm::WeightedRoundRobin),
         ("ip_hashing", Algorithm::IpHashing),
         ("IpHashing", Algorithm::IpHashing),
+        ("least_connections", Algorithm::LeastConnections),
+        ("LeastConnections", Algorithm::LeastConnections),
+        (
+            "weighted_least_connections",
+            Algorithm::WeightedLeastConnections,
+        ),
     ];
 
     for (algo_str, _expected) in test_cases {
@@ -80,7 +91,7 @@ weight = 1
         fs::write(&config_path, config_content).unwrap();
 
         // Should not panic - algorithm should be parsed correctly
-        let _config = Config::new(Path::new(&config_path));
+        let _config = Config::load(Path::new(&config_path)).unwrap();
 
         // Clean up
         fs::remove_file(&config_path).ok();
@@ -99,13 +110,13 @@ port = 3000
     let config_path = "/tmp/test_config_defaults.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Should use default load balancer address
     assert_eq!(config.load_balancer_address.host().unwrap(), "localhost");
 
     // Should have servers
-    assert!(!config.servers.is_empty());
+    assert!(!config.servers().is_empty());
 
     // Clean up
     fs::remove_file(config_path).ok();
@@ -141,12 +152,12 @@ weight = 3
     let config_path = "/tmp/test_config_multi.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
-    assert_eq!(config.servers.len(), 3);
-    assert_eq!(config.servers[0].lock().unwrap().weight, 1);
-    assert_eq!(config.servers[1].lock().unwrap().weight, 2);
-    assert_eq!(config.servers[2].lock().unwrap().weight, 3);
+    assert_eq!(config.servers().len(), 3);
+    assert_eq!(config.servers()[0].lock().unwrap().weight, 1);
+    assert_eq!(config.servers()[1].lock().unwrap().weight, 2);
+    assert_eq!(config.servers()[2].lock().unwrap().weight, 3);
 
     // Clean up
     fs::remove_file(config_path).ok();
@@ -169,7 +180,7 @@ port = 3000
     let config_path = "/tmp/test_config_layer_l4.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     assert_eq!(config.layer_mode, LayerMode::L4);
 
@@ -194,7 +205,7 @@ port = 3000
     let config_path = "/tmp/test_config_layer_l7.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     assert_eq!(config.layer_mode, LayerMode::L7);
 
@@ -236,7 +247,7 @@ port = 3000
         let config_path = format!("/tmp/test_config_layer_{}.toml", layer_str.replace("_", ""));
         fs::write(&config_path, config_content).unwrap();
 
-        let config = Config::new(Path::new(&config_path));
+        let config = Config::load(Path::new(&config_path)).unwrap();
         assert_eq!(config.layer_mode, expected_mode);
 
         // Clean up
@@ -261,7 +272,7 @@ port = 3000
     let config_path = "/tmp/test_config_layer_default.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Should default to L4
     assert_eq!(config.layer_mode, LayerMode::L4);
@@ -269,3 +280,420 @@ port = 3000
     // Clean up
     fs::remove_file(config_path).ok();
 }
+
+// Helper to load an invalid config and return its error
+fn load_error(name: &str, config_content: &str) -> ConfigError {
+    let config_path = format!("/tmp/{}.toml", name);
+    fs::write(&config_path, config_content).unwrap();
+    let err = Config::load(Path::new(&config_path)).err().unwrap();
+    fs::remove_file(&config_path).ok();
+    err
+}
+
+#[test]
+fn test_config_rejects_unknown_keys() {
+    let err = load_error(
+        "test_config_unknown_key",
+        r#"
+[load_balancer]
+port = 8080
+algoritm = "round_robin"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#,
+    );
+    assert_eq!(err.key(), Some("load_balancer.algoritm"));
+    assert_eq!(err.line(), Some(4));
+    assert!(err
+        .to_string()
+        .starts_with("line 4: load_balancer.algoritm: unknown field `algoritm`"));
+
+    let err = load_error(
+        "test_config_unknown_server_key",
+        r#"
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+
+[server.health_check]
+intervall_ms = 1000
+"#,
+    );
+    assert_eq!(err.key(), Some("server[1].health_check.intervall_ms"));
+    assert_eq!(err.line(), Some(11));
+}
+
+#[test]
+fn test_config_rejects_wrong_types() {
+    let err = load_error(
+        "test_config_wrong_type",
+        r#"
+[[server]]
+address = "127.0.0.1"
+port = "3000"
+"#,
+    );
+    assert_eq!(err.key(), Some("server[0].port"));
+    assert_eq!(err.line(), Some(4));
+    assert_eq!(
+        err.to_string(),
+        "line 4: server[0].port: invalid type: string \"3000\", expected u16"
+    );
+
+    let err = load_error(
+        "test_config_wrong_table_type",
+        "load_balancer = 8080\n\n[[server]]\nport = 3000\n",
+    );
+    assert_eq!(err.key(), Some("load_balancer"));
+    assert_eq!(err.line(), Some(1));
+}
+
+#[test]
+fn test_config_rejects_invalid_ports() {
+    for port in ["0", "70000", "-1"] {
+        let err = load_error(
+            "test_config_invalid_port",
+            &format!(
+                "[load_balancer]\nport = {}\n\n[[server]]\nport = 3000\n",
+                port
+            ),
+        );
+        assert_eq!(err.key(), Some("load_balancer.port"));
+        assert_eq!(err.line(), Some(2));
+    }
+
+    let err = load_error(
+        "test_config_zero_port",
+        "[load_balancer]\nport = 0\n\n[[server]]\nport = 3000\n",
+    );
+    assert_eq!(
+        err.to_string(),
+        "line 2: load_balancer.port: must be between 1 and 65535"
+    );
+}
+
+#[test]
+fn test_config_rejects_invalid_addresses() {
+    // Addresses with a scheme or path parse as URIs without a port
+    let err = load_error(
+        "test_config_server_address_path",
+        "[[server]]\naddress = \"http://a/b\"\nport = 3000\n",
+    );
+    assert_eq!(err.key(), Some("server[0].address"));
+    assert_eq!(err.line(), Some(2));
+
+    let err = load_error(
+        "test_config_listener_address_path",
+        "[load_balancer]\naddress = \"http://a/b\"\n\n[[server]]\nport = 3000\n",
+    );
+    assert_eq!(err.key(), Some("load_balancer.address"));
+
+    let err = load_error(
+        "test_config_empty_address",
+        "[[server]]\naddress = \"\"\nport = 3000\n",
+    );
+    assert_eq!(err.key(), Some("server[0].address"));
+}
+
+#[test]
+fn test_config_rejects_zero_weight() {
+    let err = load_error(
+        "test_config_zero_weight",
+        r#"
+[[server]]
+address = "127.0.0.1"
+port = 3000
+weight = 0
+"#,
+    );
+    assert_eq!(err.key(), Some("server[0].weight"));
+    assert_eq!(err.line(), Some(5));
+}
+
+#[test]
+fn test_config_rejects_values_breaking_the_proxy() {
+    let config_content = r#"
+[load_balancer]
+upstream_timeout_ms = 1000
+
+[outlier_detection]
+window_size = 10
+max_ejection_percent = 50
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+max_connections = 10
+"#;
+    for (setting, invalid, key, line) in [
+        (
+            "upstream_timeout_ms = 1000",
+            "upstream_timeout_ms = 0",
+            "load_balancer.upstream_timeout_ms",
+            3,
+        ),
+        (
+            "window_size = 10",
+            "window_size = 0",
+            "outlier_detection.window_size",
+            6,
+        ),
+        (
+            "max_ejection_percent = 50",
+            "max_ejection_percent = 101",
+            "outlier_detection.max_ejection_percent",
+            7,
+        ),
+        (
+            "max_connections = 10",
+            "max_connections = 0",
+            "server[0].max_connections",
+            12,
+        ),
+    ] {
+        let err = load_error(
+            "test_config_breaking_values",
+            &config_content.replace(setting, invalid),
+        );
+        assert_eq!(err.key(), Some(key));
+        assert_eq!(err.line(), Some(line));
+    }
+}
+
+#[test]
+fn test_config_rejects_reversed_status_ranges() {
+    let err = load_error(
+        "test_config_reversed_status_range",
+        r#"
+[health_check]
+type = "http"
+expected_status = [200, "300-200"]
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#,
+    );
+    assert_eq!(err.key(), Some("health_check.expected_status[1]"));
+    assert_eq!(err.line(), Some(4));
+    assert!(err
+        .to_string()
+        .ends_with("range \"300-200\" starts after it ends"));
+}
+
+#[test]
+fn test_config_rejects_invalid_tls_passthrough() {
+    // Passthrough is only read by L4 listeners
+    let err = load_error(
+        "test_config_passthrough_l7",
+        r#"
+[load_balancer]
+layer = "L7"
+tls_passthrough = true
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#,
+    );
+    assert_eq!(err.key(), Some("load_balancer.tls_passthrough"));
+    assert_eq!(err.line(), Some(4));
+
+    // The TLS session of the client can not be wrapped in another one
+    let err = load_error(
+        "test_config_passthrough_tls_server",
+        r#"
+[load_balancer]
+layer = "L4"
+tls_passthrough = true
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+tls = true
+"#,
+    );
+    assert_eq!(err.key(), Some("server[0].tls"));
+    assert_eq!(err.line(), Some(9));
+
+    // Server names only route connections with passthrough
+    let err = load_error(
+        "test_config_server_names_without_passthrough",
+        r#"
+[upstream.api]
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 3000
+server_names = ["api.example.com"]
+
+[load_balancer]
+layer = "L4"
+upstream = "api"
+"#,
+    );
+    assert_eq!(err.key(), Some("upstream.api.server[0].server_names"));
+    assert_eq!(err.line(), Some(6));
+}
+
+#[test]
+fn test_config_requires_servers() {
+    let err = load_error("test_config_no_servers", "[load_balancer]\nport = 8080\n");
+    assert_eq!(err.key(), Some("server"));
+    assert_eq!(err.line(), None);
+
+    let err = load_error("test_config_empty_servers", "server = []\n");
+    assert_eq!(err.key(), Some("server"));
+    assert_eq!(err.line(), Some(1));
+}
+
+#[test]
+fn test_config_rejects_unknown_names() {
+    let err = load_error(
+        "test_config_unknown_algorithm",
+        r#"
+[load_balancer]
+algorithm = "fastest"
+
+[[server]]
+port = 3000
+"#,
+    );
+    assert_eq!(err.key(), Some("load_balancer.algorithm"));
+    assert_eq!(
+        err.to_string(),
+        "line 3: load_balancer.algorithm: unknown value \"fastest\""
+    );
+
+    let err = load_error(
+        "test_config_unknown_layer",
+        r#"
+[load_balancer]
+layer = "L5"
+
+[[server]]
+port = 3000
+"#,
+    );
+    assert_eq!(err.key(), Some("load_balancer.layer"));
+    assert_eq!(err.line(), Some(3));
+}
+
+#[test]
+fn test_config_read_and_parse_errors() {
+    let err = Config::load(Path::new("/nonexistent/deston.toml"))
+        .err()
+        .unwrap();
+    assert!(matches!(err, ConfigError::Read(_)));
+
+    let err = load_error("test_config_bad_toml", "[[server]\nport = ");
+    assert!(matches!(err, ConfigError::Parse(_)));
+    assert_eq!(err.key(), None);
+}
+
+#[test]
+fn test_config_schema_defaults() {
+    let file = ConfigFile::parse("[[server]]\n").unwrap();
+    assert_eq!(file.load_balancer, None);
+    // A missing [load_balancer] is the default listener
+    let listeners = file.listeners().unwrap();
+    let (load_balancer, path, _) = &listeners[0];
+    assert_eq!(path, "load_balancer");
+    assert_eq!(load_balancer.port, 8080);
+    assert_eq!(load_balancer.algorithm, None);
+    assert_eq!(
+        *load_balancer,
+        LoadBalancerSection {
+            server: file.server.clone(),
+            ..LoadBalancerSection::default()
+        }
+    );
+    assert_eq!(file.server, vec![ServerSection::default()]);
+    assert_eq!(file.server[0].port, 3000);
+    assert_eq!(file.server[0].weight, 1);
+    // Health check keys not set are inherited
+    assert_eq!(file.health_check, HealthCheckSection::default());
+    assert_eq!(file.health_check.interval_ms, None);
+}
+
+#[test]
+fn test_config_dump_round_trip() {
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 8443
+algorithm = "LeastConnections"
+layer = "l7"
+queue_size = 5
+
+[load_balancer.tls]
+client_ca = "/etc/deston/ca.pem"
+
+[[load_balancer.tls.certificate]]
+cert_chain = "/etc/deston/api.pem"
+private_key = "/etc/deston/api.key"
+server_names = ["api.example.com"]
+
+[connection_pool]
+max_idle = 4
+
+[health_check]
+type = "http"
+path = "/healthz"
+expected_status = [200, "300-399"]
+body_regex = "ok|up"
+
+[outlier_detection]
+enabled = true
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+weight = 3
+protocol = "grpc"
+tls = true
+tls_server_name = "backend.internal"
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+
+[server.health_check]
+type = "tcp"
+"#;
+    let config_path = "/tmp/test_config_dump.toml";
+    fs::write(config_path, config_content).unwrap();
+    let config = Config::load(Path::new(config_path)).unwrap();
+    fs::remove_file(config_path).ok();
+
+    // Every effective setting is dumped in its canonical form
+    let dump = config.to_file().to_toml();
+    let file = ConfigFile::parse(&dump).unwrap();
+    let load_balancer = file.load_balancer.as_ref().unwrap();
+    assert_eq!(load_balancer.algorithm, Some(Algorithm::LeastConnections));
+    assert!(dump.contains("algorithm = \"least_connections\""));
+    assert!(dump.contains("layer = \"L7\""));
+    assert!(dump.contains("protocol = \"h2c\""));
+    assert_eq!(load_balancer.queue_size, 5);
+    assert_eq!(file.connection_pool.max_idle, 4);
+    assert_eq!(file.health_check.path.as_deref(), Some("/healthz"));
+    assert_eq!(file.health_check.interval_ms, Some(5000));
+    assert_eq!(file.server.len(), 2);
+    assert_eq!(file.server[0].weight, 3);
+    // Server health checks are dumped with the inherited settings
+    let server_health_check = file.server[1].health_check.as_ref().unwrap();
+    assert_eq!(server_health_check.kind, Some(ProbeKind::Tcp));
+    // The interval is only dumped globally
+    assert_eq!(server_health_check.interval_ms, None);
+
+    // The dump loads into the same config
+    let reloaded = Config::from_file(&file).unwrap();
+    assert_eq!(reloaded.to_file(), file);
+    assert_eq!(reloaded.tls, config.tls);
+    assert_eq!(reloaded.health_check, config.health_check);
+}
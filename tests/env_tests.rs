@@ -0,0 +1,216 @@
+use deston::config::config::{Config, LayerMode};
+use deston::config::env::{apply_overrides, interpolate};
+use std::collections::HashMap;
+use std::fs;
+use toml::{Table, Value};
+
+// Helper to look up variables in a map instead of the environment
+fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<HashMap<_, _>>();
+    move |name| vars.get(name).cloned()
+}
+
+// Helper to build overriding variables
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_interpolate_variables() {
+    let lookup = lookup(&[("HOST", "10.0.0.5"), ("PORT", "3001"), ("EMPTY", "")]);
+    let contents = r#"
+address = "${HOST}"
+port = ${PORT:-3000}
+weight = ${WEIGHT:-2}
+protocol = "${EMPTY:-http1}"
+error_body = "$${status}"
+"#;
+    assert_eq!(
+        interpolate(contents, lookup).unwrap(),
+        r#"
+address = "10.0.0.5"
+port = 3001
+weight = 2
+protocol = "http1"
+error_body = "${status}"
+"#
+    );
+}
+
+#[test]
+fn test_interpolate_errors() {
+    let err = interpolate("[[server]]\naddress = \"${HOST}\"\n", lookup(&[])).unwrap_err();
+    assert_eq!(err.key(), Some("${HOST}"));
+    assert_eq!(err.line(), Some(2));
+    assert_eq!(
+        err.to_string(),
+        "line 2: ${HOST}: environment variable is not set"
+    );
+
+    let err = interpolate("port = 1\naddress = \"${HOST\"\n", lookup(&[])).unwrap_err();
+    assert_eq!(err.line(), Some(2));
+    assert!(err.to_string().ends_with("missing closing }"));
+
+    let err = interpolate("address = \"${1HOST}\"\n", lookup(&[])).unwrap_err();
+    assert_eq!(err.key(), Some("${1HOST}"));
+
+    // Keys can not be replaced
+    let err = interpolate("[load_balancer]\n${KEY} = 1\n", lookup(&[("KEY", "port")])).unwrap_err();
+    assert_eq!(err.key(), Some("${KEY}"));
+    assert_eq!(err.line(), Some(2));
+    assert!(err.to_string().ends_with("can not be used in keys"));
+    let err = interpolate("set = { ${KEY} = \"a\" }\n", lookup(&[("KEY", "x")])).unwrap_err();
+    assert_eq!(err.key(), Some("${KEY}"));
+
+    // Literal strings can not escape quotes
+    let err = interpolate("address = '${HOST}'\n", lookup(&[("HOST", "a'b")])).unwrap_err();
+    assert_eq!(err.key(), Some("${HOST}"));
+    assert!(interpolate("address = '''${HOST}'''\n", lookup(&[("HOST", "a'''b")])).is_err());
+}
+
+#[test]
+fn test_interpolate_escapes_values() {
+    let lookup = lookup(&[
+        ("BODY", "say \"hi\"\\\nport = 1"),
+        ("PATH", "C:\\temp"),
+        ("WORDS", "not a number"),
+        ("PORT", "3001"),
+    ]);
+    let contents = r#"
+# ${UNSET} in comments is left as is
+error_body = "${BODY}" # ${UNSET}
+literal = '${PATH}'
+multi = '''${PATH}'''
+bare = ${WORDS}
+array = [${PORT}, "${PATH}"]
+table = { port = ${PORT} }
+"#;
+    let interpolated = interpolate(contents, lookup).unwrap();
+    let values = interpolated.parse::<Table>().unwrap();
+    // Values are strings of the value, they do not add keys
+    assert_eq!(
+        values["error_body"],
+        Value::String("say \"hi\"\\\nport = 1".to_owned())
+    );
+    assert!(!values.contains_key("port"));
+    assert_eq!(values["literal"], Value::String("C:\\temp".to_owned()));
+    assert_eq!(values["multi"], Value::String("C:\\temp".to_owned()));
+    assert_eq!(values["bare"], Value::String("not a number".to_owned()));
+    assert_eq!(values["array"][0], Value::Integer(3001));
+    assert_eq!(values["array"][1], Value::String("C:\\temp".to_owned()));
+    assert_eq!(values["table"]["port"], Value::Integer(3001));
+    assert!(interpolated.contains("# ${UNSET} in comments"));
+}
+
+#[test]
+fn test_apply_overrides() {
+    let mut values = r#"
+[load_balancer]
+port = 8080
+
+[[server]]
+address = "127.0.0.1"
+
+[[server]]
+address = "127.0.0.1"
+"#
+    .parse::<Table>()
+    .unwrap();
+    apply_overrides(
+        &mut values,
+        vars(&[
+            ("DESTON_LOAD_BALANCER__PORT", "9000"),
+            ("DESTON_LOAD_BALANCER__LAYER", "L7"),
+            ("DESTON_SERVER__1__ADDRESS", "10.0.0.6"),
+            ("DESTON_SERVER__1__HEALTH_CHECK__ENABLED", "false"),
+            ("DESTON_HEALTH_CHECK__EXPECTED_STATUS", "[200, \"300-399\"]"),
+            ("HOME", "/root"),
+            ("DESTON_LOG", "debug"),
+        ]),
+    )
+    .unwrap();
+
+    let load_balancer = values["load_balancer"].as_table().unwrap();
+    assert_eq!(load_balancer["port"], Value::Integer(9000));
+    assert_eq!(load_balancer["layer"], Value::String("L7".to_owned()));
+    let server = values["server"].as_array().unwrap()[1].as_table().unwrap();
+    assert_eq!(server["address"], Value::String("10.0.0.6".to_owned()));
+    assert_eq!(server["health_check"]["enabled"], Value::Boolean(false));
+    // Missing sections are created
+    assert_eq!(
+        values["health_check"]["expected_status"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(!values.contains_key("home"));
+    // Variables not naming a section are ignored
+    assert!(!values.contains_key("log"));
+}
+
+#[test]
+fn test_apply_overrides_errors() {
+    let mut values = "[[server]]\nport = 3000\n".parse::<Table>().unwrap();
+    let err =
+        apply_overrides(&mut values, vars(&[("DESTON_SERVER__2__PORT", "3001")])).unwrap_err();
+    assert_eq!(err.key(), Some("DESTON_SERVER__2__PORT"));
+    assert_eq!(
+        err.to_string(),
+        "DESTON_SERVER__2__PORT: index 2 is out of range, server has 1 entries"
+    );
+
+    let err = apply_overrides(&mut values, vars(&[("DESTON_SERVER__PORT", "3001")])).unwrap_err();
+    assert_eq!(err.key(), Some("DESTON_SERVER__PORT"));
+
+    let err = apply_overrides(&mut values, vars(&[("DESTON_", "3001")])).unwrap_err();
+    assert_eq!(err.key(), Some("DESTON_"));
+}
+
+// The only test of this file reading the process environment
+#[test]
+fn test_config_from_environment() {
+    std::env::set_var("TEST_ENV_BACKEND_ADDRESS", "127.0.0.2");
+    std::env::set_var("DESTON_LOAD_BALANCER__PORT", "9090");
+    std::env::set_var("DESTON_SERVER__0__WEIGHT", "4");
+
+    let config_path = std::env::temp_dir().join("test_config_from_environment.toml");
+    fs::write(
+        &config_path,
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 8080
+layer = "${TEST_ENV_LAYER:-L7}"
+
+[[server]]
+address = "${TEST_ENV_BACKEND_ADDRESS}"
+port = ${TEST_ENV_BACKEND_PORT:-3005}
+"#,
+    )
+    .unwrap();
+    let config = Config::load(&config_path).unwrap();
+    assert_eq!(config.load_balancer_address.to_string(), "127.0.0.1:9090");
+    assert_eq!(config.layer_mode, LayerMode::L7);
+    let servers = config.servers();
+    let server = servers[0].lock().unwrap();
+    assert_eq!(server.host(), "127.0.0.2");
+    assert_eq!(server.port(), 3005);
+    assert_eq!(server.weight, 4);
+    drop(server);
+
+    // Overrides of unknown keys are rejected
+    std::env::set_var("DESTON_LOAD_BALANCER__PROT", "9090");
+    let err = Config::load(&config_path).err().unwrap();
+    assert_eq!(err.key(), Some("load_balancer.prot"));
+
+    std::env::remove_var("DESTON_LOAD_BALANCER__PROT");
+    std::env::remove_var("DESTON_LOAD_BALANCER__PORT");
+    std::env::remove_var("DESTON_SERVER__0__WEIGHT");
+    fs::remove_file(config_path).ok();
+}
@@ -0,0 +1,313 @@
+use deston::config::config::Config;
+use deston::config::schema::ConfigFile;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::server::forwarded::{ForwardedHeaders, Forwarding, TrustedProxy};
+use deston::server::server::ClientInfo;
+use http::HeaderMap;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Helper to get a plain HTTP client connected to a listener on port 8080
+fn client(addr: &str) -> ClientInfo {
+    ClientInfo {
+        addr: addr.parse().unwrap(),
+        local_addr: "192.0.2.1:8080".parse().unwrap(),
+        proto: "http",
+        cert: None,
+    }
+}
+
+// Helper to get headers sent by a previous proxy
+fn proxied_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("forwarded", "for=198.51.100.7;proto=https".parse().unwrap());
+    headers.insert("x-forwarded-for", "198.51.100.7".parse().unwrap());
+    headers.insert("x-forwarded-proto", "https".parse().unwrap());
+    headers.insert("x-forwarded-host", "www.example.com".parse().unwrap());
+    headers.insert("x-forwarded-port", "443".parse().unwrap());
+    headers
+}
+
+// Helper to get the value of a header as a string
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).map(|value| value.to_str().unwrap())
+}
+
+// Helper to spawn an HTTP backend answering with the request headers it received
+async fn spawn_headers_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let headers: String = req
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                        .collect();
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(headers))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// Helper to send a request with extra headers and return the response body
+async fn http_get(port: u16, headers: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: app.example.com\r\n{}Connection: close\r\n\r\n",
+        headers
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.split_once("\r\n\r\n").unwrap().1.to_owned()
+}
+
+#[test]
+fn test_trusted_proxies() {
+    let network = TrustedProxy::parse("10.0.0.0/8").unwrap();
+    assert!(network.contains("10.200.1.2".parse().unwrap()));
+    assert!(!network.contains("11.0.0.1".parse().unwrap()));
+    // IPv4 clients of dual-stack listeners
+    assert!(network.contains("::ffff:10.0.0.1".parse().unwrap()));
+    assert_eq!(network.to_string(), "10.0.0.0/8");
+
+    let single = TrustedProxy::parse("192.168.1.1").unwrap();
+    assert!(single.contains("192.168.1.1".parse().unwrap()));
+    assert!(!single.contains("192.168.1.2".parse().unwrap()));
+    assert_eq!(single.to_string(), "192.168.1.1");
+
+    let ipv6 = TrustedProxy::parse("2001:db8::/32").unwrap();
+    assert!(ipv6.contains("2001:db8:1::1".parse().unwrap()));
+    assert!(!ipv6.contains("10.0.0.1".parse().unwrap()));
+
+    let any = TrustedProxy::parse("0.0.0.0/0").unwrap();
+    assert!(any.contains("203.0.113.9".parse().unwrap()));
+
+    assert!(TrustedProxy::parse("10.0.0/8").is_err());
+    assert!(TrustedProxy::parse("10.0.0.0/33").is_err());
+    assert!(TrustedProxy::parse("localhost").is_err());
+}
+
+#[test]
+fn test_forwarded_headers_replace_untrusted() {
+    let forwarding = Forwarding::default();
+    let mut headers = proxied_headers();
+    forwarding
+        .apply(
+            &mut headers,
+            &client("203.0.113.9:50000"),
+            "app.example.com",
+        )
+        .unwrap();
+
+    // Spoofed values are replaced by those of this hop
+    assert_eq!(
+        header(&headers, "forwarded"),
+        Some("for=203.0.113.9;by=\"192.0.2.1:8080\";host=app.example.com;proto=http")
+    );
+    assert_eq!(header(&headers, "x-forwarded-for"), Some("203.0.113.9"));
+    assert_eq!(header(&headers, "x-forwarded-proto"), Some("http"));
+    assert_eq!(
+        header(&headers, "x-forwarded-host"),
+        Some("app.example.com")
+    );
+    assert_eq!(header(&headers, "x-forwarded-port"), Some("8080"));
+
+    // IPv6 nodes and hosts with a port are quoted
+    let mut headers = HeaderMap::new();
+    forwarding
+        .apply(
+            &mut headers,
+            &client("[2001:db8::7]:50000"),
+            "app.example.com:8080",
+        )
+        .unwrap();
+    assert_eq!(
+        header(&headers, "forwarded"),
+        Some(
+            "for=\"[2001:db8::7]\";by=\"192.0.2.1:8080\";host=\"app.example.com:8080\";proto=http"
+        )
+    );
+    assert_eq!(header(&headers, "x-forwarded-for"), Some("2001:db8::7"));
+}
+
+#[test]
+fn test_forwarded_headers_append_trusted() {
+    let forwarding = Forwarding {
+        headers: ForwardedHeaders::All,
+        trusted_proxies: vec![TrustedProxy::parse("10.0.0.0/8").unwrap()],
+    };
+    let mut headers = proxied_headers();
+    forwarding
+        .apply(&mut headers, &client("10.0.0.5:50000"), "app.example.com")
+        .unwrap();
+
+    // The chain of the trusted proxy is appended to
+    assert_eq!(
+        header(&headers, "forwarded"),
+        Some("for=198.51.100.7;proto=https, for=10.0.0.5;by=\"192.0.2.1:8080\";host=app.example.com;proto=http")
+    );
+    assert_eq!(
+        header(&headers, "x-forwarded-for"),
+        Some("198.51.100.7, 10.0.0.5")
+    );
+    // The proxy knows the scheme, host and port of the original client
+    assert_eq!(header(&headers, "x-forwarded-proto"), Some("https"));
+    assert_eq!(
+        header(&headers, "x-forwarded-host"),
+        Some("www.example.com")
+    );
+    assert_eq!(header(&headers, "x-forwarded-port"), Some("443"));
+
+    // Headers that are not added are kept for trusted proxies only
+    let forwarding = Forwarding {
+        headers: ForwardedHeaders::Forwarded,
+        ..forwarding
+    };
+    let mut headers = proxied_headers();
+    forwarding
+        .apply(&mut headers, &client("10.0.0.5:50000"), "")
+        .unwrap();
+    assert_eq!(header(&headers, "x-forwarded-for"), Some("198.51.100.7"));
+    let mut headers = proxied_headers();
+    forwarding
+        .apply(&mut headers, &client("203.0.113.9:50000"), "")
+        .unwrap();
+    assert_eq!(
+        header(&headers, "forwarded"),
+        Some("for=203.0.113.9;by=\"192.0.2.1:8080\";proto=http")
+    );
+    assert_eq!(header(&headers, "x-forwarded-for"), None);
+}
+
+#[test]
+fn test_config_forwarded_headers() {
+    let config_content = r#"
+[load_balancer]
+layer = "L7"
+forwarded_headers = "x_forwarded"
+trusted_proxies = ["10.0.0.0/8", "2001:db8::1"]
+
+[[server]]
+"#;
+    let file = ConfigFile::parse(config_content).unwrap();
+    let config = Config::from_file(&file).unwrap();
+    assert_eq!(config.forwarding.headers, ForwardedHeaders::XForwarded);
+    assert!(config.forwarding.is_trusted("10.1.1.1".parse().unwrap()));
+    assert!(config.forwarding.is_trusted("2001:db8::1".parse().unwrap()));
+
+    // The settings are dumped
+    let dump = config.to_file().to_toml();
+    let dumped = ConfigFile::parse(&dump).unwrap();
+    let load_balancer = dumped.load_balancer.as_ref().unwrap();
+    assert_eq!(
+        load_balancer.forwarded_headers,
+        ForwardedHeaders::XForwarded
+    );
+    assert_eq!(
+        load_balancer.trusted_proxies,
+        vec!["10.0.0.0/8", "2001:db8::1"]
+    );
+
+    // Invalid proxies point to their entry
+    let invalid = config_content.replace("2001:db8::1", "10.0.0.0/40");
+    let err = ConfigFile::parse(&invalid)
+        .and_then(|file| Config::from_file(&file))
+        .map_err(|err| err.locate(&invalid))
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("load_balancer.trusted_proxies[1]"));
+    assert_eq!(err.line(), Some(5));
+}
+
+#[tokio::test]
+async fn test_layer7_forwarded_headers() {
+    spawn_headers_backend(13291).await;
+
+    let config_content = r#"
+[health_check]
+enabled = false
+
+[[listener]]
+address = "127.0.0.1"
+port = 18291
+layer = "L7"
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13291
+
+[[listener]]
+address = "127.0.0.1"
+port = 18292
+layer = "L7"
+trusted_proxies = ["127.0.0.0/8"]
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13291
+"#;
+    let file = ConfigFile::parse(config_content).unwrap();
+    let listeners = Config::listeners_from_file(&file).unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    for config in listeners {
+        let lb = Layer7::new(Arc::new(Mutex::new(config)));
+        let shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move { lb.start(shutdown_rx).await });
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let spoofed = "X-Forwarded-For: 198.51.100.7\r\nForwarded: for=198.51.100.7\r\n";
+
+    // Headers of untrusted clients are replaced
+    let body = http_get(18291, spoofed).await;
+    assert!(
+        body.contains(
+            "forwarded: for=127.0.0.1;by=\"127.0.0.1:18291\";host=app.example.com;proto=http\n"
+        ),
+        "got {}",
+        body
+    );
+    assert!(
+        body.contains("x-forwarded-for: 127.0.0.1\n"),
+        "got {}",
+        body
+    );
+    assert!(
+        body.contains("x-forwarded-host: app.example.com\n"),
+        "got {}",
+        body
+    );
+    assert!(body.contains("x-forwarded-port: 18291\n"), "got {}", body);
+
+    // Headers of trusted proxies are appended to
+    let body = http_get(18292, spoofed).await;
+    assert!(
+        body.contains("forwarded: for=198.51.100.7, for=127.0.0.1;by=\"127.0.0.1:18292\""),
+        "got {}",
+        body
+    );
+    assert!(
+        body.contains("x-forwarded-for: 198.51.100.7, 127.0.0.1\n"),
+        "got {}",
+        body
+    );
+
+    let _ = shutdown_tx.send(true);
+}
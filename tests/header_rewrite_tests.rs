@@ -0,0 +1,230 @@
+use deston::config::config::Config;
+use deston::config::schema::ConfigFile;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::server::headers::{next_request_id, HeaderTemplate, Variables};
+use deston::server::server::ClientInfo;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Helper to spawn an HTTP backend answering with the request headers it received,
+// and with headers a route may remove
+async fn spawn_headers_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let headers: String = req
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                        .collect();
+                    let response = Response::builder()
+                        .header("server", "backend")
+                        .header("x-powered-by", "test")
+                        .body(Full::new(Bytes::from(headers)))
+                        .unwrap();
+                    Ok::<_, Infallible>(response)
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// Helper to send a request with extra headers and return the raw response
+async fn http_get(port: u16, path: &str, headers: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: app.example.com\r\n{}Connection: close\r\n\r\n",
+        path, headers
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn test_header_template_variables() {
+    let client = ClientInfo {
+        addr: "10.1.2.3:45678".parse().unwrap(),
+        local_addr: "10.0.0.1:443".parse().unwrap(),
+        proto: "https",
+        cert: None,
+    };
+    let variables = Variables {
+        client: &client,
+        host: "app.example.com",
+        request_id: "abc-1",
+        upstream: "api",
+        server: "127.0.0.1:3000",
+    };
+    let render = |template: &str| {
+        HeaderTemplate::parse(template)
+            .unwrap()
+            .render(&variables)
+            .unwrap()
+    };
+    assert_eq!(render("{client_ip}:{client_port}"), "10.1.2.3:45678");
+    assert_eq!(render("{scheme}://{host}"), "https://app.example.com");
+    assert_eq!(
+        render("{upstream} via {server} ({request_id})"),
+        "api via 127.0.0.1:3000 (abc-1)"
+    );
+    // Doubled braces are literal braces
+    assert_eq!(render("{{client_ip}}"), "{client_ip}");
+    assert_eq!(render("plain"), "plain");
+
+    assert_eq!(
+        HeaderTemplate::parse("{client}").unwrap_err(),
+        "unknown variable {client}"
+    );
+    assert!(HeaderTemplate::parse("{client_ip").is_err());
+    assert!(HeaderTemplate::parse("a}b").is_err());
+
+    // Request ids are unique
+    assert_ne!(next_request_id(), next_request_id());
+}
+
+#[test]
+fn test_config_header_rules() {
+    let config_content = r#"
+[upstream.api]
+[[upstream.api.server]]
+
+[load_balancer]
+layer = "L7"
+
+[[load_balancer.route]]
+upstream = "api"
+preserve_host = true
+
+[load_balancer.route.request_headers]
+set = { "X-Request-Id" = "{request_id}" }
+add = { "X-Via" = "deston {upstream}" }
+remove = ["Cookie"]
+
+[load_balancer.route.response_headers]
+remove = ["Server"]
+"#;
+    let file = ConfigFile::parse(config_content).unwrap();
+    let config = Config::from_file(&file).unwrap();
+    let rewrite = &config.router.routes[0].rewrite;
+    assert!(rewrite.preserve_host);
+    assert_eq!(rewrite.request.set[0].0, "x-request-id");
+    assert_eq!(rewrite.request.set[0].1.as_str(), "{request_id}");
+    assert_eq!(rewrite.request.add.len(), 1);
+    assert_eq!(rewrite.request.remove, vec!["cookie"]);
+    assert_eq!(rewrite.response.remove, vec!["server"]);
+
+    // The rules are dumped with their variables
+    let dump = config.to_file().to_toml();
+    let dumped = ConfigFile::parse(&dump).unwrap();
+    let route = &dumped.load_balancer.as_ref().unwrap().route[0];
+    assert!(route.preserve_host);
+    assert_eq!(route.request_headers.set["x-request-id"], "{request_id}");
+    assert_eq!(route.response_headers.remove, vec!["server"]);
+
+    // Unknown variables point to their header
+    let invalid = config_content.replace("{request_id}", "{request}");
+    let err = ConfigFile::parse(&invalid)
+        .and_then(|file| Config::from_file(&file))
+        .map_err(|err| err.locate(&invalid))
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.key(),
+        Some("load_balancer.route[0].request_headers.set.X-Request-Id")
+    );
+    assert_eq!(err.line(), Some(13));
+    assert!(err.to_string().contains("unknown variable {request}"));
+}
+
+#[tokio::test]
+async fn test_layer7_rewrites_headers() {
+    spawn_headers_backend(13290).await;
+
+    // Environment variables and request variables do not clash
+    let config_content = r#"
+[health_check]
+enabled = false
+
+[upstream.api]
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 13290
+
+[load_balancer]
+address = "127.0.0.1"
+port = 18290
+layer = "L7"
+upstream = "api"
+
+[[load_balancer.route]]
+path_prefix = "/preserve"
+upstream = "api"
+preserve_host = true
+
+[[load_balancer.route]]
+path_prefix = "/"
+upstream = "api"
+
+[load_balancer.route.request_headers]
+set = { "X-Real-Ip" = "{client_ip}", "X-Env" = "${DESTON_TEST_UNSET_VARIABLE:-staging}" }
+add = { "X-Via" = "deston {upstream}" }
+remove = ["X-Debug"]
+
+[load_balancer.route.response_headers]
+set = { "X-Request-Id" = "{request_id}" }
+remove = ["X-Powered-By"]
+"#;
+    let mut config_path = std::env::temp_dir();
+    config_path.push("test_header_rewrites.toml");
+    fs::write(&config_path, config_content).unwrap();
+    let config = Config::load(&config_path).unwrap();
+    fs::remove_file(config_path).ok();
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = http_get(18290, "/", "X-Debug: 1\r\nX-Via: proxy\r\n").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let head = head.to_lowercase();
+    // Request headers are set, added and removed
+    assert!(body.contains("x-real-ip: 127.0.0.1\n"), "got {}", body);
+    assert!(body.contains("x-env: staging\n"), "got {}", body);
+    assert!(body.contains("x-via: proxy\n"), "got {}", body);
+    assert!(body.contains("x-via: deston api\n"), "got {}", body);
+    assert!(!body.contains("x-debug"), "got {}", body);
+    // The Host is the server address by default
+    assert!(body.contains("host: 127.0.0.1\n"), "got {}", body);
+    // Response headers are set and removed
+    assert!(head.contains("x-request-id: "), "got {}", head);
+    assert!(head.contains("server: backend"), "got {}", head);
+    assert!(!head.contains("x-powered-by"), "got {}", head);
+
+    // Requests matching no rules are forwarded as is, with the Host of the client
+    let response = http_get(18290, "/preserve", "X-Debug: 1\r\n").await;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(body.contains("host: app.example.com\n"), "got {}", body);
+    assert!(body.contains("x-debug: 1\n"), "got {}", body);
+    assert!(head.to_lowercase().contains("x-powered-by"), "got {}", head);
+
+    let _ = shutdown_tx.send(true);
+}
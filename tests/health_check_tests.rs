@@ -0,0 +1,633 @@
+use deston::config::config::Config;
+use deston::config::schema::ConfigFile;
+use deston::health_check::health_check::{HealthCheck, HealthChecker, HttpProbe, Probe};
+use deston::health_check::outlier_detection::{OutlierDetection, OutlierDetector};
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::server::server::Server;
+use hyper::Uri;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Helper to load a config from a config string
+fn load_config(name: &str, config_content: &str) -> Config {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    let config = Config::load(&config_path).unwrap();
+    fs::remove_file(config_path).ok();
+    config
+}
+
+// Helper to spawn an HTTP backend answering every request with the given status and body
+async fn spawn_http_backend(port: u16, status: &'static str, body: &'static str) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+#[test]
+fn test_record_health_check_rise_and_fall() {
+    let uri = "http://127.0.0.1:3000".parse::<Uri>().unwrap();
+    let mut server = Server::new(uri, 1000, 1);
+    assert!(server.is_alive());
+
+    // Two failures are not enough with fall = 3
+    assert!(!server.record_health_check(false, 2, 3));
+    assert!(!server.record_health_check(false, 2, 3));
+    assert!(server.is_alive());
+
+    // A success resets the streak
+    assert!(!server.record_health_check(true, 2, 3));
+    assert!(!server.record_health_check(false, 2, 3));
+    assert!(!server.record_health_check(false, 2, 3));
+    assert!(server.is_alive());
+
+    // Third consecutive failure marks the server dead
+    assert!(server.record_health_check(false, 2, 3));
+    assert!(!server.is_alive());
+    assert!(!server.is_available());
+
+    // Two consecutive successes mark it alive again
+    assert!(!server.record_health_check(true, 2, 3));
+    assert!(server.record_health_check(true, 2, 3));
+    assert!(server.is_alive());
+    assert!(server.is_available());
+}
+
+#[test]
+fn test_health_check_config() {
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 8080
+
+[health_check]
+interval_ms = 250
+timeout_ms = 100
+rise = 1
+fall = 5
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+
+    let config = load_config("test_health_check_config", config_content);
+
+    assert_eq!(
+        config.health_check,
+        HealthCheck {
+            enabled: true,
+            interval: Duration::from_millis(250),
+            timeout: Duration::from_millis(100),
+            rise: 1,
+            fall: 5,
+            probe: Probe::Tcp,
+        }
+    );
+
+    // Zero intervals, timeouts and thresholds are rejected
+    for (setting, key, line) in [
+        ("interval_ms = 250", "health_check.interval_ms", 7),
+        ("timeout_ms = 100", "health_check.timeout_ms", 8),
+        ("rise = 1", "health_check.rise", 9),
+        ("fall = 5", "health_check.fall", 10),
+    ] {
+        let (name, _) = setting.split_once(' ').unwrap();
+        let invalid = config_content.replace(setting, &format!("{} = 0", name));
+        let err = ConfigFile::parse(&invalid)
+            .and_then(|file| Config::from_file(&file))
+            .map_err(|err| err.locate(&invalid))
+            .err()
+            .unwrap();
+        assert_eq!(err.key(), Some(key));
+        assert_eq!(err.line(), Some(line));
+    }
+    let invalid = "[[server]]\n[server.health_check]\nfall = 0\n";
+    let err = ConfigFile::parse(invalid)
+        .and_then(|file| Config::from_file(&file))
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("server[0].health_check.fall"));
+}
+
+#[test]
+fn test_http_health_check_config() {
+    let config_content = r#"
+[health_check]
+type = "http"
+method = "head"
+path = "/healthz"
+host = "app.internal"
+expected_status = [204, "200-299"]
+body_contains = "ok"
+body_regex = "^ok"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+
+[server.health_check]
+path = "/status"
+fall = 1
+
+[[server]]
+address = "127.0.0.1"
+port = 3002
+
+[server.health_check]
+type = "tcp"
+"#;
+
+    let config = load_config("test_http_health_check_config", config_content);
+
+    let Probe::Http(http_probe) = &config.health_check.probe else {
+        panic!("expected http probe");
+    };
+    assert_eq!(http_probe.method, hyper::Method::HEAD);
+    assert_eq!(http_probe.path, "/healthz");
+    assert_eq!(http_probe.host.as_deref(), Some("app.internal"));
+    assert_eq!(http_probe.expected_status, vec![204..=204, 200..=299]);
+    assert_eq!(http_probe.body_contains.as_deref(), Some("ok"));
+    assert_eq!(http_probe.body_regex.as_ref().unwrap().as_str(), "^ok");
+
+    // First server uses the global settings
+    let servers = config.servers();
+    assert!(servers[0].lock().unwrap().health_check().is_none());
+
+    // Second server overrides the path and fall, keeping the rest
+    let server = servers[1].lock().unwrap();
+    let health_check = server.health_check().unwrap();
+    assert_eq!(health_check.fall, 1);
+    let Probe::Http(server_probe) = &health_check.probe else {
+        panic!("expected http probe");
+    };
+    assert_eq!(server_probe.path, "/status");
+    assert_eq!(server_probe.host.as_deref(), Some("app.internal"));
+
+    // Third server switches to tcp probes
+    let server = servers[2].lock().unwrap();
+    assert_eq!(server.health_check().unwrap().probe, Probe::Tcp);
+
+    // The interval can only be set globally
+    let invalid = config_content.replace("fall = 1", "interval_ms = 100");
+    let err = ConfigFile::parse(&invalid)
+        .and_then(|file| Config::from_file(&file))
+        .map_err(|err| err.locate(&invalid))
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("server[1].health_check.interval_ms"));
+    assert_eq!(err.line(), Some(21));
+    let invalid = r#"
+[upstream.api.health_check]
+interval_ms = 100
+
+[[upstream.api.server]]
+"#;
+    let err = ConfigFile::parse(invalid)
+        .and_then(|file| Config::from_file(&file))
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("upstream.api.health_check.interval_ms"));
+}
+
+#[test]
+fn test_http_probe_matching() {
+    let http_probe = HttpProbe {
+        expected_status: vec![200..=299, 404..=404],
+        body_contains: Some("healthy".to_owned()),
+        body_regex: Some(regex::Regex::new(r"version=\d+").unwrap()),
+        ..HttpProbe::default()
+    };
+
+    assert!(http_probe.is_expected_status(hyper::StatusCode::OK));
+    assert!(http_probe.is_expected_status(hyper::StatusCode::NOT_FOUND));
+    assert!(!http_probe.is_expected_status(hyper::StatusCode::INTERNAL_SERVER_ERROR));
+
+    assert!(http_probe.is_expected_body("healthy version=3"));
+    assert!(!http_probe.is_expected_body("healthy"));
+    assert!(!http_probe.is_expected_body("version=3"));
+}
+
+#[test]
+fn test_health_check_config_defaults() {
+    let config_content = r#"
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+
+    let config = load_config("test_health_check_defaults", config_content);
+
+    assert_eq!(config.health_check, HealthCheck::default());
+}
+
+#[tokio::test]
+async fn test_health_checker_marks_servers_down_and_up() {
+    let config_content = r#"
+[health_check]
+interval_ms = 50
+timeout_ms = 50
+rise = 2
+fall = 2
+
+[[server]]
+address = "127.0.0.1"
+port = 13200
+"#;
+
+    let config = load_config("test_health_checker", config_content);
+    let server = config.servers()[0].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let checker = HealthChecker::new(Arc::new(Mutex::new(config)));
+    let checker_handle = tokio::spawn(checker.run(shutdown_rx));
+
+    // Nothing listens on the port, so the server is marked down
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!server.lock().unwrap().is_alive());
+
+    // Once the server listens, it is marked up again
+    let _listener = TcpListener::bind("127.0.0.1:13200").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(server.lock().unwrap().is_alive());
+
+    // Checker stops on shutdown
+    let _ = shutdown_tx.send(true);
+    let result = tokio::time::timeout(Duration::from_secs(1), checker_handle).await;
+    assert!(result.is_ok(), "Health checker should stop on shutdown");
+}
+
+#[tokio::test]
+async fn test_health_checker_disabled() {
+    let config_content = r#"
+[health_check]
+enabled = false
+interval_ms = 50
+fall = 1
+
+[[server]]
+address = "127.0.0.1"
+port = 13201
+"#;
+
+    let config = load_config("test_health_checker_disabled", config_content);
+    let server = config.servers()[0].clone();
+    let config = Arc::new(Mutex::new(config));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let checker = HealthChecker::new(config.clone());
+    tokio::spawn(checker.run(shutdown_rx));
+
+    // Disabled checker leaves servers alive
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(server.lock().unwrap().is_alive());
+
+    // Checks enabled by a reload start without a restart
+    let enabled = load_config(
+        "test_health_checker_enabled",
+        &config_content.replace("enabled = false", "enabled = true"),
+    );
+    config.lock().unwrap().apply(enabled).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!server.lock().unwrap().is_alive());
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_http_health_checker_matches_status_and_body() {
+    spawn_http_backend(13210, "200 OK", "status: ok").await;
+    spawn_http_backend(13211, "500 Internal Server Error", "status: ok").await;
+    spawn_http_backend(13212, "200 OK", "status: degraded").await;
+
+    let config_content = r#"
+[health_check]
+type = "http"
+path = "/health"
+interval_ms = 50
+timeout_ms = 200
+fall = 1
+body_contains = "ok"
+
+[[server]]
+address = "127.0.0.1"
+port = 13210
+
+[[server]]
+address = "127.0.0.1"
+port = 13211
+
+[[server]]
+address = "127.0.0.1"
+port = 13212
+"#;
+
+    let config = load_config("test_http_health_checker", config_content);
+    let servers = config.servers().clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let checker = HealthChecker::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(checker.run(shutdown_rx));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Only the server with an expected status and body stays alive
+    assert!(servers[0].lock().unwrap().is_alive());
+    assert!(!servers[1].lock().unwrap().is_alive());
+    assert!(!servers[2].lock().unwrap().is_alive());
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[test]
+fn test_outlier_detection_config() {
+    let config_content = r#"
+[outlier_detection]
+enabled = true
+consecutive_failures = 3
+failure_rate_percent = 40
+window_size = 10
+base_ejection_ms = 1000
+max_ejection_ms = 8000
+max_ejection_percent = 30
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+
+    let config = load_config("test_outlier_detection_config", config_content);
+
+    assert_eq!(
+        config.outlier_detection,
+        OutlierDetection {
+            enabled: true,
+            consecutive_failures: 3,
+            failure_rate_percent: 40,
+            window_size: 10,
+            base_ejection: Duration::from_millis(1000),
+            max_ejection: Duration::from_millis(8000),
+            max_ejection_percent: 30,
+        }
+    );
+}
+
+#[test]
+fn test_outlier_ejection_duration_grows_exponentially() {
+    let settings = OutlierDetection {
+        base_ejection: Duration::from_secs(10),
+        max_ejection: Duration::from_secs(50),
+        ..OutlierDetection::default()
+    };
+
+    assert_eq!(settings.ejection_duration(0), Duration::from_secs(10));
+    assert_eq!(settings.ejection_duration(1), Duration::from_secs(20));
+    assert_eq!(settings.ejection_duration(2), Duration::from_secs(40));
+    assert_eq!(settings.ejection_duration(3), Duration::from_secs(50));
+    assert_eq!(settings.ejection_duration(40), Duration::from_secs(50));
+}
+
+#[test]
+fn test_outlier_detector_ejects_after_consecutive_failures() {
+    let config_content = r#"
+[outlier_detection]
+enabled = true
+consecutive_failures = 3
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#;
+
+    let config = Arc::new(Mutex::new(load_config(
+        "test_outlier_consecutive",
+        config_content,
+    )));
+    let server = config.lock().unwrap().servers()[0].clone();
+
+    // A success in between resets the consecutive failures
+    OutlierDetector::report(&config, &server, false);
+    OutlierDetector::report(&config, &server, false);
+    OutlierDetector::report(&config, &server, true);
+    OutlierDetector::report(&config, &server, false);
+    OutlierDetector::report(&config, &server, false);
+    assert!(!server.lock().unwrap().is_ejected());
+
+    OutlierDetector::report(&config, &server, false);
+    let server = server.lock().unwrap();
+    assert!(server.is_ejected());
+    assert!(!server.is_available());
+    assert_eq!(server.ejections(), 1);
+    assert_eq!(server.successful_connections(), 1);
+    assert_eq!(server.failed_connections(), 5);
+}
+
+#[tokio::test]
+async fn test_outlier_ejection_expiry_wakes_queued_connections() {
+    let config_content = r#"
+[load_balancer]
+queue_timeout_ms = 2000
+
+[outlier_detection]
+enabled = true
+consecutive_failures = 1
+base_ejection_ms = 200
+max_ejection_percent = 100
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+
+    let config = Arc::new(Mutex::new(load_config(
+        "test_outlier_ejection_expiry",
+        config_content,
+    )));
+    let (server, queue) = {
+        let config = config.lock().unwrap();
+        (config.servers()[0].clone(), config.queue.clone())
+    };
+    OutlierDetector::report(&config, &server, false);
+    assert!(server.lock().unwrap().is_ejected());
+
+    // The queued connection gets the server as soon as its ejection expires
+    let start = tokio::time::Instant::now();
+    let picked = queue
+        .wait(|| server.lock().unwrap().is_available().then_some(()))
+        .await;
+    assert_eq!(picked, Some(()));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn test_outlier_detector_ejects_on_failure_rate() {
+    let config_content = r#"
+[outlier_detection]
+enabled = true
+consecutive_failures = 100
+failure_rate_percent = 50
+window_size = 4
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#;
+
+    let config = Arc::new(Mutex::new(load_config(
+        "test_outlier_failure_rate",
+        config_content,
+    )));
+    let server = config.lock().unwrap().servers()[0].clone();
+
+    // Window is not full yet
+    OutlierDetector::report(&config, &server, false);
+    OutlierDetector::report(&config, &server, true);
+    OutlierDetector::report(&config, &server, false);
+    assert!(!server.lock().unwrap().is_ejected());
+
+    // Half of the latest 4 results failed
+    OutlierDetector::report(&config, &server, true);
+    OutlierDetector::report(&config, &server, false);
+    assert!(server.lock().unwrap().is_ejected());
+}
+
+#[test]
+fn test_outlier_detector_respects_max_ejection_percent() {
+    let config_content = r#"
+[outlier_detection]
+enabled = true
+consecutive_failures = 1
+max_ejection_percent = 50
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#;
+
+    let config = Arc::new(Mutex::new(load_config(
+        "test_outlier_max_percent",
+        config_content,
+    )));
+    let servers = config.lock().unwrap().servers().clone();
+
+    OutlierDetector::report(&config, &servers[0], false);
+    OutlierDetector::report(&config, &servers[1], false);
+
+    // Only one of the two servers may be ejected
+    assert!(servers[0].lock().unwrap().is_ejected());
+    assert!(!servers[1].lock().unwrap().is_ejected());
+}
+
+#[test]
+fn test_outlier_detector_disabled() {
+    let config_content = r#"
+[outlier_detection]
+consecutive_failures = 1
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#;
+
+    let config = Arc::new(Mutex::new(load_config(
+        "test_outlier_disabled",
+        config_content,
+    )));
+    let server = config.lock().unwrap().servers()[0].clone();
+
+    // Results are still counted, but the server is not ejected
+    OutlierDetector::report(&config, &server, false);
+    OutlierDetector::report(&config, &server, false);
+    assert!(!server.lock().unwrap().is_ejected());
+    assert_eq!(server.lock().unwrap().failed_connections(), 2);
+}
+
+#[tokio::test]
+async fn test_layer7_outlier_detector_ejects_on_server_errors() {
+    spawn_http_backend(13292, "500 Internal Server Error", "error").await;
+    spawn_http_backend(13293, "200 OK", "ok").await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18293
+layer = "L7"
+
+[health_check]
+enabled = false
+
+[outlier_detection]
+enabled = true
+consecutive_failures = 3
+
+[[server]]
+address = "127.0.0.1"
+port = 13292
+
+[[server]]
+address = "127.0.0.1"
+port = 13293
+"#;
+    let config = Arc::new(Mutex::new(load_config(
+        "test_layer7_outlier_server_errors",
+        config_content,
+    )));
+    let failing = config.lock().unwrap().servers()[0].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(config.clone());
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for _ in 0..10 {
+        let mut stream = TcpStream::connect(("127.0.0.1", 18293)).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+    }
+
+    // Every 5xx response counts once as a failure, so the server is ejected
+    let failing = failing.lock().unwrap();
+    assert!(failing.is_ejected());
+    assert_eq!(failing.successful_connections(), 0);
+    assert_eq!(failing.failed_connections(), 3);
+
+    let _ = shutdown_tx.send(true);
+}
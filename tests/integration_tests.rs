@@ -28,12 +28,12 @@ weight = 1
     let config_path = "/tmp/test_integration_l4.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Verify L4 configuration
     assert_eq!(config.layer_mode, LayerMode::L4);
     assert_eq!(config.load_balancer_address.to_string(), "127.0.0.1:8080");
-    assert_eq!(config.servers.len(), 2);
+    assert_eq!(config.servers().len(), 2);
 
     // Clean up
     fs::remove_file(config_path).ok();
@@ -65,12 +65,12 @@ weight = 1
     let config_path = "/tmp/test_integration_l7.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Verify L7 configuration
     assert_eq!(config.layer_mode, LayerMode::L7);
     assert_eq!(config.load_balancer_address.to_string(), "127.0.0.1:8080");
-    assert_eq!(config.servers.len(), 2);
+    assert_eq!(config.servers().len(), 2);
 
     // Clean up
     fs::remove_file(config_path).ok();
@@ -93,7 +93,7 @@ port = 3000
     let config_path = "/tmp/test_integration_backward_compat.toml";
     fs::write(config_path, config_content).unwrap();
 
-    let config = Config::new(Path::new(config_path));
+    let config = Config::load(Path::new(config_path)).unwrap();
 
     // Should default to L4
     assert_eq!(config.layer_mode, LayerMode::L4);
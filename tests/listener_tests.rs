@@ -0,0 +1,270 @@
+use deston::config::config::{Algorithm, Config, LayerMode, Overrides};
+use deston::config::reload::{reload_listeners, ReloadError};
+use deston::config::schema::ConfigFile;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const LISTENERS_CONFIG: &str = r#"
+[health_check]
+enabled = false
+
+[[listener]]
+name = "tcp"
+address = "127.0.0.1"
+port = 18260
+layer = "L4"
+algorithm = "least_connections"
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13260
+
+[[listener]]
+name = "http"
+address = "127.0.0.1"
+port = 18261
+layer = "L7"
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13261
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13262
+weight = 2
+"#;
+
+// Helper to write a config file, returns its path
+fn write_config(name: &str, config_content: &str) -> PathBuf {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    config_path
+}
+
+// Helper to spawn a TCP backend that echoes back everything it receives
+async fn spawn_echo_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+// Helper to spawn an HTTP backend answering every request with its port
+async fn spawn_http_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                let mut request = Vec::new();
+                // Read until the end of the request headers
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let body = format!("backend {}", port);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+// Helper to connect to a listener, retrying while the process starts
+async fn connect(port: u16) -> TcpStream {
+    for _ in 0..50 {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("listener on port {} did not start", port);
+}
+
+#[test]
+fn test_config_parses_listeners() {
+    let file = ConfigFile::parse(LISTENERS_CONFIG).unwrap();
+    let listeners = Config::listeners_from_file(&file).unwrap();
+    assert_eq!(listeners.len(), 2);
+
+    let tcp = &listeners[0];
+    assert_eq!(tcp.name.as_deref(), Some("tcp"));
+    assert_eq!(tcp.load_balancer_address.to_string(), "127.0.0.1:18260");
+    assert_eq!(tcp.layer_mode, LayerMode::L4);
+    assert_eq!(tcp.upstreams[0].algorithm, Algorithm::LeastConnections);
+    assert_eq!(tcp.servers().len(), 1);
+
+    let http = &listeners[1];
+    assert_eq!(http.layer_mode, LayerMode::L7);
+    assert_eq!(http.upstreams[0].algorithm, Algorithm::RoundRobin);
+    assert_eq!(http.servers().len(), 2);
+    assert_eq!(http.servers()[1].lock().unwrap().weight, 2);
+    // Shared settings apply to every listener
+    assert!(!tcp.health_check.enabled && !http.health_check.enabled);
+
+    // A file with several listeners is not a single Config
+    let err = Config::from_file(&file).err().unwrap();
+    assert_eq!(err.key(), Some("listener"));
+
+    // The dump keeps the listeners and loads into the same configs
+    let dump = Config::listeners_to_file(&listeners);
+    assert_eq!(dump.load_balancer, None);
+    assert!(dump.server.is_empty());
+    let reloaded =
+        Config::listeners_from_file(&ConfigFile::parse(&dump.to_toml()).unwrap()).unwrap();
+    assert_eq!(Config::listeners_to_file(&reloaded), dump);
+}
+
+#[test]
+fn test_config_rejects_invalid_listeners() {
+    // [load_balancer] and [[server]] can not be mixed with [[listener]]
+    let config_content = format!("[[server]]\nport = 3000\n{}", LISTENERS_CONFIG);
+    let config_path = write_config("test_listeners_with_server", &config_content);
+    let err = Config::load_listeners(&config_path, &Overrides::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("server"));
+    assert_eq!(err.line(), Some(1));
+
+    let config_content = format!("[load_balancer]\nport = 8080\n{}", LISTENERS_CONFIG);
+    fs::write(&config_path, config_content).unwrap();
+    let err = Config::load_listeners(&config_path, &Overrides::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("load_balancer"));
+
+    // Every listener needs servers
+    fs::write(&config_path, "[[listener]]\nport = 8080\n").unwrap();
+    let err = Config::load_listeners(&config_path, &Overrides::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("listener[0].server"));
+
+    // Errors point to the listener
+    let config_content = LISTENERS_CONFIG.replace("weight = 2", "weight = 0");
+    fs::write(&config_path, config_content).unwrap();
+    let err = Config::load_listeners(&config_path, &Overrides::default())
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("listener[1].server[1].weight"));
+    assert_eq!(err.line(), Some(29));
+
+    // The listener address can only be overridden with a single listener
+    fs::write(&config_path, LISTENERS_CONFIG).unwrap();
+    let overrides = Overrides {
+        port: Some(9000),
+        ..Overrides::default()
+    };
+    let err = Config::load_listeners(&config_path, &overrides)
+        .err()
+        .unwrap();
+    assert_eq!(err.key(), Some("listener"));
+
+    fs::remove_file(config_path).ok();
+}
+
+#[test]
+fn test_reload_listeners() {
+    let config_path = write_config("test_reload_listeners", LISTENERS_CONFIG);
+    let configs = Config::load_listeners(&config_path, &Overrides::default())
+        .unwrap()
+        .into_iter()
+        .map(|config| Arc::new(Mutex::new(config)))
+        .collect::<Vec<_>>();
+
+    // Listeners are reloaded in the order of the file
+    let config_content = LISTENERS_CONFIG.replace("weight = 2", "weight = 5");
+    fs::write(&config_path, config_content).unwrap();
+    reload_listeners(&configs, &config_path, &Overrides::default()).unwrap();
+    assert_eq!(
+        configs[1].lock().unwrap().servers()[1]
+            .lock()
+            .unwrap()
+            .weight,
+        5
+    );
+
+    // Adding or removing listeners needs a restart
+    let config_content = LISTENERS_CONFIG
+        .split("[[listener]]\nname = \"http\"")
+        .next()
+        .unwrap();
+    fs::write(&config_path, config_content).unwrap();
+    assert!(matches!(
+        reload_listeners(&configs, &config_path, &Overrides::default()),
+        Err(ReloadError::Invalid(err)) if err.key() == Some("listener")
+    ));
+    assert_eq!(configs[1].lock().unwrap().servers().len(), 2);
+
+    fs::remove_file(config_path).ok();
+}
+
+#[tokio::test]
+async fn test_run_starts_every_listener() {
+    spawn_echo_backend(13260).await;
+    spawn_http_backend(13261).await;
+    spawn_http_backend(13262).await;
+    let config_path = write_config("test_run_starts_every_listener", LISTENERS_CONFIG);
+    let mut process = Command::new(env!("CARGO_BIN_EXE_deston"))
+        .args(["run", "--config"])
+        .arg(&config_path)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // The L4 listener forwards raw bytes
+    let mut stream = connect(18260).await;
+    stream.write_all(b"hello").await.unwrap();
+    let mut buf = [0u8; 5];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"hello");
+    drop(stream);
+
+    // The L7 listener forwards HTTP requests to its own servers
+    let mut stream = connect(18261).await;
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+    assert!(response.ends_with("backend 13261") || response.ends_with("backend 13262"));
+
+    // Both listeners stop on the shared shutdown signal
+    let status = Command::new("kill")
+        .args(["-INT", &process.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let exit = tokio::task::spawn_blocking(move || process.wait().unwrap())
+        .await
+        .unwrap();
+    assert!(exit.success());
+    assert!(TcpStream::connect(("127.0.0.1", 18260)).await.is_err());
+    assert!(TcpStream::connect(("127.0.0.1", 18261)).await.is_err());
+
+    fs::remove_file(config_path).ok();
+}
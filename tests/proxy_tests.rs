@@ -0,0 +1,782 @@
+use deston::config::config::Config;
+use deston::load_balancer::layer4::Layer4;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::server::error::ProxyError;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Response;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::convert::Infallible;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Helper to spawn a TCP echo backend on the given port
+async fn spawn_echo_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+// Helper to spawn an HTTP backend that answers every request after a delay
+async fn spawn_http_backend(port: u16, delay: Duration) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                let mut request = Vec::new();
+                // Read until the end of the request headers
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                tokio::time::sleep(delay).await;
+                let body = format!("backend {}", port);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+// Helper to spawn a keep-alive HTTP backend, returns the number of accepted connections
+async fn spawn_keep_alive_backend(port: u16) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let accepted_clone = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            accepted_clone.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let service = service_fn(|_req| async {
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from("keep-alive"))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    accepted
+}
+
+// Helper to spawn an HTTP backend that answers with the request headers it received
+async fn spawn_headers_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let headers: String = req
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                        .collect();
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(headers))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// Helper to spawn an h2c backend answering like a gRPC service with trailers,
+// returns the number of accepted connections
+async fn spawn_grpc_backend(port: u16) -> Arc<AtomicUsize> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let accepted_clone = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            accepted_clone.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let service = service_fn(
+                    move |req: hyper::Request<hyper::body::Incoming>| async move {
+                        let te = req.headers().get("te").cloned();
+                        let mut trailers = hyper::HeaderMap::new();
+                        trailers.insert("grpc-status", "0".parse().unwrap());
+                        let body = Full::new(Bytes::from(format!("backend {}", port)))
+                            .with_trailers(async move { Some(Ok(trailers)) });
+                        let mut response = Response::new(body);
+                        if let Some(te) = te {
+                            response.headers_mut().insert("x-received-te", te);
+                        }
+                        Ok::<_, Infallible>(response)
+                    },
+                );
+                let _ = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+    accepted
+}
+
+// Helper to send a GET request through the load balancer and return the raw response
+async fn http_get(port: u16) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+// Helper to load a config from a config string
+fn load_config(name: &str, config_content: &str) -> Config {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    let config = Config::load(&config_path).unwrap();
+    fs::remove_file(config_path).ok();
+    config
+}
+
+#[tokio::test]
+async fn test_layer4_tracks_connections() {
+    spawn_echo_backend(13100).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18100
+algorithm = "least_connections"
+layer = "L4"
+
+[[server]]
+address = "127.0.0.1"
+port = 13100
+"#;
+
+    let config = load_config("test_proxy_l4_connections", config_content);
+    let server = config.servers()[0].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Connection should be counted while the client is connected
+    let mut client = TcpStream::connect("127.0.0.1:18100").await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    assert_eq!(server.lock().unwrap().connections(), 1);
+
+    // Connection should be released once the client disconnects
+    drop(client);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.lock().unwrap().connections(), 0);
+    assert_eq!(server.lock().unwrap().total_connections(), 1);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_returns_503_when_saturated() {
+    spawn_http_backend(13110, Duration::from_millis(500)).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18110
+layer = "L7"
+queue_size = 0
+
+[[server]]
+address = "127.0.0.1"
+port = 13110
+max_connections = 1
+"#;
+
+    let config = load_config("test_proxy_l7_saturated", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // First request holds the only connection of the server
+    let first = tokio::spawn(http_get(18110));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Second request can not be queued and gets a 503
+    let second = http_get(18110).await;
+    assert!(second.starts_with("HTTP/1.1 503"), "got {}", second);
+
+    let first = first.await.unwrap();
+    assert!(first.starts_with("HTTP/1.1 200"), "got {}", first);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_queues_until_connection_released() {
+    spawn_http_backend(13111, Duration::from_millis(300)).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18111
+layer = "L7"
+queue_size = 10
+queue_timeout_ms = 2000
+
+[[server]]
+address = "127.0.0.1"
+port = 13111
+max_connections = 1
+"#;
+
+    let config = load_config("test_proxy_l7_queue", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Both requests succeed, the second one waits for the first to finish
+    let first = tokio::spawn(http_get(18111));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let second = http_get(18111).await;
+    let first = first.await.unwrap();
+
+    assert!(first.starts_with("HTTP/1.1 200"), "got {}", first);
+    assert!(second.starts_with("HTTP/1.1 200"), "got {}", second);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer4_closes_connection_when_queue_times_out() {
+    spawn_echo_backend(13112).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18112
+layer = "L4"
+queue_size = 10
+queue_timeout_ms = 100
+
+[[server]]
+address = "127.0.0.1"
+port = 13112
+max_connections = 1
+"#;
+
+    let config = load_config("test_proxy_l4_queue_timeout", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // First client holds the only connection of the server
+    let mut first = TcpStream::connect("127.0.0.1:18112").await.unwrap();
+    first.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    first.read_exact(&mut buf).await.unwrap();
+
+    // Second client is closed once the queue timeout expires
+    let mut second = TcpStream::connect("127.0.0.1:18112").await.unwrap();
+    let read = tokio::time::timeout(Duration::from_secs(2), second.read(&mut buf))
+        .await
+        .expect("connection should be closed after queue timeout");
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_ejects_failing_server() {
+    spawn_http_backend(13120, Duration::ZERO).await;
+
+    // Second server refuses connections
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18120
+layer = "L7"
+
+[outlier_detection]
+enabled = true
+consecutive_failures = 1
+base_ejection_ms = 60000
+
+[[server]]
+address = "127.0.0.1"
+port = 13120
+
+[[server]]
+address = "127.0.0.1"
+port = 13121
+"#;
+
+    let config = load_config("test_proxy_l7_outlier", config_content);
+    let failing = config.servers()[1].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Round robin hits the failing server once and fails over, then it is ejected
+    for _ in 0..6 {
+        let response = http_get(18120).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+    }
+    assert!(failing.lock().unwrap().is_ejected());
+    assert_eq!(failing.lock().unwrap().failed_connections(), 1);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer4_fails_over_to_another_server() {
+    spawn_echo_backend(13130).await;
+
+    // First server refuses connections
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18130
+layer = "L4"
+max_connect_attempts = 2
+
+[[server]]
+address = "127.0.0.1"
+port = 13131
+
+[[server]]
+address = "127.0.0.1"
+port = 13130
+"#;
+
+    let config = load_config("test_proxy_l4_failover", config_content);
+    let servers = config.servers().clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Every client reaches the echo server
+    for _ in 0..3 {
+        let mut client = TcpStream::connect("127.0.0.1:18130").await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+    assert!(servers[0].lock().unwrap().failed_connections() > 0);
+    assert_eq!(servers[1].lock().unwrap().successful_connections(), 3);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_fails_over_to_another_server() {
+    spawn_http_backend(13140, Duration::ZERO).await;
+
+    // First two servers refuse connections
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18140
+layer = "L7"
+max_connect_attempts = 3
+
+[[server]]
+address = "127.0.0.1"
+port = 13141
+
+[[server]]
+address = "127.0.0.1"
+port = 13142
+
+[[server]]
+address = "127.0.0.1"
+port = 13140
+"#;
+
+    let config = load_config("test_proxy_l7_failover", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for _ in 0..3 {
+        let response = http_get(18140).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+        assert!(response.ends_with("backend 13140"), "got {}", response);
+    }
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_gives_up_after_max_connect_attempts() {
+    spawn_http_backend(13150, Duration::ZERO).await;
+
+    // First server refuses connections and only one attempt is allowed
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18150
+layer = "L7"
+max_connect_attempts = 1
+
+[[server]]
+address = "127.0.0.1"
+port = 13151
+
+[[server]]
+address = "127.0.0.1"
+port = 13150
+"#;
+
+    let config = load_config("test_proxy_l7_no_failover", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let first = http_get(18150).await;
+    let second = http_get(18150).await;
+    assert!(first.starts_with("HTTP/1.1 502"), "got {}", first);
+    assert!(second.starts_with("HTTP/1.1 200"), "got {}", second);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[test]
+fn test_proxy_error_statuses() {
+    let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+    assert_eq!(ProxyError::NoServer.status().as_u16(), 503);
+    assert_eq!(ProxyError::Connect(refused).status().as_u16(), 502);
+    assert_eq!(ProxyError::Timeout.status().as_u16(), 504);
+
+    let response = ProxyError::Timeout.to_response("{status}: {reason}", "text/plain");
+    assert_eq!(response.status().as_u16(), 504);
+    assert_eq!(response.headers()["content-type"], "text/plain");
+}
+
+#[tokio::test]
+async fn test_layer7_returns_504_on_upstream_timeout() {
+    spawn_http_backend(13160, Duration::from_millis(500)).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18160
+layer = "L7"
+upstream_timeout_ms = 100
+
+[[server]]
+address = "127.0.0.1"
+port = 13160
+"#;
+
+    let config = load_config("test_proxy_l7_timeout", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = http_get(18160).await;
+    assert!(response.starts_with("HTTP/1.1 504"), "got {}", response);
+    assert!(
+        response.ends_with("504 Gateway Timeout"),
+        "got {}",
+        response
+    );
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_uses_configured_error_body() {
+    // Nothing listens on the server port
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18161
+layer = "L7"
+error_body = "<h1>{status} {reason}</h1>"
+error_content_type = "text/html"
+
+[[server]]
+address = "127.0.0.1"
+port = 13161
+"#;
+
+    let config = load_config("test_proxy_l7_error_body", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = http_get(18161).await;
+    assert!(response.starts_with("HTTP/1.1 502"), "got {}", response);
+    assert!(
+        response.to_lowercase().contains("content-type: text/html"),
+        "got {}",
+        response
+    );
+    assert!(
+        response.ends_with("<h1>502 Bad Gateway</h1>"),
+        "got {}",
+        response
+    );
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_reuses_pooled_connections() {
+    let accepted = spawn_keep_alive_backend(13170).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18170
+layer = "L7"
+
+[[server]]
+address = "127.0.0.1"
+port = 13170
+"#;
+
+    let config = load_config("test_proxy_l7_pool", config_content);
+    let server = config.servers()[0].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Every client closes its connection, the server connection is reused anyway
+    for _ in 0..5 {
+        let response = http_get(18170).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+        assert!(response.ends_with("keep-alive"), "got {}", response);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+    assert_eq!(server.lock().unwrap().idle_connections(), 1);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_pool_disabled_and_idle_timeout() {
+    let accepted_disabled = spawn_keep_alive_backend(13171).await;
+    let accepted_timeout = spawn_keep_alive_backend(13172).await;
+
+    let config_disabled = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18171
+layer = "L7"
+
+[connection_pool]
+max_idle = 0
+
+[[server]]
+address = "127.0.0.1"
+port = 13171
+"#;
+    let config_timeout = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18172
+layer = "L7"
+
+[connection_pool]
+idle_timeout_ms = 100
+
+[[server]]
+address = "127.0.0.1"
+port = 13172
+"#;
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    for (name, content) in [
+        ("test_proxy_l7_pool_disabled", config_disabled),
+        ("test_proxy_l7_pool_timeout", config_timeout),
+    ] {
+        let config = load_config(name, content);
+        let lb = Layer7::new(Arc::new(Mutex::new(config)));
+        let shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move { lb.start(shutdown_rx).await });
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Without pooling every request opens a new connection
+    for _ in 0..3 {
+        assert!(http_get(18171).await.starts_with("HTTP/1.1 200"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(accepted_disabled.load(Ordering::SeqCst), 3);
+
+    // Connections idle for longer than idle_timeout_ms are not reused
+    assert!(http_get(18172).await.starts_with("HTTP/1.1 200"));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(http_get(18172).await.starts_with("HTTP/1.1 200"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(http_get(18172).await.starts_with("HTTP/1.1 200"));
+    assert_eq!(accepted_timeout.load(Ordering::SeqCst), 2);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_serves_http2_prior_knowledge() {
+    spawn_headers_backend(13180).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18180
+layer = "L7"
+
+[[server]]
+address = "127.0.0.1"
+port = 13180
+"#;
+
+    let config = load_config("test_proxy_l7_http2", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Connect with HTTP/2 prior knowledge (h2c)
+    let stream = TcpStream::connect(("127.0.0.1", 18180)).await.unwrap();
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+
+    for _ in 0..2 {
+        let req = hyper::Request::get("http://localhost:18180/h2")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender.send_request(req).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.version(), hyper::Version::HTTP_2);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let headers = String::from_utf8(body.to_vec()).unwrap();
+        assert!(headers.contains("host: 127.0.0.1\n"), "got {}", headers);
+        assert!(headers.contains("proto=http"), "got {}", headers);
+        assert!(!headers.contains("http1"), "got {}", headers);
+    }
+
+    // HTTP/1.1 is still served on the same listener
+    let response = http_get(18180).await;
+    assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+    assert!(response.contains("proto=http"), "got {}", response);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_forwards_grpc_to_h2c_servers() {
+    let accepted_1 = spawn_grpc_backend(13190).await;
+    let accepted_2 = spawn_grpc_backend(13191).await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18190
+layer = "L7"
+algorithm = "round_robin"
+
+[[server]]
+address = "127.0.0.1"
+port = 13190
+protocol = "h2c"
+
+[[server]]
+address = "127.0.0.1"
+port = 13191
+protocol = "h2c"
+"#;
+
+    let config = load_config("test_proxy_l7_grpc", config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A single client connection sends concurrent gRPC calls
+    let stream = TcpStream::connect(("127.0.0.1", 18190)).await.unwrap();
+    let (sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+
+    let mut calls = Vec::new();
+    for _ in 0..8 {
+        let mut sender = sender.clone();
+        calls.push(tokio::spawn(async move {
+            let req = hyper::Request::post("http://localhost:18190/helloworld.Greeter/SayHello")
+                .header("content-type", "application/grpc")
+                .header("te", "trailers")
+                .body(Full::new(Bytes::from_static(b"\0\0\0\0\0")))
+                .unwrap();
+            let response = sender.send_request(req).await.unwrap();
+            assert_eq!(response.status().as_u16(), 200);
+            assert_eq!(response.headers()["x-received-te"], "trailers");
+            let collected = response.into_body().collect().await.unwrap();
+            assert_eq!(collected.trailers().unwrap()["grpc-status"], "0");
+            String::from_utf8(collected.to_bytes().to_vec()).unwrap()
+        }));
+    }
+    let mut bodies = Vec::new();
+    for call in calls {
+        bodies.push(call.await.unwrap());
+    }
+
+    // Calls are balanced per request, over one multiplexed connection per server
+    let to_first = bodies
+        .iter()
+        .filter(|body| *body == "backend 13190")
+        .count();
+    let to_second = bodies
+        .iter()
+        .filter(|body| *body == "backend 13191")
+        .count();
+    assert_eq!((to_first, to_second), (4, 4));
+    assert_eq!(accepted_1.load(Ordering::SeqCst), 1);
+    assert_eq!(accepted_2.load(Ordering::SeqCst), 1);
+
+    let _ = shutdown_tx.send(true);
+}
@@ -0,0 +1,283 @@
+use deston::config::config::{Algorithm, Config, Overrides};
+use deston::config::reload::{reload, reload_listeners, ReloadError};
+use deston::load_balancer::layer4::Layer4;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::load_balancer::queue::WaitQueue;
+use deston::server::server::ConnectionGuard;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// Helper to spawn a TCP backend that echoes back everything it receives
+async fn spawn_echo_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+// Helper to write a config file, returns its path
+fn write_config(name: &str, config_content: &str) -> PathBuf {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    config_path
+}
+
+// Helper to send data over a stream and read the echoed data
+async fn echo(stream: &mut TcpStream, data: &[u8]) -> Vec<u8> {
+    stream.write_all(data).await.unwrap();
+    let mut buf = vec![0u8; data.len()];
+    stream.read_exact(&mut buf).await.unwrap();
+    buf
+}
+
+#[test]
+fn test_reload_diffs_servers() {
+    let config_path = write_config(
+        "test_reload_diffs_servers",
+        r#"
+[load_balancer]
+algorithm = "round_robin"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#,
+    );
+    let config = Arc::new(Mutex::new(Config::load(&config_path).unwrap()));
+    let (kept, removed) = {
+        let config = config.lock().unwrap();
+        (config.servers()[0].clone(), config.servers()[1].clone())
+    };
+    let connection = ConnectionGuard::new(removed.clone());
+
+    fs::write(
+        &config_path,
+        r#"
+[load_balancer]
+algorithm = "least_connections"
+queue_size = 5
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+weight = 5
+max_connections = 10
+
+[[server]]
+address = "127.0.0.1"
+port = 3002
+"#,
+    )
+    .unwrap();
+    reload(&config, &config_path).unwrap();
+
+    let config = config.lock().unwrap();
+    // Kept servers keep their state and take the new settings
+    assert_eq!(config.servers().len(), 2);
+    assert!(Arc::ptr_eq(&config.servers()[0], &kept));
+    assert_eq!(kept.lock().unwrap().weight, 5);
+    assert_eq!(kept.lock().unwrap().max_connections(), 10);
+    // New servers are added, removed servers keep their connections
+    assert_eq!(config.servers()[1].lock().unwrap().port(), 3002);
+    assert_eq!(removed.lock().unwrap().connections(), 1);
+    drop(connection);
+    assert_eq!(removed.lock().unwrap().connections(), 0);
+    // Other settings are replaced
+    assert_eq!(config.upstreams[0].algorithm, Algorithm::LeastConnections);
+    assert_eq!(config.queue.capacity(), 5);
+
+    fs::remove_file(config_path).ok();
+}
+
+#[test]
+fn test_reload_rejects_invalid_config() {
+    let config_path = write_config(
+        "test_reload_rejects_invalid_config",
+        r#"
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#,
+    );
+    let config = Arc::new(Mutex::new(Config::load(&config_path).unwrap()));
+    let server = config.lock().unwrap().servers()[0].clone();
+
+    // Files that are not TOML are rejected
+    fs::write(&config_path, "[[server]\naddress = ").unwrap();
+    assert!(matches!(
+        reload(&config, &config_path),
+        Err(ReloadError::Invalid(_))
+    ));
+
+    // Invalid values are rejected
+    fs::write(
+        &config_path,
+        r#"
+[health_check]
+type = "http"
+body_regex = "("
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        reload(&config, &config_path),
+        Err(ReloadError::Invalid(err)) if err.key() == Some("health_check.body_regex")
+    ));
+
+    // The current config is kept
+    let config = config.lock().unwrap();
+    assert_eq!(config.servers().len(), 1);
+    assert!(Arc::ptr_eq(&config.servers()[0], &server));
+
+    fs::remove_file(config_path).ok();
+}
+
+#[test]
+fn test_reload_shares_upstream_servers() {
+    let config_content = r#"
+[upstream.api]
+
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 3000
+
+[[listener]]
+name = "api"
+port = 8080
+upstream = "api"
+
+[[listener]]
+name = "inline"
+port = 8081
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 3001
+"#;
+    let config_path = write_config("test_reload_shares_upstream_servers", config_content);
+    let configs: Vec<_> = Config::load_listeners(&config_path, &Overrides::default())
+        .unwrap()
+        .into_iter()
+        .map(|config| Arc::new(Mutex::new(config)))
+        .collect();
+    let kept = configs[0].lock().unwrap().servers()[0].clone();
+
+    // Add a server to the upstream and move the second listener to it
+    let reloaded = config_content
+        .replace(
+            "port = 3000\n",
+            "port = 3000\n\n[[upstream.api.server]]\naddress = \"127.0.0.1\"\nport = 3002\n",
+        )
+        .replace(
+            "port = 8081\n\n[[listener.server]]\naddress = \"127.0.0.1\"\nport = 3001\n",
+            "port = 8081\nupstream = \"api\"\n",
+        );
+    fs::write(&config_path, reloaded).unwrap();
+    reload_listeners(&configs, &config_path, &Overrides::default()).unwrap();
+
+    // Both listeners use the running servers and the same new server
+    let api = configs[0].lock().unwrap().servers();
+    let other = configs[1].lock().unwrap().servers();
+    assert_eq!(api.len(), 2);
+    assert!(Arc::ptr_eq(&api[0], &kept));
+    assert!(api.iter().zip(other.iter()).all(|(a, b)| Arc::ptr_eq(a, b)));
+
+    fs::remove_file(config_path).ok();
+}
+
+#[tokio::test]
+async fn test_resized_queue_counts_waiting_connections() {
+    let queue = Arc::new(WaitQueue::new(1, Duration::from_millis(300)));
+    let waiting_queue = queue.clone();
+    let waiter = tokio::spawn(async move { waiting_queue.wait(|| None::<()>).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Connections waiting before a reload still take a place in the queue
+    let resized = queue.resized(1, Duration::from_millis(300));
+    assert_eq!(resized.waiting(), 1);
+    assert_eq!(resized.wait(|| None::<()>).await, None);
+
+    // They leave the resized queue once they stop waiting
+    assert_eq!(waiter.await.unwrap(), None);
+    assert_eq!(resized.waiting(), 0);
+}
+
+#[tokio::test]
+async fn test_layer4_reload_keeps_connections() {
+    spawn_echo_backend(13250).await;
+    spawn_echo_backend(13251).await;
+    let config_path = write_config(
+        "test_layer4_reload_keeps_connections",
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18250
+layer = "L4"
+
+[[server]]
+address = "127.0.0.1"
+port = 13250
+"#,
+    );
+    let config = Arc::new(Mutex::new(Config::load(&config_path).unwrap()));
+    let removed = config.lock().unwrap().servers()[0].clone();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(config.clone());
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut old_stream = TcpStream::connect(("127.0.0.1", 18250)).await.unwrap();
+    assert_eq!(echo(&mut old_stream, b"before").await, b"before");
+
+    // Replace the server
+    fs::write(
+        &config_path,
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18250
+layer = "L4"
+
+[[server]]
+address = "127.0.0.1"
+port = 13251
+"#,
+    )
+    .unwrap();
+    reload(&config, &config_path).unwrap();
+    let added = config.lock().unwrap().servers()[0].clone();
+
+    // The established connection to the removed server keeps working
+    assert_eq!(echo(&mut old_stream, b"after").await, b"after");
+    assert_eq!(removed.lock().unwrap().connections(), 1);
+
+    // New connections go to the new server
+    let mut new_stream = TcpStream::connect(("127.0.0.1", 18250)).await.unwrap();
+    assert_eq!(echo(&mut new_stream, b"new").await, b"new");
+    assert_eq!(added.lock().unwrap().connections(), 1);
+
+    let _ = shutdown_tx.send(true);
+    fs::remove_file(config_path).ok();
+}
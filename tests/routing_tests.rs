@@ -0,0 +1,401 @@
+use deston::config::config::Config;
+use deston::config::schema::ConfigFile;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::load_balancer::router::RouteMatching;
+use http::Request;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const UPSTREAMS: &str = r#"
+[health_check]
+enabled = false
+
+[upstream.api]
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 13280
+
+[upstream.web]
+[[upstream.web.server]]
+address = "127.0.0.1"
+port = 13281
+
+[upstream.static]
+[[upstream.static.server]]
+address = "127.0.0.1"
+port = 13282
+"#;
+
+const ROUTES: &str = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18280
+layer = "L7"
+upstream = "web"
+
+[[load_balancer.route]]
+hosts = ["api.example.com", "*.api.example.org"]
+upstream = "api"
+
+[[load_balancer.route]]
+path_prefix = "/static"
+methods = ["GET", "head"]
+upstream = "static"
+
+[[load_balancer.route]]
+path_regex = "^/v[0-9]+/"
+headers = { "X-Canary" = "1" }
+upstream = "api"
+"#;
+
+// Helper to get a config from the upstreams and a listener section
+fn routes_config(listener: &str) -> Config {
+    let file = ConfigFile::parse(&format!("{}{}", UPSTREAMS, listener)).unwrap();
+    Config::from_file(&file).unwrap()
+}
+
+// Helper to get the upstream a request is routed to, None if no route matches
+fn route(config: &Config, req: Request<()>) -> Option<String> {
+    config
+        .router
+        .route(&req)
+        .map(|route| route.upstream.clone())
+}
+
+// Helper to build a request with a Host header
+fn get(host: &str, path: &str) -> Request<()> {
+    Request::get(path).header("host", host).body(()).unwrap()
+}
+
+// Helper to get the key of the error of an invalid listener section
+fn config_error(listener: &str) -> String {
+    let file = ConfigFile::parse(&format!("{}{}", UPSTREAMS, listener)).unwrap();
+    Config::from_file(&file)
+        .err()
+        .unwrap()
+        .key()
+        .unwrap()
+        .to_owned()
+}
+
+// Helper to spawn an HTTP backend answering every request with its port after a delay
+async fn spawn_http_backend(port: u16, delay: Duration) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 4096];
+                let mut request = Vec::new();
+                // Read until the end of the request headers
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                tokio::time::sleep(delay).await;
+                let body = format!("backend {}", port);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
+
+// Helper to send a request for path with host and return the raw response
+async fn http_get(port: u16, host: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn test_routes_match_requests() {
+    let config = routes_config(ROUTES);
+    assert_eq!(config.upstreams.len(), 3);
+
+    // Hosts match exactly or by wildcard, ignoring the port and case
+    assert_eq!(
+        route(&config, get("api.example.com", "/")).as_deref(),
+        Some("api")
+    );
+    assert_eq!(
+        route(&config, get("EU.api.example.org:8080", "/")).as_deref(),
+        Some("api")
+    );
+    assert_eq!(route(&config, get("api.example.org", "/")), None);
+
+    // Path prefixes match at a segment boundary, methods are case-insensitive
+    assert_eq!(
+        route(&config, get("www.example.com", "/static/app.js")).as_deref(),
+        Some("static")
+    );
+    assert_eq!(
+        route(&config, get("www.example.com", "/static")).as_deref(),
+        Some("static")
+    );
+    assert_eq!(route(&config, get("www.example.com", "/statics")), None);
+    let head = Request::head("/static/app.js").body(()).unwrap();
+    assert_eq!(route(&config, head).as_deref(), Some("static"));
+    let post = Request::post("/static/app.js").body(()).unwrap();
+    assert_eq!(route(&config, post), None);
+
+    // Regexes and headers must both match
+    let canary = Request::get("/v2/users")
+        .header("x-canary", "1")
+        .body(())
+        .unwrap();
+    assert_eq!(route(&config, canary).as_deref(), Some("api"));
+    assert_eq!(route(&config, get("www.example.com", "/v2/users")), None);
+
+    // HTTP/2 requests carry the host in the uri
+    let h2 = Request::get("https://api.example.com/").body(()).unwrap();
+    assert_eq!(route(&config, h2).as_deref(), Some("api"));
+}
+
+#[test]
+fn test_routes_longest_prefix() {
+    let listener = r#"
+[load_balancer]
+layer = "L7"
+route_matching = "longest_prefix"
+
+[[load_balancer.route]]
+path_prefix = "/"
+upstream = "web"
+
+[[load_balancer.route]]
+path_prefix = "/api/"
+upstream = "api"
+
+[[load_balancer.route]]
+path = "/api/static"
+upstream = "static"
+
+[[load_balancer.route]]
+path_prefix = "/api/static"
+upstream = "web"
+"#;
+    let config = routes_config(listener);
+    assert_eq!(config.router.matching, RouteMatching::LongestPrefix);
+    // Without servers of its own, requests matching no route are rejected
+    assert_eq!(config.upstream, None);
+
+    assert_eq!(
+        route(&config, get("a", "/index.html")).as_deref(),
+        Some("web")
+    );
+    assert_eq!(
+        route(&config, get("a", "/api/users")).as_deref(),
+        Some("api")
+    );
+    // Exact paths win over prefixes of the same length
+    assert_eq!(
+        route(&config, get("a", "/api/static")).as_deref(),
+        Some("static")
+    );
+    assert_eq!(
+        route(&config, get("a", "/api/static/x")).as_deref(),
+        Some("web")
+    );
+
+    // With first match, the catch-all route comes first
+    let config = routes_config(&listener.replace("longest_prefix", "first_match"));
+    assert_eq!(
+        route(&config, get("a", "/api/users")).as_deref(),
+        Some("web")
+    );
+}
+
+#[test]
+fn test_config_rejects_invalid_routes() {
+    let route = |route: &str| {
+        format!(
+            "[load_balancer]\nlayer = \"L7\"\n[[load_balancer.route]]\n{}\n",
+            route
+        )
+    };
+    assert_eq!(
+        config_error(&route("upstream = \"missing\"")),
+        "load_balancer.route[0].upstream"
+    );
+    assert_eq!(
+        config_error(&route(
+            "path = \"/a\"\npath_prefix = \"/b\"\nupstream = \"api\""
+        )),
+        "load_balancer.route[0]"
+    );
+    assert_eq!(
+        config_error(&route("path_prefix = \"api\"\nupstream = \"api\"")),
+        "load_balancer.route[0].path_prefix"
+    );
+    assert_eq!(
+        config_error(&route("path_regex = \"(\"\nupstream = \"api\"")),
+        "load_balancer.route[0].path_regex"
+    );
+    assert_eq!(
+        config_error(&route("methods = [\"GE T\"]\nupstream = \"api\"")),
+        "load_balancer.route[0].methods[0]"
+    );
+    // Routes need L7
+    assert_eq!(
+        config_error(
+            "[load_balancer]\nupstream = \"web\"\n[[load_balancer.route]]\nupstream = \"api\"\n"
+        ),
+        "load_balancer.route"
+    );
+}
+
+#[test]
+fn test_config_dumps_routes() {
+    let config = routes_config(ROUTES);
+    let dump = config.to_file().to_toml();
+    let file = ConfigFile::parse(&dump).unwrap();
+    let load_balancer = file.load_balancer.as_ref().unwrap();
+    assert_eq!(load_balancer.route.len(), 3);
+    assert_eq!(load_balancer.route[1].methods, vec!["GET", "HEAD"]);
+    assert_eq!(load_balancer.route[2].headers["x-canary"], "1");
+    // Every upstream used by a route is dumped
+    assert_eq!(file.upstream.len(), 3);
+
+    let reparsed = Config::from_file(&file).unwrap();
+    assert_eq!(
+        route(&reparsed, get("www.example.com", "/static/app.js")).as_deref(),
+        Some("static")
+    );
+}
+
+#[tokio::test]
+async fn test_layer7_routes_requests_to_upstreams() {
+    spawn_http_backend(13283, Duration::ZERO).await;
+    spawn_http_backend(13284, Duration::ZERO).await;
+
+    let config_content = r#"
+[health_check]
+enabled = false
+
+[upstream.api]
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 13283
+
+[upstream.web]
+[[upstream.web.server]]
+address = "127.0.0.1"
+port = 13284
+
+[load_balancer]
+address = "127.0.0.1"
+port = 18281
+layer = "L7"
+
+[[load_balancer.route]]
+hosts = ["api.example.com"]
+upstream = "api"
+
+[[load_balancer.route]]
+path_prefix = "/"
+upstream = "web"
+"#;
+    let file = ConfigFile::parse(config_content).unwrap();
+    let config = Config::from_file(&file).unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let api = http_get(18281, "api.example.com", "/users").await;
+    assert!(api.ends_with("backend 13283"), "got {}", api);
+    let web = http_get(18281, "www.example.com", "/users").await;
+    assert!(web.ends_with("backend 13284"), "got {}", web);
+
+    // Requests matching no route get a 404
+    let unrouted = http_get(18281, "www.example.com", "*").await;
+    assert!(unrouted.starts_with("HTTP/1.1 404"), "got {}", unrouted);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_queue_wakes_requests_of_other_upstreams() {
+    spawn_http_backend(13294, Duration::from_millis(200)).await;
+    spawn_http_backend(13295, Duration::from_millis(800)).await;
+
+    let config_content = r#"
+[health_check]
+enabled = false
+
+[upstream.fast]
+[[upstream.fast.server]]
+address = "127.0.0.1"
+port = 13294
+max_connections = 1
+
+[upstream.slow]
+[[upstream.slow.server]]
+address = "127.0.0.1"
+port = 13295
+max_connections = 1
+
+[load_balancer]
+address = "127.0.0.1"
+port = 18294
+layer = "L7"
+queue_size = 10
+queue_timeout_ms = 500
+
+[[load_balancer.route]]
+hosts = ["fast"]
+upstream = "fast"
+
+[[load_balancer.route]]
+hosts = ["slow"]
+upstream = "slow"
+"#;
+    let file = ConfigFile::parse(config_content).unwrap();
+    let config = Config::from_file(&file).unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Both servers are busy, a request for the slow upstream queues first
+    let fast = tokio::spawn(http_get(18294, "fast", "/"));
+    let slow = tokio::spawn(http_get(18294, "slow", "/"));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let slow_queued = tokio::spawn(http_get(18294, "slow", "/"));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // The release of the fast server reaches the queued request of its upstream
+    let fast_queued = http_get(18294, "fast", "/").await;
+    assert!(
+        fast_queued.starts_with("HTTP/1.1 200"),
+        "got {}",
+        fast_queued
+    );
+    assert!(
+        fast_queued.ends_with("backend 13294"),
+        "got {}",
+        fast_queued
+    );
+
+    for response in [fast, slow, slow_queued] {
+        let response = response.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 "), "got {}", response);
+    }
+
+    let _ = shutdown_tx.send(true);
+}
@@ -28,7 +28,7 @@ weight = 1
     config_path.push("test_shutdown_l4.toml");
     fs::write(&config_path, config_content).unwrap();
 
-    let config = Config::new(&config_path);
+    let config = Config::load(&config_path).unwrap();
     let config_arc = Arc::new(Mutex::new(config));
 
     // Create shutdown channel
@@ -83,7 +83,7 @@ weight = 1
     config_path.push("test_shutdown_l7.toml");
     fs::write(&config_path, config_content).unwrap();
 
-    let config = Config::new(&config_path);
+    let config = Config::load(&config_path).unwrap();
     let config_arc = Arc::new(Mutex::new(config));
 
     // Create shutdown channel
@@ -138,7 +138,7 @@ weight = 1
     config_path.push("test_shutdown_no_signal.toml");
     fs::write(&config_path, config_content).unwrap();
 
-    let config = Config::new(&config_path);
+    let config = Config::load(&config_path).unwrap();
     let config_arc = Arc::new(Mutex::new(config));
 
     // Create shutdown channel
@@ -0,0 +1,989 @@
+use deston::config::config::Config;
+use deston::load_balancer::layer4::Layer4;
+use deston::load_balancer::layer7::Layer7;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use deston::tls::client_hello::{parse_client_hello, ClientHello};
+use deston::tls::resolver::CertResolver;
+use deston::tls::tls::{CertificateSettings, TlsSettings};
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Response;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, ServerConfig};
+use sha2::Digest;
+use std::convert::Infallible;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+// Helper to spawn an HTTP backend that answers with the request headers it received
+async fn spawn_headers_backend(port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let headers: String = req
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                        .collect();
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(headers))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// Helper to write a self-signed certificate and its key for the given names,
+// returns the paths of the PEM files and the certificate
+fn write_self_signed(name: &str, names: &[&str]) -> (PathBuf, PathBuf, CertificateDer<'static>) {
+    let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    let certified = rcgen::generate_simple_self_signed(names).unwrap();
+    let mut cert_path = std::env::temp_dir();
+    cert_path.push(format!("{}_cert.pem", name));
+    let mut key_path = std::env::temp_dir();
+    key_path.push(format!("{}_key.pem", name));
+    fs::write(&cert_path, certified.cert.pem()).unwrap();
+    fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+    (cert_path, key_path, certified.cert.der().clone())
+}
+
+// Helper to spawn a TLS backend for the given names that answers every
+// connection with its name, returns its certificate
+async fn spawn_tls_name_backend(port: u16, name: &'static str) -> CertificateDer<'static> {
+    let certified = rcgen::generate_simple_self_signed(vec![name.to_owned()]).unwrap();
+    let cert = certified.cert.der().clone();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let server_config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                if let Ok(mut stream) = acceptor.accept(stream).await {
+                    let _ = stream.write_all(name.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                }
+            });
+        }
+    });
+    cert
+}
+
+// Helper to write a CA certificate, returns the CA and the path of its PEM file
+fn write_ca(name: &str) -> (rcgen::Certificate, rcgen::KeyPair, PathBuf) {
+    let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let key = rcgen::KeyPair::generate().unwrap();
+    let cert = params.self_signed(&key).unwrap();
+    let mut path = std::env::temp_dir();
+    path.push(format!("{}_ca.pem", name));
+    fs::write(&path, cert.pem()).unwrap();
+    (cert, key, path)
+}
+
+// Helper to write a certificate for the given names signed by a CA,
+// returns the paths of the PEM files and the certificate and key
+fn write_signed(
+    name: &str,
+    names: &[&str],
+    ca: &rcgen::Certificate,
+    ca_key: &rcgen::KeyPair,
+) -> (
+    PathBuf,
+    PathBuf,
+    CertificateDer<'static>,
+    PrivateKeyDer<'static>,
+) {
+    let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    let params = rcgen::CertificateParams::new(names).unwrap();
+    let key = rcgen::KeyPair::generate().unwrap();
+    let cert = params.signed_by(&key, ca, ca_key).unwrap();
+    let mut cert_path = std::env::temp_dir();
+    cert_path.push(format!("{}_cert.pem", name));
+    let mut key_path = std::env::temp_dir();
+    key_path.push(format!("{}_key.pem", name));
+    fs::write(&cert_path, cert.pem()).unwrap();
+    fs::write(&key_path, key.serialize_pem()).unwrap();
+    let private_key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+    (cert_path, key_path, cert.der().clone(), private_key)
+}
+
+// Helper to spawn an HTTPS backend requiring client certificates signed by ca,
+// answers with the request headers it received
+async fn spawn_mtls_headers_backend(
+    port: u16,
+    ca: CertificateDer<'static>,
+    cert: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+) {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    roots.add(ca).unwrap();
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .unwrap();
+    let server_config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(server_config));
+    let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let service = service_fn(|req: hyper::Request<hyper::body::Incoming>| async move {
+                    let headers: String = req
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| format!("{}: {}\n", name, value.to_str().unwrap()))
+                        .collect();
+                    Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(headers))))
+                });
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    });
+}
+
+// Helper to build the ClientHello a client sends for server_name
+fn client_hello_bytes(server_name: &str) -> Vec<u8> {
+    let client_config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+    let mut connection = ClientConnection::new(
+        Arc::new(client_config),
+        ServerName::try_from(server_name.to_owned()).unwrap(),
+    )
+    .unwrap();
+    let mut bytes = Vec::new();
+    connection.write_tls(&mut bytes).unwrap();
+    bytes
+}
+
+// Helper to connect to the load balancer over TLS, trusting only cert
+async fn tls_connect(
+    port: u16,
+    server_name: &str,
+    cert: CertificateDer<'static>,
+    alpn: &[&[u8]],
+) -> TlsStream<TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let mut client_config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    client_config.alpn_protocols = alpn.iter().map(|proto| proto.to_vec()).collect();
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    TlsConnector::from(Arc::new(client_config))
+        .connect(
+            ServerName::try_from(server_name.to_owned()).unwrap(),
+            stream,
+        )
+        .await
+        .unwrap()
+}
+
+// Helper to connect to the load balancer over TLS, trusting only cert and
+// presenting client_cert if set
+async fn mtls_connect(
+    port: u16,
+    server_name: &str,
+    cert: CertificateDer<'static>,
+    client_cert: Option<(CertificateDer<'static>, PrivateKeyDer<'static>)>,
+) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+    let mut client_config = match client_cert {
+        Some((cert, key)) => builder.with_client_auth_cert(vec![cert], key).unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    client_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+    TlsConnector::from(Arc::new(client_config))
+        .connect(
+            ServerName::try_from(server_name.to_owned()).unwrap(),
+            stream,
+        )
+        .await
+}
+
+// Helper to load a config from a config string
+fn load_config(name: &str, config_content: &str) -> Config {
+    let mut config_path = std::env::temp_dir();
+    config_path.push(format!("{}.toml", name));
+    fs::write(&config_path, config_content).unwrap();
+    let config = Config::load(&config_path).unwrap();
+    fs::remove_file(config_path).ok();
+    config
+}
+
+// Helper to start a Layer 7 load balancer terminating TLS with the given certificate
+async fn start_tls_layer7(
+    name: &str,
+    port: u16,
+    backend_port: u16,
+    cert_path: &Path,
+    key_path: &Path,
+) -> tokio::sync::watch::Sender<bool> {
+    let config_content = format!(
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = {}
+layer = "L7"
+
+[load_balancer.tls]
+cert_chain = "{}"
+private_key = "{}"
+
+[[server]]
+address = "127.0.0.1"
+port = {}
+"#,
+        port,
+        cert_path.display(),
+        key_path.display(),
+        backend_port
+    );
+    let config = load_config(name, &config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown_tx
+}
+
+#[test]
+fn test_tls_config_parsing() {
+    let config_content = r#"
+[load_balancer]
+layer = "L7"
+
+[load_balancer.tls]
+cert_chain = "/etc/deston/cert.pem"
+private_key = "/etc/deston/key.pem"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+    let config = load_config("test_tls_config_parsing", config_content);
+    let tls = config.tls.unwrap();
+    assert_eq!(tls.certificates.len(), 1);
+    assert_eq!(
+        tls.certificates[0].cert_chain,
+        PathBuf::from("/etc/deston/cert.pem")
+    );
+    assert_eq!(
+        tls.certificates[0].private_key,
+        PathBuf::from("/etc/deston/key.pem")
+    );
+
+    let config = load_config(
+        "test_tls_config_parsing_none",
+        "[[server]]\naddress = \"127.0.0.1\"\nport = 3000\n",
+    );
+    assert!(config.tls.is_none());
+}
+
+#[tokio::test]
+async fn test_layer7_terminates_tls() {
+    spawn_headers_backend(13200).await;
+    let (cert_path, key_path, cert) = write_self_signed("test_tls_terminate", &["localhost"]);
+    let shutdown_tx =
+        start_tls_layer7("test_tls_terminate", 18200, 13200, &cert_path, &key_path).await;
+
+    let mut stream = tls_connect(18200, "localhost", cert, &[b"http/1.1"]).await;
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    // The server may close without close_notify once the response is sent
+    let _ = stream.read_to_string(&mut response).await;
+
+    assert!(response.starts_with("HTTP/1.1 200"), "got {}", response);
+    assert!(
+        response.contains("x-forwarded-proto: https\n"),
+        "got {}",
+        response
+    );
+    assert!(response.contains("proto=https"), "got {}", response);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_closes_stalled_tls_handshakes() {
+    let (cert_path, key_path, _) = write_self_signed("test_tls_stalled", &["localhost"]);
+    let config_content = format!(
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18296
+layer = "L7"
+client_hello_timeout_ms = 200
+
+[load_balancer.tls]
+cert_chain = "{}"
+private_key = "{}"
+
+[[server]]
+address = "127.0.0.1"
+port = 13296
+"#,
+        cert_path.display(),
+        key_path.display()
+    );
+    let config = load_config("test_tls_stalled", &config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A client never starting the handshake is closed after the timeout
+    let mut stream = TcpStream::connect(("127.0.0.1", 18296)).await.unwrap();
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf)).await;
+    assert!(matches!(read, Ok(Ok(0)) | Ok(Err(_))), "got {:?}", read);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_negotiates_h2_over_tls() {
+    spawn_headers_backend(13201).await;
+    let (cert_path, key_path, cert) = write_self_signed("test_tls_h2", &["localhost"]);
+    let shutdown_tx = start_tls_layer7("test_tls_h2", 18201, 13201, &cert_path, &key_path).await;
+
+    let stream = tls_connect(18201, "localhost", cert, &[b"h2", b"http/1.1"]).await;
+    assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(conn);
+    let req = hyper::Request::get("https://localhost:18201/")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = sender.send_request(req).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.version(), hyper::Version::HTTP_2);
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let headers = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        headers.contains("x-forwarded-proto: https\n"),
+        "got {}",
+        headers
+    );
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_fails_to_start_with_missing_certificate() {
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18202
+layer = "L7"
+
+[load_balancer.tls]
+cert_chain = "/nonexistent/deston/cert.pem"
+private_key = "/nonexistent/deston/key.pem"
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+    let config = load_config("test_tls_missing_certificate", config_content);
+    let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    assert!(lb.start(shutdown_rx).await.is_err());
+}
+
+// Helper to build the settings of a certificate
+fn certificate_settings(
+    cert_path: &Path,
+    key_path: &Path,
+    server_names: &[&str],
+    default: bool,
+) -> CertificateSettings {
+    CertificateSettings {
+        cert_chain: cert_path.to_path_buf(),
+        private_key: key_path.to_path_buf(),
+        server_names: server_names.iter().map(|name| name.to_string()).collect(),
+        default,
+    }
+}
+
+#[test]
+fn test_tls_certificate_list_parsing() {
+    let config_content = r#"
+[load_balancer]
+layer = "L7"
+
+[[load_balancer.tls.certificate]]
+cert_chain = "/etc/deston/api.pem"
+private_key = "/etc/deston/api.key"
+server_names = ["api.example.com", "*.api.example.com"]
+
+[[load_balancer.tls.certificate]]
+cert_chain = "/etc/deston/web.pem"
+private_key = "/etc/deston/web.key"
+default = true
+
+[[server]]
+address = "127.0.0.1"
+port = 3000
+"#;
+    let config = load_config("test_tls_certificate_list_parsing", config_content);
+    let tls = config.tls.unwrap();
+    assert_eq!(tls.certificates.len(), 2);
+    assert_eq!(
+        tls.certificates[0].server_names,
+        vec!["api.example.com", "*.api.example.com"]
+    );
+    assert!(!tls.certificates[0].default);
+    assert!(tls.certificates[1].server_names.is_empty());
+    assert!(tls.certificates[1].default);
+}
+
+#[test]
+fn test_cert_resolver_matches_sni() {
+    let (exact_cert, exact_key, exact) = write_self_signed("test_sni_exact", &["a.example.com"]);
+    let (wild_cert, wild_key, wild) = write_self_signed("test_sni_wild", &["*.example.org"]);
+    let (default_cert, default_key, default) =
+        write_self_signed("test_sni_default", &["default.test"]);
+    let settings = TlsSettings {
+        certificates: vec![
+            certificate_settings(&exact_cert, &exact_key, &["A.example.com"], false),
+            certificate_settings(&wild_cert, &wild_key, &["*.example.org"], false),
+            certificate_settings(&default_cert, &default_key, &[], true),
+        ],
+        client_ca: None,
+    };
+    let resolver = CertResolver::new();
+    resolver.load(&settings).unwrap();
+
+    let presented = |name: Option<&str>| resolver.certificate(name).unwrap().cert[0].clone();
+    assert_eq!(presented(Some("a.example.com")), exact);
+    assert_eq!(presented(Some("A.EXAMPLE.COM")), exact);
+    assert_eq!(presented(Some("api.example.org")), wild);
+    // Wildcards match a single label only
+    assert_eq!(presented(Some("example.org")), default);
+    assert_eq!(presented(Some("a.b.example.org")), default);
+    assert_eq!(presented(Some("unknown.test")), default);
+    assert_eq!(presented(None), default);
+
+    // Without a certificate marked default, the first one is the default
+    let settings = TlsSettings {
+        certificates: vec![
+            certificate_settings(&wild_cert, &wild_key, &["*.example.org"], false),
+            certificate_settings(&exact_cert, &exact_key, &["a.example.com"], false),
+        ],
+        client_ca: None,
+    };
+    resolver.load(&settings).unwrap();
+    assert_eq!(presented(Some("unknown.test")), wild);
+
+    // A certificate failing to load keeps the current ones
+    let settings = TlsSettings {
+        certificates: vec![certificate_settings(
+            Path::new("/nonexistent/deston/cert.pem"),
+            &exact_key,
+            &[],
+            true,
+        )],
+        client_ca: None,
+    };
+    assert!(resolver.load(&settings).is_err());
+    assert_eq!(presented(Some("unknown.test")), wild);
+}
+
+#[tokio::test]
+async fn test_layer7_selects_certificate_by_sni_and_reloads() {
+    spawn_headers_backend(13210).await;
+    let (api_cert, api_key, api) = write_self_signed("test_sni_api", &["api.example.com"]);
+    let (web_cert, web_key, web) = write_self_signed("test_sni_web", &["*.example.net"]);
+
+    let config_content = format!(
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18210
+layer = "L7"
+
+[[load_balancer.tls.certificate]]
+cert_chain = "{}"
+private_key = "{}"
+server_names = ["api.example.com"]
+
+[[load_balancer.tls.certificate]]
+cert_chain = "{}"
+private_key = "{}"
+server_names = ["*.example.net"]
+
+[[server]]
+address = "127.0.0.1"
+port = 13210
+"#,
+        api_cert.display(),
+        api_key.display(),
+        web_cert.display(),
+        web_key.display()
+    );
+    let config = Arc::new(Mutex::new(load_config(
+        "test_tls_sni_layer7",
+        &config_content,
+    )));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(config.clone());
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each name gets its own certificate
+    let stream = tls_connect(18210, "www.example.net", web.clone(), &[b"http/1.1"]).await;
+    assert_eq!(stream.get_ref().1.peer_certificates().unwrap()[0], web);
+    let stream = tls_connect(18210, "api.example.com", api, &[b"http/1.1"]).await;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    // Replace the api certificate and reload
+    let (new_api_cert, new_api_key, new_api) =
+        write_self_signed("test_sni_api_new", &["api.example.com"]);
+    fs::copy(&new_api_cert, &api_cert).unwrap();
+    fs::copy(&new_api_key, &api_key).unwrap();
+    {
+        let config = config.lock().unwrap();
+        let tls = config.tls.as_ref().unwrap();
+        config.cert_resolver.load(tls).unwrap();
+    }
+
+    // The existing connection keeps working
+    let req = hyper::Request::get("/")
+        .header("host", "api.example.com")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = sender.send_request(req).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // New connections get the new certificate
+    let stream = tls_connect(18210, "api.example.com", new_api.clone(), &[b"http/1.1"]).await;
+    assert_eq!(stream.get_ref().1.peer_certificates().unwrap()[0], new_api);
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[test]
+fn test_parse_client_hello() {
+    let bytes = client_hello_bytes("API.example.com");
+
+    // The SNI hostname is found, lowercased
+    assert_eq!(
+        parse_client_hello(&bytes),
+        ClientHello::Parsed(Some("api.example.com".to_string()))
+    );
+
+    // Partial ClientHellos need more bytes
+    assert_eq!(parse_client_hello(&bytes[..3]), ClientHello::Incomplete);
+    assert_eq!(
+        parse_client_hello(&bytes[..bytes.len() - 1]),
+        ClientHello::Incomplete
+    );
+
+    // A ClientHello split across two records is reassembled
+    let record = &bytes[5..];
+    let (first, second) = record.split_at(20);
+    let mut split = vec![0x16, 0x03, 0x01, 0x00, first.len() as u8];
+    split.extend_from_slice(first);
+    split.extend_from_slice(&[0x16, 0x03, 0x01]);
+    split.extend_from_slice(&(second.len() as u16).to_be_bytes());
+    split.extend_from_slice(second);
+    assert_eq!(
+        parse_client_hello(&split),
+        ClientHello::Parsed(Some("api.example.com".to_string()))
+    );
+
+    // IP addresses are not sent as SNI
+    assert_eq!(
+        parse_client_hello(&client_hello_bytes("127.0.0.1")),
+        ClientHello::Parsed(None)
+    );
+
+    // Other protocols are not TLS
+    assert_eq!(
+        parse_client_hello(b"GET / HTTP/1.1\r\n\r\n"),
+        ClientHello::NotTls
+    );
+}
+
+#[tokio::test]
+async fn test_layer4_routes_tls_passthrough_by_sni() {
+    let api = spawn_tls_name_backend(13220, "api.example.com").await;
+    let www = spawn_tls_name_backend(13221, "www.example.net").await;
+    let default = spawn_tls_name_backend(13222, "default.example.org").await;
+
+    let config_content = r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18220
+layer = "L4"
+tls_passthrough = true
+
+[[server]]
+address = "127.0.0.1"
+port = 13220
+server_names = ["api.example.com"]
+
+[[server]]
+address = "127.0.0.1"
+port = 13221
+server_names = ["*.example.net"]
+
+[[server]]
+address = "127.0.0.1"
+port = 13222
+"#;
+    let config = load_config("test_tls_passthrough_layer4", config_content);
+    assert!(config.tls_passthrough);
+    assert_eq!(config.client_hello_timeout, Duration::from_secs(5));
+    assert_eq!(
+        config.servers()[1].lock().unwrap().server_names(),
+        ["*.example.net"]
+    );
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each hostname reaches its own server, which terminates TLS itself
+    for (server_name, cert, expected) in [
+        ("api.example.com", api, "api.example.com"),
+        ("www.example.net", www, "www.example.net"),
+        (
+            "default.example.org",
+            default.clone(),
+            "default.example.org",
+        ),
+    ] {
+        let mut stream = tls_connect(18220, server_name, cert, &[]).await;
+        let mut body = String::new();
+        stream.read_to_string(&mut body).await.unwrap();
+        assert_eq!(body, expected);
+    }
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[test]
+fn test_upstream_tls_config_parsing() {
+    let (_, _, ca_path) = write_ca("test_upstream_tls_parsing");
+    let config_content = format!(
+        r#"
+[[server]]
+address = "127.0.0.1"
+port = 3000
+tls = true
+tls_ca = "{}"
+tls_server_name = "backend.internal"
+tls_client_cert = "/etc/deston/client.pem"
+tls_client_key = "/etc/deston/client.key"
+
+[[server]]
+address = "127.0.0.1"
+port = 3001
+"#,
+        ca_path.display()
+    );
+    let mut config_path = std::env::temp_dir();
+    config_path.push("test_upstream_tls_parsing.toml");
+    fs::write(&config_path, &config_content).unwrap();
+    // Missing client certificate files fail when the config is loaded
+    let err = Config::load(&config_path).err().unwrap();
+    assert_eq!(err.key(), Some("server[0].tls"));
+    assert_eq!(err.line(), Some(5));
+    fs::remove_file(config_path).ok();
+
+    let config_content = config_content
+        .replace("tls_client_cert = \"/etc/deston/client.pem\"\n", "")
+        .replace("tls_client_key = \"/etc/deston/client.key\"\n", "");
+    let config = load_config("test_upstream_tls_parsing", &config_content);
+    let tls = config.servers()[0].lock().unwrap().tls().cloned().unwrap();
+    assert_eq!(tls.ca, Some(ca_path));
+    assert_eq!(tls.server_name.as_deref(), Some("backend.internal"));
+    assert_eq!(tls.client_cert, None);
+    assert!(config.servers()[1].lock().unwrap().tls().is_none());
+}
+
+#[tokio::test]
+async fn test_layer7_forwards_to_mtls_servers() {
+    let (ca, ca_key, ca_path) = write_ca("test_upstream_mtls");
+    let (_, _, server_cert, server_key) = write_signed(
+        "test_upstream_mtls_server",
+        &["backend.internal"],
+        &ca,
+        &ca_key,
+    );
+    let (client_cert, client_key, _, _) =
+        write_signed("test_upstream_mtls_client", &["deston"], &ca, &ca_key);
+    spawn_mtls_headers_backend(13230, ca.der().clone(), server_cert, server_key).await;
+
+    let start_layer7 = |name: &str, port: u16, client_auth: &str| {
+        let config_content = format!(
+            r#"
+[load_balancer]
+address = "127.0.0.1"
+port = {}
+layer = "L7"
+
+[[server]]
+address = "127.0.0.1"
+port = 13230
+tls = true
+tls_ca = "{}"
+tls_server_name = "backend.internal"
+{}
+"#,
+            port,
+            ca_path.display(),
+            client_auth
+        );
+        let config = load_config(name, &config_content);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let lb = Layer7::new(Arc::new(Mutex::new(config)));
+        tokio::spawn(async move { lb.start(shutdown_rx).await });
+        shutdown_tx
+    };
+    let client_auth = format!(
+        "tls_client_cert = \"{}\"\ntls_client_key = \"{}\"",
+        client_cert.display(),
+        client_key.display()
+    );
+    let mtls_shutdown = start_layer7("test_upstream_mtls_layer7", 18230, &client_auth);
+    let tls_shutdown = start_layer7("test_upstream_tls_layer7", 18231, "");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Requests reach the server over mutual TLS
+    let stream = TcpStream::connect(("127.0.0.1", 18230)).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+    let req = hyper::Request::get("/")
+        .header("host", "example.com")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = sender.send_request(req).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body).contains("host: 127.0.0.1"));
+
+    // The server rejects connections without a client certificate
+    let stream = TcpStream::connect(("127.0.0.1", 18231)).await.unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+    let req = hyper::Request::get("/")
+        .header("host", "example.com")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = sender.send_request(req).await.unwrap();
+    assert_eq!(response.status().as_u16(), 502);
+
+    let _ = mtls_shutdown.send(true);
+    let _ = tls_shutdown.send(true);
+}
+
+#[tokio::test]
+async fn test_layer4_reencrypts_to_tls_servers() {
+    let (ca, ca_key, ca_path) = write_ca("test_upstream_reencrypt");
+    let (_, _, server_cert, server_key) = write_signed(
+        "test_upstream_reencrypt_server",
+        &["backend.internal"],
+        &ca,
+        &ca_key,
+    );
+    let (client_cert, client_key, _, _) =
+        write_signed("test_upstream_reencrypt_client", &["deston"], &ca, &ca_key);
+    spawn_mtls_headers_backend(13232, ca.der().clone(), server_cert, server_key).await;
+
+    let config_content = format!(
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18232
+layer = "L4"
+
+[[server]]
+address = "127.0.0.1"
+port = 13232
+tls = true
+tls_ca = "{}"
+tls_server_name = "backend.internal"
+tls_client_cert = "{}"
+tls_client_key = "{}"
+"#,
+        ca_path.display(),
+        client_cert.display(),
+        client_key.display()
+    );
+    let config = load_config("test_upstream_reencrypt_layer4", &config_content);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer4::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Plain bytes from the client are encrypted to the server
+    let mut stream = TcpStream::connect(("127.0.0.1", 18232)).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: plain.example.com\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("host: plain.example.com"));
+
+    let _ = shutdown_tx.send(true);
+}
+
+#[tokio::test]
+async fn test_layer7_requires_client_certificates() {
+    spawn_headers_backend(13240).await;
+    let (cert_path, key_path, cert) = write_self_signed("test_frontend_mtls", &["localhost"]);
+    let (ca, ca_key, ca_path) = write_ca("test_frontend_mtls");
+    let (_, _, client_cert, client_key) = write_signed(
+        "test_frontend_mtls_client",
+        &["partner.example.com"],
+        &ca,
+        &ca_key,
+    );
+
+    let config_content = format!(
+        r#"
+[load_balancer]
+address = "127.0.0.1"
+port = 18240
+layer = "L7"
+
+[load_balancer.tls]
+cert_chain = "{}"
+private_key = "{}"
+client_ca = "{}"
+
+[[server]]
+address = "127.0.0.1"
+port = 13240
+"#,
+        cert_path.display(),
+        key_path.display(),
+        ca_path.display()
+    );
+    let config = load_config("test_frontend_mtls", &config_content);
+    assert_eq!(config.tls.as_ref().unwrap().client_ca, Some(ca_path));
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let lb = Layer7::new(Arc::new(Mutex::new(config)));
+    tokio::spawn(async move { lb.start(shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The verified identity is forwarded, spoofed identity headers are dropped
+    let stream = mtls_connect(
+        18240,
+        "localhost",
+        cert.clone(),
+        Some((client_cert.clone(), client_key)),
+    )
+    .await
+    .unwrap();
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+    let req = hyper::Request::get("/")
+        .header("host", "localhost")
+        .header("x-client-cert-subject", "CN=spoofed")
+        .body(Empty::<Bytes>::new())
+        .unwrap();
+    let response = sender.send_request(req).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("x-client-cert-subject: CN=rcgen self signed cert\n"));
+    assert!(!body.contains("CN=spoofed"));
+    assert!(body.contains("x-client-cert-san: DNS:partner.example.com\n"));
+    let fingerprint: String = sha2::Sha256::digest(client_cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    assert!(body.contains(&format!("x-client-cert-fingerprint: {}\n", fingerprint)));
+
+    // Clients without a certificate are rejected during the handshake
+    let rejected = async {
+        let stream = mtls_connect(18240, "localhost", cert, None).await?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(std::io::Error::other)?;
+        tokio::spawn(conn);
+        let req = hyper::Request::get("/")
+            .header("host", "localhost")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        sender
+            .send_request(req)
+            .await
+            .map_err(std::io::Error::other)
+    };
+    assert!(rejected.await.is_err());
+
+    let _ = shutdown_tx.send(true);
+}
@@ -0,0 +1,239 @@
+use deston::config::config::{Algorithm, Config};
+use deston::config::schema::ConfigFile;
+use deston::health_check::health_check::Probe;
+use deston::load_balancer::layer4::Layer4;
+use deston::load_balancer::load_balancer::LoadBalancer;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const UPSTREAMS_CONFIG: &str = r#"
+[health_check]
+enabled = false
+
+[upstream.api]
+algorithm = "least_connections"
+
+[upstream.api.health_check]
+enabled = true
+type = "tcp"
+fall = 1
+
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 13270
+
+[[upstream.api.server]]
+address = "127.0.0.1"
+port = 13271
+
+[upstream.api.server.health_check]
+rise = 5
+
+[upstream.static]
+
+[[upstream.static.server]]
+address = "127.0.0.1"
+port = 13272
+
+[[listener]]
+name = "api"
+port = 18270
+upstream = "api"
+
+[[listener]]
+name = "inline"
+port = 18271
+algorithm = "ip_hashing"
+
+[[listener.server]]
+address = "127.0.0.1"
+port = 13273
+"#;
+
+// Helper to get the address of a test client
+fn client_addr() -> SocketAddr {
+    "127.0.0.1:50000".parse().unwrap()
+}
+
+// Helper to get the key and line of the error of an invalid config
+fn config_error(config_content: &str) -> (String, Option<usize>) {
+    let err = ConfigFile::parse(config_content)
+        .and_then(|file| Config::listeners_from_file(&file))
+        .map_err(|err| err.locate(config_content))
+        .err()
+        .unwrap();
+    (err.key().unwrap().to_owned(), err.line())
+}
+
+#[test]
+fn test_config_parses_upstreams() {
+    let file = ConfigFile::parse(UPSTREAMS_CONFIG).unwrap();
+    let listeners = Config::listeners_from_file(&file).unwrap();
+
+    // The first listener forwards to the api upstream only
+    let api = &listeners[0];
+    assert_eq!(api.upstream.as_deref(), Some("api"));
+    assert_eq!(api.upstreams.len(), 1);
+    let upstream = api.find_upstream("api").unwrap();
+    assert_eq!(upstream.algorithm, Algorithm::LeastConnections);
+    assert!(upstream.health_check.as_ref().unwrap().enabled);
+    let servers = api.servers();
+    assert_eq!(servers.len(), 2);
+
+    // Servers inherit the health check of their upstream
+    let health_check = servers[0].lock().unwrap().health_check().cloned().unwrap();
+    assert_eq!(health_check.probe, Probe::Tcp);
+    assert_eq!(health_check.fall, 1);
+    let health_check = servers[1].lock().unwrap().health_check().cloned().unwrap();
+    assert_eq!(health_check.rise, 5);
+    assert_eq!(health_check.fall, 1);
+
+    // The second listener forwards to its own servers
+    let inline = &listeners[1];
+    assert_eq!(inline.upstream.as_deref(), Some(""));
+    assert_eq!(inline.upstreams[0].algorithm, Algorithm::IpHashing);
+    assert_eq!(inline.servers()[0].lock().unwrap().port(), 13273);
+    assert!(inline.servers()[0].lock().unwrap().health_check().is_none());
+    assert!(inline.find_upstream("api").is_none());
+}
+
+#[test]
+fn test_config_rejects_invalid_upstreams() {
+    // Unknown upstream
+    let (key, line) = config_error("[load_balancer]\nupstream = \"missing\"\n");
+    assert_eq!(key, "load_balancer.upstream");
+    assert_eq!(line, Some(2));
+
+    // Upstream with servers of its own
+    let (key, _) = config_error(
+        "[load_balancer]\nupstream = \"api\"\n[[server]]\n[upstream.api]\n[[upstream.api.server]]\n",
+    );
+    assert_eq!(key, "server");
+
+    // Algorithm is set in the upstream
+    let (key, line) = config_error(
+        "[[listener]]\nupstream = \"api\"\nalgorithm = \"ip_hashing\"\n[upstream.api]\n[[upstream.api.server]]\n",
+    );
+    assert_eq!(key, "listener[0].algorithm");
+    assert_eq!(line, Some(3));
+
+    // Upstreams are validated even if no listener uses them
+    let (key, line) =
+        config_error("[[server]]\n[upstream.api]\n[[upstream.api.server]]\nport = 0\n");
+    assert_eq!(key, "upstream.api.server[0].port");
+    assert_eq!(line, Some(4));
+    let (key, _) = config_error("[[server]]\n[upstream.api]\n");
+    assert_eq!(key, "upstream.api.server");
+}
+
+#[test]
+fn test_config_dumps_upstreams() {
+    let file = ConfigFile::parse(UPSTREAMS_CONFIG).unwrap();
+    let listeners = Config::listeners_from_file(&file).unwrap();
+    let dump = Config::listeners_to_file(&listeners).to_toml();
+    assert!(dump.contains("[upstream.api]"));
+    assert!(dump.contains("upstream = \"api\""));
+    // Unused upstreams are not part of any listener
+    assert!(!dump.contains("[upstream.static]"));
+
+    let dumped = ConfigFile::parse(&dump).unwrap();
+    let upstream = &dumped.upstream["api"];
+    assert_eq!(upstream.algorithm, Algorithm::LeastConnections);
+    // Health check settings inherited from the upstream are written once
+    assert!(upstream.server[0].health_check.is_none());
+    assert_eq!(
+        upstream.server[1].health_check.as_ref().unwrap().rise,
+        Some(5)
+    );
+    assert!(dumped.listener[0].server.is_empty());
+    assert_eq!(dumped.listener[1].algorithm, Some(Algorithm::IpHashing));
+
+    // The dump parses to the same listeners
+    let reparsed = Config::listeners_from_file(&dumped).unwrap();
+    assert_eq!(reparsed[0].upstream.as_deref(), Some("api"));
+    assert_eq!(reparsed[0].servers().len(), 2);
+}
+
+#[tokio::test]
+async fn test_pick_server_from_upstream() {
+    let file = ConfigFile::parse(UPSTREAMS_CONFIG).unwrap();
+    let config = Config::listeners_from_file(&file).unwrap().remove(0);
+    let config = Arc::new(Mutex::new(config));
+
+    // Servers are picked from the named upstream
+    let connection = Layer4::pick_server(config.clone(), "api", client_addr(), &[])
+        .await
+        .unwrap();
+    let port = connection.server().lock().unwrap().port();
+    assert!(port == 13270 || port == 13271);
+
+    // Upstreams the listener does not use have no servers
+    assert!(
+        Layer4::pick_server(config.clone(), "static", client_addr(), &[])
+            .await
+            .is_none()
+    );
+}
+
+#[test]
+fn test_apply_keeps_upstream_servers() {
+    let file = ConfigFile::parse(UPSTREAMS_CONFIG).unwrap();
+    let mut config = Config::listeners_from_file(&file).unwrap().remove(0);
+    let kept = config.servers()[0].clone();
+
+    // Reload with a different algorithm and one server less
+    let reloaded = UPSTREAMS_CONFIG
+        .replace("\"least_connections\"", "\"round_robin\"")
+        .replace(
+            "[[upstream.api.server]]\naddress = \"127.0.0.1\"\nport = 13271\n",
+            "",
+        )
+        .replace("[upstream.api.server.health_check]\nrise = 5\n", "");
+    let file = ConfigFile::parse(&reloaded).unwrap();
+    let new = Config::listeners_from_file(&file).unwrap().remove(0);
+    config.apply(new).unwrap();
+
+    let upstream = config.find_upstream("api").unwrap();
+    assert_eq!(upstream.algorithm, Algorithm::RoundRobin);
+    assert_eq!(upstream.servers.len(), 1);
+    assert!(Arc::ptr_eq(&upstream.servers[0], &kept));
+}
+
+#[tokio::test]
+async fn test_listeners_share_upstream_servers() {
+    // Both listeners use the api upstream, its servers take one connection each
+    let shared = UPSTREAMS_CONFIG
+        .replace("algorithm = \"ip_hashing\"\n", "upstream = \"api\"\n")
+        .replace(
+            "[[listener.server]]\naddress = \"127.0.0.1\"\nport = 13273\n",
+            "",
+        )
+        .replace("port = 13270\n", "port = 13270\nmax_connections = 1\n")
+        .replace("port = 13271\n", "port = 13271\nmax_connections = 1\n")
+        .replace("[[listener]]\n", "[[listener]]\nqueue_size = 0\n");
+    let file = ConfigFile::parse(&shared).unwrap();
+    let listeners = Config::listeners_from_file(&file).unwrap();
+
+    // The listeners use the same server objects
+    let (api, other) = (listeners[0].servers(), listeners[1].servers());
+    assert_eq!(api.len(), 2);
+    assert!(api.iter().zip(other.iter()).all(|(a, b)| Arc::ptr_eq(a, b)));
+
+    // Connections of one listener count against the limits of the other
+    let mut listeners = listeners
+        .into_iter()
+        .map(|config| Arc::new(Mutex::new(config)));
+    let (api, other) = (listeners.next().unwrap(), listeners.next().unwrap());
+    let first = Layer4::pick_server(api.clone(), "api", client_addr(), &[]).await;
+    let second = Layer4::pick_server(api.clone(), "api", client_addr(), &[]).await;
+    assert!(first.is_some() && second.is_some());
+    assert!(
+        Layer4::pick_server(other.clone(), "api", client_addr(), &[])
+            .await
+            .is_none()
+    );
+    drop(first);
+    assert!(Layer4::pick_server(other, "api", client_addr(), &[])
+        .await
+        .is_some());
+}